@@ -0,0 +1,604 @@
+use crate::commands::{reset_session_state, RustError};
+use crate::cookie_vault;
+use crate::headless::{AppDirs, HeadlessApp};
+use chrono::Local;
+use log::{debug, error, info, warn};
+use once_cell::sync::Lazy;
+use reqwest::header::HeaderValue;
+use reqwest::Client;
+use reqwest_cookie_store::{CookieStore, CookieStoreMutex};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LockResult, MutexGuard, RwLock};
+use std::time::Duration;
+use tauri_plugin_store::StoreExt;
+use tokio::sync::{Mutex, Notify};
+
+pub(crate) const ACCOUNTS_STORE: &str = "accounts.json";
+const ACCOUNTS_KEY: &str = "accounts";
+pub(crate) const CURRENT_USER_ID_KEY: &str = "current-user-id";
+/// 以前フロントエンドがアカウント一覧を保存していたキー（store.json）
+const LEGACY_USERS_KEY: &str = "users";
+const USER_AGENT: &str = "VSM/1.0/meronmks.8914@gmail.com";
+/// Cookieが変わってから保存するまでの待ち時間。続けて変わった場合はまとめて1回保存する
+const COOKIE_SAVE_DEBOUNCE: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct Account {
+    pub id: String,
+    pub display_name: String,
+    /// ユーザーが付けた表示名。未設定ならVRChatの表示名を使う
+    pub label: Option<String>,
+    pub last_login: String,
+    /// 起動時にアカウントを選んでいなければこのアカウントでログインする
+    #[serde(default)]
+    pub is_default: bool,
+    /// 最後に取得した `/auth/user` のJSON
+    pub user_data: String,
+}
+
+/// Cookieが変わったことを保存タスクに知らせる
+static COOKIES_CHANGED: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// レスポンスでCookieが更新されたら保存待ちにするCookieストア
+pub(crate) struct TrackedCookieStore {
+    store: CookieStoreMutex,
+    dirty: AtomicBool,
+}
+
+impl TrackedCookieStore {
+    pub(crate) fn lock(&self) -> LockResult<MutexGuard<'_, CookieStore>> {
+        self.store.lock()
+    }
+}
+
+impl reqwest::cookie::CookieStore for TrackedCookieStore {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &reqwest::Url) {
+        self.store.set_cookies(cookie_headers, url);
+        self.dirty.store(true, Ordering::SeqCst);
+        COOKIES_CHANGED.notify_one();
+    }
+
+    fn cookies(&self, url: &reqwest::Url) -> Option<HeaderValue> {
+        self.store.cookies(url)
+    }
+}
+
+/// 1アカウント分のセッション。Cookieとクライアントは必ず組で差し替える
+pub(crate) struct Session {
+    pub(crate) user_id: Option<String>,
+    pub(crate) cookie_store: Arc<TrackedCookieStore>,
+    pub(crate) client: Arc<Client>,
+}
+
+impl Session {
+    pub(crate) fn new(user_id: Option<String>, cookie_store: CookieStore) -> Self {
+        let cookie_store = Arc::new(TrackedCookieStore {
+            store: CookieStoreMutex::new(cookie_store),
+            dirty: AtomicBool::new(false),
+        });
+        let client = Client::builder()
+            .cookie_provider(cookie_store.clone())
+            .user_agent(USER_AGENT)
+            .build()
+            .unwrap();
+
+        Self {
+            user_id,
+            cookie_store,
+            client: Arc::new(client),
+        }
+    }
+}
+
+static SESSION: Lazy<RwLock<Arc<Session>>> =
+    Lazy::new(|| RwLock::new(Arc::new(Session::new(None, CookieStore::default()))));
+
+/// 現在のアカウント以外で保持しているセッション（アカウントID → セッション）。
+/// 複数アカウントのフレンドを同時に取得するときに使う
+static BACKGROUND_SESSIONS: Lazy<RwLock<HashMap<String, Arc<Session>>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// セッションの差し替えを直列化する
+static SWITCH_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+pub(crate) fn current_session() -> Arc<Session> {
+    SESSION.read().unwrap().clone()
+}
+
+/// 現在のアカウントのクライアント。
+/// 切り替え前に取得したクライアントは元のアカウントのCookieを使い続けるため、実行中のリクエストが別アカウントに混ざらない
+pub(crate) fn client() -> Arc<Client> {
+    current_session().client.clone()
+}
+
+fn replace_session(session: Arc<Session>) -> Arc<Session> {
+    std::mem::replace(&mut *SESSION.write().unwrap(), session)
+}
+
+/// 現在のアカウントでなくなったセッションを、Cookieごと保持しておく
+fn park_session(session: Arc<Session>) {
+    if let Some(user_id) = session.user_id.clone() {
+        BACKGROUND_SESSIONS.write().unwrap().insert(user_id, session);
+    }
+}
+
+fn take_background_session(user_id: &str) -> Option<Arc<Session>> {
+    BACKGROUND_SESSIONS.write().unwrap().remove(user_id)
+}
+
+/// 指定したアカウントのセッションを返す。
+/// 現在のアカウントでなければ、保存済みのCookieで別のクライアントを作って保持する
+pub(crate) async fn account_session(app_handle: &tauri::AppHandle, user_id: &str) -> Result<Arc<Session>, RustError> {
+    let _guard = SWITCH_LOCK.lock().await;
+    let current = current_session();
+    if current.user_id.as_deref() == Some(user_id) {
+        return Ok(current);
+    }
+    if let Some(session) = BACKGROUND_SESSIONS.read().unwrap().get(user_id) {
+        return Ok(session.clone());
+    }
+
+    let session = Arc::new(Session::new(Some(user_id.to_string()), load_cookie_store(app_handle, user_id)?));
+    BACKGROUND_SESSIONS
+        .write()
+        .unwrap()
+        .insert(user_id.to_string(), session.clone());
+    Ok(session)
+}
+
+/// セッションのCookieをそのアカウントの保存先に書き出す
+pub(crate) fn save_session_cookies(app: &impl AppDirs, session: &Session) -> Result<(), RustError> {
+    let Some(user_id) = &session.user_id else {
+        return Ok(());
+    };
+    session.cookie_store.dirty.store(false, Ordering::SeqCst);
+    let result = {
+        let cookie_store = session.cookie_store.lock()?;
+        cookie_vault::save_jar(app, user_id, &cookie_store)
+    };
+    if result.is_err() {
+        // 次の保存で再試行する
+        session.cookie_store.dirty.store(true, Ordering::SeqCst);
+    }
+    result
+}
+
+pub(crate) fn load_cookie_store(app: &impl AppDirs, user_id: &str) -> Result<CookieStore, RustError> {
+    debug!("Loading cookies for user: {}", user_id);
+    cookie_vault::load_jar(app, user_id)
+}
+
+fn all_sessions() -> Vec<Arc<Session>> {
+    let mut sessions = vec![current_session()];
+    sessions.extend(BACKGROUND_SESSIONS.read().unwrap().values().cloned());
+    sessions
+}
+
+/// Cookieが変わるたびに（少し待ってまとめて）保存し、アプリが落ちても更新されたCookieを失わないようにする
+pub(crate) async fn run_cookie_persister(app_handle: tauri::AppHandle) {
+    loop {
+        COOKIES_CHANGED.notified().await;
+        tokio::time::sleep(COOKIE_SAVE_DEBOUNCE).await;
+
+        for session in all_sessions() {
+            if session.user_id.is_none() || !session.cookie_store.dirty.load(Ordering::SeqCst) {
+                continue;
+            }
+            if let Err(e) = save_session_cookies(&app_handle, &session) {
+                error!("Failed to save cookies: {:?}", e);
+            }
+        }
+    }
+}
+
+/// 現在のセッションのCookieをキーリングに保存する
+pub(crate) fn save_current_session(app: &impl AppDirs) -> Result<(), RustError> {
+    save_session_cookies(app, &current_session())
+}
+
+/// 保持しているすべてのセッションのCookieをキーリングに保存する
+pub(crate) fn save_all_sessions(app_handle: &tauri::AppHandle) -> Result<(), RustError> {
+    for session in all_sessions() {
+        save_session_cookies(app_handle, &session)?;
+    }
+    Ok(())
+}
+
+fn set_current_user_id(app_handle: &tauri::AppHandle, user_id: Option<&str>) -> Result<(), RustError> {
+    let store = app_handle.store("store.json")?;
+    match user_id {
+        Some(user_id) => store.set(CURRENT_USER_ID_KEY, user_id),
+        None => {
+            store.delete(CURRENT_USER_ID_KEY);
+        }
+    }
+    store.save()?;
+    Ok(())
+}
+
+fn get_current_user_id(app_handle: &tauri::AppHandle) -> Option<String> {
+    app_handle
+        .store("store.json")
+        .ok()
+        .and_then(|store| store.get(CURRENT_USER_ID_KEY))
+        .and_then(|value| value.as_str().map(|id| id.to_string()))
+}
+
+/// store.json の `users`（JSON文字列）に保存されていた一覧を取り込む
+fn migrate_legacy_accounts(app_handle: &tauri::AppHandle) -> Result<Vec<Account>, RustError> {
+    let store = app_handle.store("store.json")?;
+    let Some(users) = store.get(LEGACY_USERS_KEY) else {
+        return Ok(Vec::new());
+    };
+    let accounts = parse_legacy_accounts(users)?;
+    info!("Migrated {} account(s) from store.json", accounts.len());
+    save_accounts(app_handle, &accounts)?;
+    store.delete(LEGACY_USERS_KEY);
+    store.save()?;
+    Ok(accounts)
+}
+
+/// store.json の `users` に保存されていた一覧を読む。JSON文字列のこともある
+fn parse_legacy_accounts(users: serde_json::Value) -> Result<Vec<Account>, RustError> {
+    let users: Vec<serde_json::Value> = match users.as_str() {
+        Some(users) => serde_json::from_str(users)?,
+        None => serde_json::from_value(users)?,
+    };
+
+    Ok(users
+        .iter()
+        .filter_map(|user| {
+            let user_data = match &user["userData"] {
+                serde_json::Value::String(data) => data.clone(),
+                data => data.to_string(),
+            };
+            Some(Account {
+                id: user["id"].as_str()?.to_string(),
+                display_name: user["displayName"].as_str().unwrap_or_default().to_string(),
+                label: None,
+                last_login: user["lastLogin"].as_str().unwrap_or_default().to_string(),
+                is_default: false,
+                user_data,
+            })
+        })
+        .collect())
+}
+
+/// Tauriを起動せずに保存済みのアカウントを読む（CLI用）。古い形式の一覧は書き換えずにそのまま読む
+pub(crate) fn read_accounts(app: &HeadlessApp) -> Result<Vec<Account>, RustError> {
+    if let Some(value) = app.store_value(ACCOUNTS_STORE, ACCOUNTS_KEY)? {
+        return Ok(serde_json::from_value(value)?);
+    }
+    match app.store_value("store.json", LEGACY_USERS_KEY)? {
+        Some(users) => parse_legacy_accounts(users),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Tauriを起動せずに、アプリで選択中のアカウント（なければデフォルトのアカウント）を求める（CLI用）
+pub(crate) fn read_current_user_id(app: &HeadlessApp) -> Result<Option<String>, RustError> {
+    let current = app.store_value("store.json", CURRENT_USER_ID_KEY)?;
+    if let Some(user_id) = current.as_ref().and_then(|value| value.as_str()) {
+        return Ok(Some(user_id.to_string()));
+    }
+    Ok(read_accounts(app)?.into_iter().find(|a| a.is_default).map(|a| a.id))
+}
+
+pub(crate) fn load_accounts(app_handle: &tauri::AppHandle) -> Result<Vec<Account>, RustError> {
+    let store = app_handle.store(ACCOUNTS_STORE)?;
+    match store.get(ACCOUNTS_KEY) {
+        Some(value) => Ok(serde_json::from_value(value)?),
+        None => migrate_legacy_accounts(app_handle),
+    }
+}
+
+fn save_accounts(app_handle: &tauri::AppHandle, accounts: &[Account]) -> Result<(), RustError> {
+    let store = app_handle.store(ACCOUNTS_STORE)?;
+    store.set(ACCOUNTS_KEY, serde_json::to_value(accounts)?);
+    store.save()?;
+    Ok(())
+}
+
+/// 起動時に、選択中のアカウント（なければデフォルトのアカウント）のセッションを復元する
+pub(crate) fn restore_session(app_handle: &tauri::AppHandle) {
+    let user_id = get_current_user_id(app_handle).or_else(|| {
+        load_accounts(app_handle)
+            .ok()?
+            .into_iter()
+            .find(|a| a.is_default)
+            .map(|a| a.id)
+    });
+    let Some(user_id) = user_id else {
+        warn!("Current user ID is not set or not a string");
+        return;
+    };
+
+    match load_cookie_store(app_handle, &user_id) {
+        Ok(cookie_store) => {
+            replace_session(Arc::new(Session::new(Some(user_id.clone()), cookie_store)));
+            if let Err(e) = set_current_user_id(app_handle, Some(&user_id)) {
+                error!("Failed to set current user: {:?}", e);
+            }
+        }
+        Err(e) => error!("Failed to load cookies: {:?}", e),
+    }
+}
+
+/// 選択中のアカウントを変えずに、指定したアカウントの保存済みCookieでセッションを作る（CLIの `--account` 用）
+pub(crate) fn use_account(app: &impl AppDirs, user_id: &str) -> Result<(), RustError> {
+    let cookie_store = load_cookie_store(app, user_id)?;
+    replace_session(Arc::new(Session::new(Some(user_id.to_string()), cookie_store)));
+    Ok(())
+}
+
+/// ログイン直後の匿名セッションを指定したアカウントのものとして登録する
+pub(crate) async fn adopt_session(app_handle: &tauri::AppHandle, user_id: &str) -> Result<(), RustError> {
+    let _guard = SWITCH_LOCK.lock().await;
+    let current = current_session();
+    if current.user_id.as_deref() != Some(user_id) {
+        let session = Session {
+            user_id: Some(user_id.to_string()),
+            cookie_store: current.cookie_store.clone(),
+            client: current.client.clone(),
+        };
+        let previous = replace_session(Arc::new(session));
+        // ログインし直したアカウントの古いセッションは使わない
+        take_background_session(user_id);
+        if previous.user_id.is_some() {
+            // 別アカウントのセッションのままログインした場合でも、元のアカウントの保存済みCookieは上書きしない
+            warn!("Session of {:?} was taken over by {:?}", previous.user_id, user_id);
+        }
+    }
+    save_current_session(app_handle)?;
+    set_current_user_id(app_handle, Some(user_id))
+}
+
+/// 2要素認証を済ませた端末であることを示すCookie。残っていれば再ログインでコードを求められない
+const TWO_FACTOR_COOKIE: &str = "twoFactorAuth";
+
+fn without_two_factor_cookie(store: &CookieStore) -> CookieStore {
+    let kept = store
+        .iter_any()
+        .filter(|cookie| cookie.name() != TWO_FACTOR_COOKIE)
+        .cloned()
+        .map(Ok::<_, std::convert::Infallible>);
+    CookieStore::from_cookies(kept, true).unwrap_or_default()
+}
+
+/// 現在のセッションから2要素認証済みの印を消し、次の再ログインで再びコードを求められるようにする
+pub(crate) fn forget_trusted_device(app_handle: &tauri::AppHandle) -> Result<(), RustError> {
+    let session = current_session();
+    {
+        let mut store = session.cookie_store.lock()?;
+        *store = without_two_factor_cookie(&store);
+    }
+    save_session_cookies(app_handle, &session)
+}
+
+/// 新しいアカウントでログインするために、空のセッションに切り替える
+pub(crate) async fn start_anonymous_session(app_handle: &tauri::AppHandle) -> Result<(), RustError> {
+    let _guard = SWITCH_LOCK.lock().await;
+    let previous = replace_session(Arc::new(Session::new(None, CookieStore::default())));
+    save_session_cookies(app_handle, &previous)?;
+    park_session(previous);
+    reset_session_state().await;
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn get_accounts(app_handle: tauri::AppHandle) -> Result<Vec<Account>, RustError> {
+    debug!("Call get_accounts");
+
+    load_accounts(&app_handle)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn get_current_account(app_handle: tauri::AppHandle) -> Result<Option<Account>, RustError> {
+    debug!("Call get_current_account");
+
+    let Some(user_id) = current_session().user_id.clone() else {
+        return Ok(None);
+    };
+    Ok(load_accounts(&app_handle)?.into_iter().find(|a| a.id == user_id))
+}
+
+/// `/auth/user` のJSONからアカウントを登録・更新し、現在のセッションをそのアカウントのものにする
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn save_account(app_handle: tauri::AppHandle, user_data: String) -> Result<Account, RustError> {
+    debug!("Call save_account");
+
+    let json: serde_json::Value = serde_json::from_str(&user_data)?;
+    let user_id = json["id"].as_str().ok_or("User ID not found in response")?;
+    let display_name = json["displayName"].as_str().unwrap_or_default();
+
+    let mut accounts = load_accounts(&app_handle)?;
+    let account = match accounts.iter_mut().find(|a| a.id == user_id) {
+        Some(account) => {
+            account.display_name = display_name.to_string();
+            account.last_login = Local::now().to_rfc3339();
+            account.user_data = user_data.clone();
+            account.clone()
+        }
+        None => {
+            let account = Account {
+                id: user_id.to_string(),
+                display_name: display_name.to_string(),
+                label: None,
+                last_login: Local::now().to_rfc3339(),
+                // 最初に登録したアカウントをデフォルトにする
+                is_default: accounts.is_empty(),
+                user_data: user_data.clone(),
+            };
+            accounts.push(account.clone());
+            account
+        }
+    };
+    save_accounts(&app_handle, &accounts)?;
+    adopt_session(&app_handle, user_id).await?;
+
+    Ok(account)
+}
+
+/// アカウントを一覧とキーリングから削除する。現在のアカウントなら空のセッションに切り替える
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn remove_account(app_handle: tauri::AppHandle, user_id: &str) -> Result<(), RustError> {
+    debug!("Call remove_account {:?}", user_id);
+
+    let _guard = SWITCH_LOCK.lock().await;
+    if current_session().user_id.as_deref() == Some(user_id) {
+        replace_session(Arc::new(Session::new(None, CookieStore::default())));
+        reset_session_state().await;
+        set_current_user_id(&app_handle, None)?;
+    }
+    take_background_session(user_id);
+
+    let mut accounts = load_accounts(&app_handle)?;
+    accounts.retain(|a| a.id != user_id);
+    save_accounts(&app_handle, &accounts)?;
+
+    crate::reauth::delete_credentials(&app_handle, user_id)?;
+    crate::totp::delete_secret(&app_handle, user_id)?;
+    cookie_vault::delete_jar(&app_handle, user_id)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn rename_account(
+    app_handle: tauri::AppHandle,
+    user_id: &str,
+    label: Option<String>,
+) -> Result<(), RustError> {
+    debug!("Call rename_account {:?} {:?}", user_id, label);
+
+    let mut accounts = load_accounts(&app_handle)?;
+    let account = accounts
+        .iter_mut()
+        .find(|a| a.id == user_id)
+        .ok_or("errors.accountNotFound")?;
+    account.label = label.map(|l| l.trim().to_string()).filter(|l| !l.is_empty());
+    save_accounts(&app_handle, &accounts)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn set_default_account(app_handle: tauri::AppHandle, user_id: Option<String>) -> Result<(), RustError> {
+    debug!("Call set_default_account {:?}", user_id);
+
+    let mut accounts = load_accounts(&app_handle)?;
+    if user_id.as_ref().is_some_and(|id| !accounts.iter().any(|a| &a.id == id)) {
+        return Err("errors.accountNotFound".into());
+    }
+    for account in accounts.iter_mut() {
+        account.is_default = user_id.as_ref() == Some(&account.id);
+    }
+    save_accounts(&app_handle, &accounts)
+}
+
+/// 保存済みのCookieを読み込んでからセッションを丸ごと差し替える。読み込みに失敗した場合は切り替えない
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn switch_user(app_handle: tauri::AppHandle, user_id: &str) -> Result<Account, RustError> {
+    debug!("Switch user to {:?}", user_id);
+
+    let _guard = SWITCH_LOCK.lock().await;
+    let account = load_accounts(&app_handle)?
+        .into_iter()
+        .find(|a| a.id == user_id)
+        .ok_or("errors.accountNotFound")?;
+    // 同時取得のために保持しているセッションがあれば、Cookieを二重に持たないようそれを使う
+    let session = match take_background_session(user_id) {
+        Some(session) => session,
+        None => Arc::new(Session::new(
+            Some(user_id.to_string()),
+            load_cookie_store(&app_handle, user_id)?,
+        )),
+    };
+
+    let previous = replace_session(session);
+    reset_session_state().await;
+    if let Err(e) = save_session_cookies(&app_handle, &previous) {
+        error!("Failed to save cookies of previous account: {:?}", e);
+    }
+    park_session(previous);
+    set_current_user_id(&app_handle, Some(user_id))?;
+
+    info!("Switched account to {}", user_id);
+    Ok(account)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn forgetting_device_keeps_the_session_cookie() {
+        let url = reqwest::Url::parse("https://api.vrchat.cloud/api/1/auth/twofactorauth/totp/verify").unwrap();
+        let mut store = CookieStore::default();
+        store.parse("auth=authcookie_0123; Path=/; Max-Age=3600", &url).unwrap();
+        store.parse("twoFactorAuth=eyJ.x.y; Path=/; Max-Age=3600", &url).unwrap();
+
+        let store = without_two_factor_cookie(&store);
+        let names: Vec<&str> = store.iter_any().map(|cookie| cookie.name()).collect();
+        assert_eq!(names, vec!["auth"]);
+    }
+
+    fn headless_app(name: &str, stores: &[(&str, serde_json::Value)]) -> (HeadlessApp, TempDir) {
+        let dir = TempDir::new(&format!("accounts-{name}"));
+        for (file, value) in stores {
+            std::fs::write(dir.join(file), serde_json::to_vec_pretty(value).unwrap()).unwrap();
+        }
+        (HeadlessApp::with_dirs("com.example.test", dir.to_path_buf(), dir.to_path_buf()), dir)
+    }
+
+    #[test]
+    fn reads_accounts_without_tauri() {
+        let account = |id: &str, is_default: bool| {
+            serde_json::json!({
+                "id": id, "displayName": id, "label": null, "lastLogin": "", "isDefault": is_default, "userData": "{}"
+            })
+        };
+        let (app, _dir) = headless_app(
+            "current",
+            &[
+                ("accounts.json", serde_json::json!({ "accounts": [account("usr_a", false), account("usr_b", true)] })),
+                ("store.json", serde_json::json!({ "current-user-id": "usr_a" })),
+            ],
+        );
+        let ids: Vec<String> = read_accounts(&app).unwrap().into_iter().map(|a| a.id).collect();
+        assert_eq!(ids, ["usr_a", "usr_b"]);
+        assert_eq!(read_current_user_id(&app).unwrap().as_deref(), Some("usr_a"));
+
+        // 選択中のアカウントが無ければデフォルトのアカウント
+        let (app, _dir) = headless_app(
+            "default",
+            &[("accounts.json", serde_json::json!({ "accounts": [account("usr_a", false), account("usr_b", true)] }))],
+        );
+        assert_eq!(read_current_user_id(&app).unwrap().as_deref(), Some("usr_b"));
+
+        let (app, _dir) = headless_app("empty", &[]);
+        assert!(read_accounts(&app).unwrap().is_empty());
+        assert_eq!(read_current_user_id(&app).unwrap(), None);
+    }
+
+    #[test]
+    fn reads_legacy_accounts_without_migrating_them() {
+        let users = serde_json::json!([{ "id": "usr_old", "displayName": "Old", "lastLogin": "x", "userData": "{}" }]);
+        let (app, dir) = headless_app("legacy", &[("store.json", serde_json::json!({ "users": users.to_string() }))]);
+
+        let accounts = read_accounts(&app).unwrap();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].id, "usr_old");
+        assert_eq!(accounts[0].display_name, "Old");
+        // CLIは古い一覧を書き換えない
+        assert!(!dir.join("accounts.json").exists());
+    }
+}
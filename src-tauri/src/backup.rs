@@ -0,0 +1,358 @@
+use crate::accounts::{ACCOUNTS_STORE, CURRENT_USER_ID_KEY};
+use crate::commands::{cached_worlds, insert_world, RustError};
+use crate::friend_history::FRIEND_HISTORY_STORE;
+use crate::history::{self, HISTORY_STORE};
+use crate::notes::NOTES_STORE;
+use crate::osc::{self, OSC_STORE};
+use crate::redaction;
+use crate::scheduler::SCHEDULES_STORE;
+use crate::settings::{self, SETTINGS_CHANGED_EVENT};
+use crate::structs::World;
+use crate::webhooks::{self, WEBHOOKS_STORE};
+use chrono::Local;
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use tauri::Emitter;
+use tauri_plugin_store::StoreExt;
+
+/// バックアップの形式のバージョン。形式を変えたら上げて `migrate` に変換を追加する
+const BACKUP_VERSION: u32 = 1;
+
+/// バックアップするストア。復元時はバックアップに含まれるストアだけを置き換える
+const BACKUP_STORES: [&str; 8] = [
+    "store.json",
+    ACCOUNTS_STORE,
+    NOTES_STORE,
+    HISTORY_STORE,
+    FRIEND_HISTORY_STORE,
+    SCHEDULES_STORE,
+    OSC_STORE,
+    WEBHOOKS_STORE,
+];
+
+/// store.json のうち、別のPCに持っていっても意味がないキー
+const EXCLUDED_KEYS: [&str; 2] = [
+    // 画面を閉じるまでの一時データ
+    "instances-data",
+    // Cookieはバックアップに含めないため、復元しても選択中のアカウントでは動かない
+    CURRENT_USER_ID_KEY,
+];
+
+/// バックアップ機能ができる前の store.json にあったキー。どれも無いJSONは store.json とみなさない
+const LEGACY_STORE_KEYS: [&str; 9] = [
+    "settings",
+    "settings-version",
+    "data-theme",
+    "lang",
+    "fetch-friends-count",
+    "auto-check-updates",
+    "user-data",
+    "users",
+    "instances-data",
+];
+
+type StoreEntries = serde_json::Map<String, serde_json::Value>;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BackupArchive {
+    version: u32,
+    created_at: String,
+    app_version: String,
+    stores: BTreeMap<String, StoreEntries>,
+    #[serde(default)]
+    world_cache: Vec<World>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupSummary {
+    pub path: String,
+    /// 読み込んだバックアップの元の形式のバージョン（作成時は現在のバージョン）
+    pub version: u32,
+    pub created_at: String,
+    pub stores: Vec<String>,
+    pub worlds: u32,
+}
+
+/// 古い形式のバックアップを現在の形式に変換する
+fn migrate(mut archive: serde_json::Value) -> Result<(u32, BackupArchive), RustError> {
+    let Some(object) = archive.as_object_mut() else {
+        return Err("errors.backupInvalid".into());
+    };
+    // バージョン0: バックアップ機能ができる前に手でコピーしていた store.json そのもの。
+    // 中の `users` は store.json を読み込んだときにアカウント一覧へ取り込まれる
+    let original = match object.get("version").and_then(|version| version.as_u64()) {
+        Some(version) => version as u32,
+        None if !object.contains_key("stores") && LEGACY_STORE_KEYS.iter().any(|key| object.contains_key(*key)) => {
+            let store = std::mem::take(object);
+            archive = serde_json::json!({
+                "version": 1,
+                "createdAt": "",
+                "appVersion": "",
+                "stores": { "store.json": store },
+            });
+            0
+        }
+        None => return Err("errors.backupInvalid".into()),
+    };
+    if original > BACKUP_VERSION {
+        return Err("errors.backupTooNew".into());
+    }
+    if original < BACKUP_VERSION {
+        info!("Migrating backup from version {} to {}", original, BACKUP_VERSION);
+    }
+
+    let archive: BackupArchive = serde_json::from_value(archive).map_err(|e| {
+        warn!("Failed to parse backup: {}", e);
+        "errors.backupInvalid"
+    })?;
+    Ok((original, archive))
+}
+
+fn default_path() -> Result<PathBuf, RustError> {
+    let dir = dirs::download_dir()
+        .or_else(dirs::home_dir)
+        .ok_or("errors.exportPathNotFound")?;
+    Ok(dir.join(format!("vsm-backup-{}.json", Local::now().format("%Y%m%d-%H%M%S"))))
+}
+
+fn snapshot_store(app_handle: &tauri::AppHandle, name: &str) -> Result<StoreEntries, RustError> {
+    Ok(app_handle.store(name)?.entries().into_iter().collect())
+}
+
+fn read_store(app_handle: &tauri::AppHandle, name: &str) -> Result<StoreEntries, RustError> {
+    let entries = snapshot_store(app_handle, name)?;
+    Ok(match name {
+        "store.json" => entries
+            .into_iter()
+            .filter(|(key, _)| !EXCLUDED_KEYS.contains(&key.as_str()))
+            .collect(),
+        WEBHOOKS_STORE => webhooks::backup_entries(entries),
+        _ => entries,
+    })
+}
+
+/// 復元する内容に、バックアップに含めなかった今の値を足す
+fn prepare_store(
+    app_handle: &tauri::AppHandle,
+    name: &str,
+    mut entries: StoreEntries,
+) -> Result<StoreEntries, RustError> {
+    match name {
+        "store.json" => {
+            let store = app_handle.store(name)?;
+            for key in EXCLUDED_KEYS {
+                match store.get(key) {
+                    Some(value) => entries.insert(key.to_string(), value),
+                    None => entries.remove(key),
+                };
+            }
+            Ok(entries)
+        }
+        WEBHOOKS_STORE => webhooks::restore_entries(app_handle, entries),
+        _ => Ok(entries),
+    }
+}
+
+fn replace_store(app_handle: &tauri::AppHandle, name: &str, entries: StoreEntries) -> Result<(), RustError> {
+    let store = app_handle.store(name)?;
+    store.clear();
+    for (key, value) in entries {
+        store.set(key, value);
+    }
+    store.save()?;
+    Ok(())
+}
+
+/// 用意した内容でストアを置き換える。途中で失敗したら、置き換えたストアを元の内容に戻す
+fn write_stores(app_handle: &tauri::AppHandle, staged: Vec<(&str, StoreEntries)>) -> Result<(), RustError> {
+    let mut written: Vec<(&str, StoreEntries)> = Vec::new();
+    for (name, entries) in staged {
+        let previous = snapshot_store(app_handle, name)?;
+        if let Err(e) = replace_store(app_handle, name, entries) {
+            warn!("Failed to restore {}, rolling back: {:?}", name, e);
+            // 途中まで書いたストアも元に戻す
+            written.push((name, previous));
+            for (name, previous) in written.into_iter().rev() {
+                if let Err(e) = replace_store(app_handle, name, previous) {
+                    error!("Failed to roll back {}: {:?}", name, e);
+                }
+            }
+            return Err(e);
+        }
+        written.push((name, previous));
+    }
+    Ok(())
+}
+
+/// 設定・アカウント一覧・メモとタグ・履歴・ワールドのキャッシュを1つのファイルにまとめる。
+/// Cookieや認証情報はキーリングにあるため含めない。WebhookのURLも含めない
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn create_backup(app_handle: tauri::AppHandle, path: Option<String>) -> Result<BackupSummary, RustError> {
+    debug!("Call create_backup {:?}", path);
+
+    let mut stores = BTreeMap::new();
+    {
+        let _guard = history::lock_history().await;
+        for name in BACKUP_STORES {
+            stores.insert(name.to_string(), read_store(&app_handle, name)?);
+        }
+    }
+    let archive = BackupArchive {
+        version: BACKUP_VERSION,
+        created_at: Local::now().to_rfc3339(),
+        app_version: app_handle.package_info().version.to_string(),
+        stores,
+        world_cache: cached_worlds().await,
+    };
+
+    let path = match path {
+        Some(path) if !path.trim().is_empty() => PathBuf::from(path),
+        _ => default_path()?,
+    };
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, serde_json::to_vec(&archive)?)?;
+    info!("Created backup {}", path.display());
+
+    Ok(BackupSummary {
+        path: path.to_string_lossy().to_string(),
+        version: archive.version,
+        created_at: archive.created_at,
+        stores: archive.stores.into_keys().collect(),
+        worlds: archive.world_cache.len() as u32,
+    })
+}
+
+/// バックアップを読み込み、含まれるストアで今のデータを置き換える。含まれないストアはそのまま残す。
+/// 古い形式は変換してから読み込む
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn restore_backup(app_handle: tauri::AppHandle, path: String) -> Result<BackupSummary, RustError> {
+    debug!("Call restore_backup {:?}", path);
+
+    let bytes = std::fs::read(&path).map_err(|_| "errors.backupNotFound")?;
+    let value: serde_json::Value = serde_json::from_slice(&bytes).map_err(|_| "errors.backupInvalid")?;
+    let (original_version, mut archive) = migrate(value)?;
+
+    let unknown: Vec<&String> = archive
+        .stores
+        .keys()
+        .filter(|name| !BACKUP_STORES.contains(&name.as_str()))
+        .collect();
+    if !unknown.is_empty() {
+        warn!("Ignoring unknown stores in backup: {:?}", unknown);
+    }
+
+    let restored: Vec<String>;
+    {
+        let mut history = history::lock_history().await;
+        // すべてのストアの内容を用意してから書き込む
+        let mut staged = Vec::new();
+        for name in BACKUP_STORES {
+            if let Some(entries) = archive.stores.remove(name) {
+                staged.push((name, prepare_store(&app_handle, name, entries)?));
+            }
+        }
+        restored = staged.iter().map(|(name, _)| name.to_string()).collect();
+        write_stores(&app_handle, staged)?;
+        // 読み込み済みの履歴を捨て、復元した内容を読み直させる
+        *history = None;
+    }
+    let worlds = archive.world_cache.len() as u32;
+    for world in archive.world_cache {
+        insert_world(world.id.clone(), world).await;
+    }
+    // 古いバックアップの設定は復元後に現在の形式へ変換する
+    settings::migrate(&app_handle)?;
+    redaction::load_policy(&app_handle);
+    app_handle.emit(SETTINGS_CHANGED_EVENT, settings::load_settings(&app_handle)?)?;
+    osc::reload_config();
+    webhooks::reload_config();
+    info!("Restored backup {} (version {})", path, original_version);
+
+    Ok(BackupSummary {
+        path,
+        version: original_version,
+        created_at: archive.created_at,
+        stores: restored,
+        worlds,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn error_of(value: serde_json::Value) -> String {
+        match migrate(value) {
+            Ok(_) => panic!("migrate should fail"),
+            Err(e) => e.message().to_string(),
+        }
+    }
+
+    #[test]
+    fn migrates_plain_store_json_from_version_0() {
+        let legacy = json!({
+            "lang": "en",
+            "fetch-friends-count": 30,
+            "users": [{"id": "usr_a"}],
+        });
+        let (original, archive) = migrate(legacy.clone()).unwrap();
+
+        assert_eq!(original, 0);
+        assert_eq!(archive.version, BACKUP_VERSION);
+        assert_eq!(archive.stores.len(), 1);
+        assert_eq!(serde_json::Value::Object(archive.stores["store.json"].clone()), legacy);
+        assert!(archive.world_cache.is_empty());
+    }
+
+    #[test]
+    fn reads_current_version_as_is() {
+        let backup = json!({
+            "version": BACKUP_VERSION,
+            "createdAt": "2024-01-15T21:00:00+09:00",
+            "appVersion": "1.0.0",
+            "stores": {"notes.json": {"users": {}}},
+        });
+        let (original, archive) = migrate(backup).unwrap();
+
+        assert_eq!(original, BACKUP_VERSION);
+        assert_eq!(archive.created_at, "2024-01-15T21:00:00+09:00");
+        assert_eq!(archive.stores.keys().collect::<Vec<_>>(), vec!["notes.json"]);
+    }
+
+    #[test]
+    fn rejects_backup_from_newer_version() {
+        let backup = json!({
+            "version": BACKUP_VERSION + 1,
+            "createdAt": "",
+            "appVersion": "",
+            "stores": {},
+        });
+        assert_eq!(error_of(backup), "errors.backupTooNew");
+    }
+
+    #[test]
+    fn rejects_json_that_is_not_a_backup() {
+        let not_backups = [
+            json!([]),
+            json!("store.json"),
+            json!({}),
+            // エクスポートしたJSONなど、store.json にないキーだけのもの
+            json!({"worldName": "Home", "friendId": "usr_a"}),
+            json!({"stores": {}}),
+            json!({"version": BACKUP_VERSION, "stores": "broken"}),
+        ];
+        for value in not_backups {
+            assert_eq!(error_of(value.clone()), "errors.backupInvalid", "{value}");
+        }
+    }
+}
@@ -0,0 +1,413 @@
+use crate::accounts::{self, current_session, read_accounts, read_current_user_id};
+use crate::commands::{
+    get_current_user_json, get_world_by_id, invite_myself_to_instance, update_current_user_inner, RustError,
+};
+use crate::export::{collect_instances, export_friend_instances, ExportColumn, ExportFormat, ExportOptions};
+use crate::friends::fetch_all_friends;
+use crate::headless::HeadlessApp;
+use crate::local_api::{friend_summary, status_summary};
+use crate::{reauth, redaction};
+use crate::structs::{CurrentUserUpdate, UserStatus};
+use log::{error, LevelFilter};
+use std::collections::HashMap;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: vsm-cli [--account <id|name>] [--json] <command>
+
+Commands:
+  accounts                         List saved accounts
+  friends list [--offline]         List friends
+  instances [--offline]            List instances where friends are
+  world <id>                       Show a world
+  invite-me <location>             Invite yourself to wrld_xxx:instance
+  status                           Show your status
+  status set <status> [message]    Set status (join-me, active, ask-me, busy)
+  export [--format csv|json|markdown] [--output <path>]
+         [--columns <a,b,...>] [--offline] [--group]
+                                   Export friends and instances to a file
+
+The session of the account selected in the app (or the default account) is used.";
+
+/// 値を取るオプション
+const VALUE_OPTIONS: [&str; 4] = ["--account", "--format", "--output", "--columns"];
+const FLAGS: [&str; 4] = ["--json", "--offline", "--group", "--help"];
+
+/// `export` で `--columns` を指定しなかったときの列
+const DEFAULT_EXPORT_COLUMNS: [ExportColumn; 6] = [
+    ExportColumn::WorldName,
+    ExportColumn::InstanceType,
+    ExportColumn::Region,
+    ExportColumn::FriendName,
+    ExportColumn::Status,
+    ExportColumn::Platform,
+];
+
+#[derive(Default)]
+struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+    flags: Vec<String>,
+}
+
+impl Args {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Args::default();
+        let mut args = args.peekable();
+        while let Some(arg) = args.next() {
+            if VALUE_OPTIONS.contains(&arg.as_str()) {
+                let value = args.next().ok_or_else(|| format!("{arg} needs a value"))?;
+                parsed.options.insert(arg, value);
+            } else if FLAGS.contains(&arg.as_str()) {
+                parsed.flags.push(arg);
+            } else if arg.starts_with("--") {
+                return Err(format!("Unknown option: {arg}"));
+            } else {
+                parsed.positional.push(arg);
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|flag| flag == name)
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+}
+
+/// 失敗の種類。使い方の誤りは終了コード2にする
+enum CliError {
+    Usage(String),
+    Failed(String),
+}
+
+impl From<RustError> for CliError {
+    fn from(value: RustError) -> Self {
+        CliError::Failed(value.message().to_string())
+    }
+}
+
+fn print_json(value: &serde_json::Value) -> Result<(), CliError> {
+    let text = serde_json::to_string_pretty(value).map_err(|e| CliError::Failed(e.to_string()))?;
+    println!("{text}");
+    Ok(())
+}
+
+fn text(value: &serde_json::Value) -> &str {
+    value.as_str().unwrap_or_default()
+}
+
+/// `join-me` のようにハイフンでも書けるようにする
+fn parse_status(value: &str) -> Result<UserStatus, CliError> {
+    serde_json::from_value(serde_json::Value::String(value.replace('-', " ")))
+        .map_err(|_| CliError::Usage(format!("Unknown status: {value}")))
+}
+
+fn parse_export_options(args: &Args) -> Result<ExportOptions, CliError> {
+    let format = match args.option("--format").unwrap_or("csv") {
+        "csv" => ExportFormat::Csv,
+        "json" => ExportFormat::Json,
+        "markdown" | "md" => ExportFormat::Markdown,
+        format => return Err(CliError::Usage(format!("Unknown format: {format}"))),
+    };
+    let columns = match args.option("--columns") {
+        Some(columns) => columns
+            .split(',')
+            .map(|column| {
+                serde_json::from_value(serde_json::Value::String(column.trim().to_string()))
+                    .map_err(|_| CliError::Usage(format!("Unknown column: {column}")))
+            })
+            .collect::<Result<Vec<ExportColumn>, CliError>>()?,
+        None => DEFAULT_EXPORT_COLUMNS.to_vec(),
+    };
+    Ok(ExportOptions {
+        format,
+        columns,
+        include_offline: args.flag("--offline"),
+        group_by_instance: args.flag("--group"),
+        path: args.option("--output").map(str::to_string),
+    })
+}
+
+/// IDかVRChatの表示名・アプリで付けた名前で保存済みのアカウントを選ぶ
+fn find_account(app: &HeadlessApp, name: &str) -> Result<String, CliError> {
+    let account = read_accounts(app)?
+        .into_iter()
+        .find(|a| a.id == name || a.display_name == name || a.label.as_deref() == Some(name))
+        .ok_or_else(|| CliError::Failed(format!("Account not found: {name}")))?;
+    Ok(account.id)
+}
+
+/// `--account` のアカウント、なければアプリで選択中のアカウントの保存済みCookieでセッションを作る
+fn select_account(app: &HeadlessApp, name: Option<&str>) -> Result<(), CliError> {
+    let user_id = match name {
+        Some(name) => find_account(app, name)?,
+        None => read_current_user_id(app)?
+            .ok_or_else(|| CliError::Failed("No saved session. Log in with the app first.".to_string()))?,
+    };
+    accounts::use_account(app, &user_id)?;
+    Ok(())
+}
+
+async fn execute(app: &HeadlessApp, args: &Args) -> Result<(), CliError> {
+    let json = args.flag("--json");
+    let command: Vec<&str> = args.positional.iter().map(String::as_str).collect();
+
+    if command == ["accounts"] {
+        let accounts = read_accounts(app)?;
+        if json {
+            return print_json(&serde_json::to_value(&accounts).map_err(|e| CliError::Failed(e.to_string()))?);
+        }
+        for account in accounts {
+            let name = account.label.as_deref().unwrap_or(&account.display_name);
+            let default = if account.is_default { " (default)" } else { "" };
+            println!("{}\t{}{}", account.id, name, default);
+        }
+        return Ok(());
+    }
+
+    select_account(app, args.option("--account"))?;
+
+    match command.as_slice() {
+        ["friends"] | ["friends", "list"] => {
+            let friends = fetch_all_friends(&current_session(), args.flag("--offline")).await?;
+            let friends: Vec<serde_json::Value> = friends.iter().map(friend_summary).collect();
+            if json {
+                return print_json(&friends.into());
+            }
+            for friend in &friends {
+                println!(
+                    "{}\t{}\t{}",
+                    text(&friend["displayName"]),
+                    text(&friend["status"]),
+                    text(&friend["location"])
+                );
+            }
+        }
+        ["instances"] => {
+            let instances = collect_instances(args.flag("--offline")).await?;
+            let instances: Vec<serde_json::Value> = instances.iter().map(|instance| instance.to_json()).collect();
+            if json {
+                return print_json(&instances.into());
+            }
+            for instance in &instances {
+                println!(
+                    "{} [{} {}] {}",
+                    text(&instance["worldName"]),
+                    text(&instance["type"]),
+                    text(&instance["region"]),
+                    text(&instance["location"])
+                );
+                for friend in instance["friends"].as_array().into_iter().flatten() {
+                    println!("  {}\t{}", text(&friend["displayName"]), text(&friend["status"]));
+                }
+            }
+        }
+        ["world", world_id] => {
+            let world: serde_json::Value =
+                serde_json::from_str(&get_world_by_id(world_id).await?).map_err(|e| CliError::Failed(e.to_string()))?;
+            if json {
+                return print_json(&world);
+            }
+            println!("{}\t{}", text(&world["id"]), text(&world["name"]));
+        }
+        ["invite-me", location] => {
+            let (world_id, instance_id) = location
+                .split_once(':')
+                .ok_or_else(|| CliError::Usage(format!("Invalid location: {location}")))?;
+            invite_myself_to_instance(world_id, instance_id).await?;
+            println!("Invited to {location}");
+        }
+        ["status"] | ["status", "get"] => {
+            let status = status_summary(&get_current_user_json().await?);
+            if json {
+                return print_json(&status);
+            }
+            println!("{}\t{}", text(&status["status"]), text(&status["statusDescription"]));
+        }
+        ["status", "set", status, message @ ..] => {
+            let update = CurrentUserUpdate {
+                status: Some(parse_status(status)?),
+                status_description: (!message.is_empty()).then(|| message.join(" ")),
+                ..Default::default()
+            };
+            let user: serde_json::Value = serde_json::from_str(&update_current_user_inner(&update).await?)
+                .map_err(|e| CliError::Failed(e.to_string()))?;
+            let status = status_summary(&user);
+            if json {
+                return print_json(&status);
+            }
+            println!("{}\t{}", text(&status["status"]), text(&status["statusDescription"]));
+        }
+        ["export"] => {
+            let summary = export_friend_instances(parse_export_options(args)?).await?;
+            if json {
+                return print_json(&serde_json::to_value(&summary).map_err(|e| CliError::Failed(e.to_string()))?);
+            }
+            println!("Exported {} rows to {}", summary.rows, summary.path);
+        }
+        _ => return Err(CliError::Usage("Unknown command".to_string())),
+    }
+    Ok(())
+}
+
+/// ログはGUIと同じフォルダの `cli.log` にだけ書く。標準出力は結果の出力に使う
+fn init_logging(app: &HeadlessApp) -> Result<(), RustError> {
+    let dir = app.log_dir()?;
+    std::fs::create_dir_all(&dir)?;
+    tauri_plugin_log::fern::Dispatch::new()
+        .format(redaction::format_record)
+        .level(LevelFilter::Info)
+        .chain(tauri_plugin_log::fern::log_file(dir.join("cli.log"))?)
+        .apply()?;
+    Ok(())
+}
+
+/// `vsm-cli` のエントリーポイント。GUIと同じストアとキーリングのセッションを使う
+pub fn run() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{message}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    if args.flag("--help") || args.positional.is_empty() {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+
+    // Tauriのイベントループは作らない（ディスプレイの無い環境でも動かすため）。
+    // ストアのファイルとキーリングはGUIと同じ場所を直接読む
+    let app = match HeadlessApp::new(&crate::context().config().identifier) {
+        Ok(app) => app,
+        Err(e) => {
+            eprintln!("Failed to initialize: {}", e.message());
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(e) = init_logging(&app) {
+        eprintln!("Failed to open the log file: {}", e.message());
+    }
+    redaction::load_policy_headless(&app);
+    reauth::init_headless(app.clone());
+
+    let result = tauri::async_runtime::block_on(execute(&app, &args));
+    // 再ログインなどで更新されたCookieを保存する
+    if let Err(e) = accounts::save_current_session(&app) {
+        error!("Failed to save cookies: {:?}", e);
+    }
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(CliError::Usage(message)) => {
+            eprintln!("{message}\n\n{USAGE}");
+            ExitCode::from(2)
+        }
+        Err(CliError::Failed(message)) => {
+            eprintln!("Error: {message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn usage_message<T>(result: Result<T, CliError>) -> String {
+        match result {
+            Err(CliError::Usage(message)) => message,
+            Err(CliError::Failed(message)) => panic!("expected a usage error, got: {message}"),
+            Ok(_) => panic!("expected a usage error"),
+        }
+    }
+
+    #[test]
+    fn parses_options_flags_and_positional_arguments() {
+        let args = parse(&["--account", "alice", "status", "set", "--json", "busy", "in a meeting"]).unwrap();
+        assert_eq!(args.positional, ["status", "set", "busy", "in a meeting"]);
+        assert_eq!(args.option("--account"), Some("alice"));
+        assert_eq!(args.option("--format"), None);
+        assert!(args.flag("--json"));
+        assert!(!args.flag("--offline"));
+
+        // 同じオプションは後の値を使う
+        let args = parse(&["export", "--format", "csv", "--format", "json"]).unwrap();
+        assert_eq!(args.option("--format"), Some("json"));
+    }
+
+    #[test]
+    fn rejects_unknown_options_and_missing_values() {
+        assert_eq!(parse(&["friends", "--verbose"]).err().unwrap(), "Unknown option: --verbose");
+        assert_eq!(parse(&["export", "--output"]).err().unwrap(), "--output needs a value");
+        // 値は `--` で始まっていてもそのまま受け取る
+        assert_eq!(parse(&["--account", "--json"]).unwrap().option("--account"), Some("--json"));
+    }
+
+    #[test]
+    fn parses_statuses_with_hyphens_or_spaces() {
+        assert_eq!(parse_status("join-me").ok(), Some(UserStatus::JoinMe));
+        assert_eq!(parse_status("join me").ok(), Some(UserStatus::JoinMe));
+        assert_eq!(parse_status("ask-me").ok(), Some(UserStatus::AskMe));
+        assert_eq!(parse_status("active").ok(), Some(UserStatus::Active));
+        assert_eq!(parse_status("busy").ok(), Some(UserStatus::Busy));
+        assert_eq!(usage_message(parse_status("offline")), "Unknown status: offline");
+        assert_eq!(usage_message(parse_status("Busy")), "Unknown status: Busy");
+    }
+
+    #[test]
+    fn export_options_default_to_csv_with_the_default_columns() {
+        let options = parse_export_options(&parse(&["export"]).unwrap()).ok().unwrap();
+        assert_eq!(options.format, ExportFormat::Csv);
+        assert_eq!(options.columns, DEFAULT_EXPORT_COLUMNS);
+        assert!(!options.include_offline);
+        assert!(!options.group_by_instance);
+        assert_eq!(options.path, None);
+    }
+
+    #[test]
+    fn parses_export_options() {
+        let args = parse(&[
+            "export",
+            "--format",
+            "md",
+            "--columns",
+            "worldName, friendCount,instanceTags",
+            "--output",
+            "out.md",
+            "--offline",
+            "--group",
+        ])
+        .unwrap();
+        let options = parse_export_options(&args).ok().unwrap();
+        assert_eq!(options.format, ExportFormat::Markdown);
+        assert_eq!(
+            options.columns,
+            [ExportColumn::WorldName, ExportColumn::FriendCount, ExportColumn::InstanceTags]
+        );
+        assert!(options.include_offline);
+        assert!(options.group_by_instance);
+        assert_eq!(options.path.as_deref(), Some("out.md"));
+
+        let format = |format: &str| parse_export_options(&parse(&["export", "--format", format]).unwrap());
+        assert_eq!(format("json").ok().unwrap().format, ExportFormat::Json);
+        assert_eq!(format("markdown").ok().unwrap().format, ExportFormat::Markdown);
+        assert_eq!(usage_message(format("xlsx")), "Unknown format: xlsx");
+    }
+
+    #[test]
+    fn rejects_unknown_export_columns() {
+        let args = parse(&["export", "--columns", "worldName,world_name"]).unwrap();
+        assert_eq!(usage_message(parse_export_options(&args)), "Unknown column: world_name");
+        let args = parse(&["export", "--columns", ""]).unwrap();
+        assert_eq!(usage_message(parse_export_options(&args)), "Unknown column: ");
+    }
+}
@@ -1,10 +1,15 @@
-use crate::structs::{ApiResponse, AppState, World};
-use crate::{load_cookies, save_cookies, CLIENT, COOKIE_STORE};
-use log::{debug, error, trace};
+use crate::structs::{ApiResponse, AppState, CurrentUserUpdate, UserStatus, World};
+use crate::friend_history;
+use crate::accounts::{self, client};
+use crate::reauth::SendWithReauth;
+use crate::redaction::{self, SecretKind};
+use crate::{reauth, totp};
+use log::{debug, error, trace, warn};
 use once_cell::sync::Lazy;
-use reqwest::Response;
+use reqwest::{Client, Response};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::HashMap;
 use std::fmt::Display;
 use std::sync::Arc;
 use std::time;
@@ -13,13 +18,12 @@ use tauri::path::BaseDirectory;
 use tauri::{generate_handler, Manager};
 use tokio::sync::RwLock;
 
-const VRCHAT_API_BASE_URL: &str = "https://api.vrchat.cloud/api";
+pub(crate) const VRCHAT_API_BASE_URL: &str = "https://api.vrchat.cloud/api";
 
 pub(crate) fn handlers() -> impl Fn(Invoke) -> bool + Send + Sync + 'static {
     generate_handler![
         login,
-        email_otp,
-        two_factor_auth,
+        verify_two_factor,
         verify_auth_token,
         cookie_clear,
         get_current_user_info,
@@ -33,8 +37,74 @@ pub(crate) fn handlers() -> impl Fn(Invoke) -> bool + Send + Sync + 'static {
         debug_api_request,
         get_group_by_id,
         get_user_group_instances,
-        switch_user,
         get_release_note,
+        get_own_avatars,
+        get_favorite_avatars,
+        get_avatar_by_id,
+        select_avatar,
+        update_current_user_profile,
+        set_current_user_status,
+        crate::accounts::get_accounts,
+        crate::accounts::get_current_account,
+        crate::accounts::save_account,
+        crate::accounts::remove_account,
+        crate::accounts::rename_account,
+        crate::accounts::set_default_account,
+        crate::accounts::switch_user,
+        crate::reauth::remember_login_credentials,
+        crate::reauth::forget_login_credentials,
+        crate::reauth::has_login_credentials,
+        crate::reauth::reauthenticate_session,
+        crate::reauth::submit_reauth_otp,
+        crate::reauth::cancel_reauth,
+        crate::totp::has_totp_secret,
+        crate::totp::forget_totp_secret,
+        crate::redaction::get_log_redaction_policy,
+        crate::redaction::set_log_redaction_policy,
+        crate::settings::get_settings,
+        crate::settings::update_settings,
+        crate::scheduler::get_status_schedules,
+        crate::scheduler::set_status_schedules,
+        crate::scheduler::get_status_schedule_log,
+        crate::notes::get_user_notes,
+        crate::notes::set_user_note,
+        crate::notes::set_user_tags,
+        crate::notes::get_all_tags,
+        crate::notes::sync_user_notes,
+        crate::friends::search_friends,
+        crate::friends::get_merged_friends,
+        crate::export::export_friend_instances,
+        crate::local_api::get_local_api_status,
+        crate::local_api::get_local_api_token,
+        crate::local_api::regenerate_local_api_token,
+        crate::presence::get_presence,
+        crate::presence::get_presence_file_path,
+        crate::instance_watcher::watch_instance,
+        crate::instance_watcher::unwatch_instance,
+        crate::instance_watcher::get_instance_watches,
+        crate::instance_watcher::set_auto_invite,
+        crate::instance_watcher::cancel_auto_invite,
+        crate::osc::get_osc_config,
+        crate::osc::set_osc_config,
+        crate::osc::send_test_osc_message,
+        crate::webhooks::get_webhooks,
+        crate::webhooks::set_webhooks,
+        crate::webhooks::get_webhook_deliveries,
+        crate::webhooks::send_test_webhook,
+        crate::backup::create_backup,
+        crate::backup::restore_backup,
+        crate::friend_history::get_friend_changes,
+        crate::history::get_vrchat_log_dir,
+        crate::history::set_vrchat_log_dir,
+        crate::history::get_instance_history,
+        crate::history::get_current_instance_visit,
+        crate::log_import::import_vrchat_logs,
+        crate::screenshots::get_screenshot_dir,
+        crate::screenshots::set_screenshot_dir,
+        crate::screenshots::index_screenshots,
+        crate::screenshots::search_screenshots,
+        crate::screenshots::read_screenshot_metadata,
+        crate::screenshots::write_screenshot_metadata,
     ]
 }
 
@@ -43,8 +113,7 @@ pub(crate) fn export_ts() {
     tauri_specta::Builder::<tauri::Wry>::new()
         .commands(tauri_specta::collect_commands![
             login,
-            email_otp,
-            two_factor_auth,
+            verify_two_factor,
             verify_auth_token,
             cookie_clear,
             get_current_user_info,
@@ -58,8 +127,74 @@ pub(crate) fn export_ts() {
             debug_api_request,
             get_group_by_id,
             get_user_group_instances,
-            switch_user,
             get_release_note,
+            get_own_avatars,
+            get_favorite_avatars,
+            get_avatar_by_id,
+            select_avatar,
+            update_current_user_profile,
+            set_current_user_status,
+            crate::accounts::get_accounts,
+            crate::accounts::get_current_account,
+            crate::accounts::save_account,
+            crate::accounts::remove_account,
+            crate::accounts::rename_account,
+            crate::accounts::set_default_account,
+            crate::accounts::switch_user,
+            crate::reauth::remember_login_credentials,
+            crate::reauth::forget_login_credentials,
+            crate::reauth::has_login_credentials,
+            crate::reauth::reauthenticate_session,
+            crate::reauth::submit_reauth_otp,
+            crate::reauth::cancel_reauth,
+            crate::totp::has_totp_secret,
+            crate::totp::forget_totp_secret,
+            crate::redaction::get_log_redaction_policy,
+            crate::redaction::set_log_redaction_policy,
+            crate::settings::get_settings,
+            crate::settings::update_settings,
+            crate::scheduler::get_status_schedules,
+            crate::scheduler::set_status_schedules,
+            crate::scheduler::get_status_schedule_log,
+            crate::notes::get_user_notes,
+            crate::notes::set_user_note,
+            crate::notes::set_user_tags,
+            crate::notes::get_all_tags,
+            crate::notes::sync_user_notes,
+            crate::friends::search_friends,
+            crate::friends::get_merged_friends,
+            crate::export::export_friend_instances,
+            crate::local_api::get_local_api_status,
+            crate::local_api::get_local_api_token,
+            crate::local_api::regenerate_local_api_token,
+            crate::presence::get_presence,
+            crate::presence::get_presence_file_path,
+            crate::instance_watcher::watch_instance,
+            crate::instance_watcher::unwatch_instance,
+            crate::instance_watcher::get_instance_watches,
+            crate::instance_watcher::set_auto_invite,
+            crate::instance_watcher::cancel_auto_invite,
+            crate::osc::get_osc_config,
+            crate::osc::set_osc_config,
+            crate::osc::send_test_osc_message,
+            crate::webhooks::get_webhooks,
+            crate::webhooks::set_webhooks,
+            crate::webhooks::get_webhook_deliveries,
+            crate::webhooks::send_test_webhook,
+            crate::backup::create_backup,
+            crate::backup::restore_backup,
+            crate::friend_history::get_friend_changes,
+            crate::history::get_vrchat_log_dir,
+            crate::history::set_vrchat_log_dir,
+            crate::history::get_instance_history,
+            crate::history::get_current_instance_visit,
+            crate::log_import::import_vrchat_logs,
+            crate::screenshots::get_screenshot_dir,
+            crate::screenshots::set_screenshot_dir,
+            crate::screenshots::index_screenshots,
+            crate::screenshots::search_screenshots,
+            crate::screenshots::read_screenshot_metadata,
+            crate::screenshots::write_screenshot_metadata,
         ])
         .export(
             specta_typescript::Typescript::default()
@@ -88,6 +223,10 @@ macro_rules! handle_raw_response {
     }};
 }
 
+async fn read_raw_response(res: Response) -> Result<String, RustError> {
+    handle_raw_response!(res)
+}
+
 static APP_STATE: Lazy<Arc<RwLock<AppState>>> =
     Lazy::new(|| Arc::new(RwLock::new(AppState::default())));
 
@@ -96,11 +235,37 @@ pub async fn insert_world(world_id: String, world: World) {
     state.worlds.world.insert(world_id, world);
 }
 
+/// アカウントを切り替えたときに、前のアカウントに紐づく状態を破棄する
+pub(crate) async fn reset_session_state() {
+    let mut state = APP_STATE.write().await;
+    state.is_login = false;
+    state.loading_friends = None;
+}
+
+pub(crate) async fn is_login() -> bool {
+    APP_STATE.read().await.is_login
+}
+
+pub(crate) async fn cached_worlds() -> Vec<World> {
+    let state = APP_STATE.read().await;
+    state.worlds.world.values().cloned().collect()
+}
+
 pub async fn get_world(world_id: String) -> Option<World> {
     let state = APP_STATE.read().await;
     state.worlds.world.get(&world_id).cloned()
 }
 
+pub async fn insert_avatar(avatar_id: String, avatar: String) {
+    let mut state = APP_STATE.write().await;
+    state.avatars.avatar.insert(avatar_id, avatar);
+}
+
+pub async fn get_avatar(avatar_id: String) -> Option<String> {
+    let state = APP_STATE.read().await;
+    state.avatars.avatar.get(&avatar_id).cloned()
+}
+
 #[derive(Debug, Clone, Serialize, specta::Type)]
 #[specta(export)]
 #[serde(tag = "type")]
@@ -114,6 +279,12 @@ impl RustError {
             message: value.to_string(),
         }
     }
+
+    pub(crate) fn message(&self) -> &str {
+        match self {
+            Self::Unrecoverable { message } => message,
+        }
+    }
 }
 
 impl<E: Display> From<E> for RustError {
@@ -125,148 +296,249 @@ impl<E: Display> From<E> for RustError {
 
 #[tauri::command]
 #[specta::specta]
-async fn cookie_clear(_app_handle: tauri::AppHandle) {
+async fn cookie_clear(app_handle: tauri::AppHandle) -> Result<(), RustError> {
     debug!("Call cookie_clear");
-    
-    let cookie_store = COOKIE_STORE.clone();
-    cookie_store.lock().unwrap().clear();
+
+    accounts::start_anonymous_session(&app_handle).await
 }
 
-#[tauri::command]
-#[specta::specta]
-fn switch_user(app_handle: tauri::AppHandle, user_id: &str) {
-    debug!("Switch user to {:?}", user_id);
+/// 2要素認証の方式。値はVRChat APIの `requiresTwoFactorAuth` と同じ
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub enum OtpMethod {
+    /// メールに届くコード
+    EmailOtp,
+    /// 認証アプリのコード
+    Totp,
+    /// TOTPを設定したときに発行されるリカバリーコード
+    Otp,
+}
 
-    let _ = load_cookies(&app_handle, user_id);
+impl OtpMethod {
+    fn verify_path(self) -> &'static str {
+        match self {
+            OtpMethod::EmailOtp => "emailotp",
+            OtpMethod::Totp => "totp",
+            OtpMethod::Otp => "otp",
+        }
+    }
+
+    /// 複数の方式が使えるときに最初に案内する方式
+    pub(crate) fn preferred(methods: &[OtpMethod]) -> Option<OtpMethod> {
+        [OtpMethod::EmailOtp, OtpMethod::Totp, OtpMethod::Otp]
+            .into_iter()
+            .find(|method| methods.contains(method))
+    }
 }
 
-#[tauri::command]
-#[specta::specta]
-async fn login(
-    _app_handle: tauri::AppHandle,
-    user_name: &str,
-    password: &str,
-) -> Result<String, RustError> {
-    debug!("Call login {:?} {:?}", user_name, password);
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(tag = "type")]
+pub enum LoginOutcome {
+    #[serde(rename_all = "camelCase")]
+    LoggedIn { user_id: String, display_name: String },
+    /// 2要素認証が必要。`methods` のいずれかで `verify_two_factor` を呼ぶ
+    TwoFactorRequired { methods: Vec<OtpMethod> },
+}
 
-    let client = CLIENT.clone();
+/// `/auth/user` のレスポンスをログイン結果に変換する
+pub(crate) fn parse_login_response(res_json: &serde_json::Value) -> Result<LoginOutcome, RustError> {
+    if let Some(methods) = res_json["requiresTwoFactorAuth"].as_array() {
+        let methods: Vec<OtpMethod> = methods
+            .iter()
+            .filter_map(|method| serde_json::from_value(method.clone()).ok())
+            .collect();
+        if methods.is_empty() {
+            error!("No supported 2FA method: {:?}", res_json["requiresTwoFactorAuth"]);
+            return Err("errors.2faUnsupported".into());
+        }
+        return Ok(LoginOutcome::TwoFactorRequired { methods });
+    }
 
+    let user_id = res_json["id"].as_str().ok_or("errors.loginFail")?;
+    Ok(LoginOutcome::LoggedIn {
+        user_id: user_id.to_string(),
+        display_name: res_json["displayName"].as_str().unwrap_or_default().to_string(),
+    })
+}
+
+/// 2要素認証のコードを送信し、認証されたかを返す
+pub(crate) async fn post_two_factor_code(client: &Client, method: OtpMethod, code: &str) -> Result<bool, RustError> {
+    // リカバリーコードは表示どおりに空白やハイフン入りで貼り付けられることがある
+    let code: String = match method {
+        OtpMethod::Otp => code.chars().filter(|c| c.is_ascii_alphanumeric()).collect(),
+        _ => code.trim().to_string(),
+    };
     let res = client
-        .get(format!("{VRCHAT_API_BASE_URL}/1/auth/user"))
-        .basic_auth(user_name, Some(password))
+        .post(format!(
+            "{VRCHAT_API_BASE_URL}/1/auth/twofactorauth/{}/verify",
+            method.verify_path()
+        ))
+        .json(&json!({"code": code}))
         .send()
         .await?;
 
     match res.status() {
         reqwest::StatusCode::OK => {
-            let res_text = res.error_for_status()?.text().await?;
-            let res_json: serde_json::Value = serde_json::from_str(&res_text).unwrap();
-            if res_json["requiresTwoFactorAuth"].is_array() {
-                if res_json["requiresTwoFactorAuth"]
-                    .as_array()
-                    .unwrap()
-                    .contains(&json!("emailOtp"))
-                {
-                    Ok("emailOtp".to_string())
-                } else {
-                    Ok("totp".to_string())
-                }
-            } else {
-                Ok(res_text)
-            }
+            let res_json: serde_json::Value = res.json().await?;
+            Ok(res_json["verified"].as_bool() == Some(true))
         }
-        reqwest::StatusCode::UNAUTHORIZED => {
-            error!("Login failed: {:?}", res);
-            Err("errors.loginFail".into())
+        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::BAD_REQUEST => {
+            error!("2FA failed: {:?}", res);
+            Err("errors.2faFail".into())
         },
         _ => {
-            error!("Login failed: {:?}", res);
+            error!("2FA failed: {:?}", res);
             Err(res.status().into())
         },
     }
 }
 
-#[tauri::command]
-#[specta::specta]
-async fn email_otp(app_handle: tauri::AppHandle, otp: &str) -> Result<bool, RustError> {
-    debug!("Call email_otp {:?}", otp);
-
-    let client = CLIENT.clone();
+/// 2要素認証が済んだセッションを、ログインしたアカウントのセッションにする
+async fn adopt_verified_session(app_handle: &tauri::AppHandle) -> Result<LoginOutcome, RustError> {
+    let res = get_current_user_info_inner().await?;
+    match res.status() {
+        reqwest::StatusCode::OK => {
+            let rj: serde_json::Value = res.json().await?;
+            match parse_login_response(&rj)? {
+                LoginOutcome::LoggedIn { user_id, display_name } => {
+                    accounts::adopt_session(app_handle, &user_id).await?;
+                    Ok(LoginOutcome::LoggedIn { user_id, display_name })
+                }
+                LoginOutcome::TwoFactorRequired { .. } => Err("errors.2faFail".into()),
+            }
+        },
+        _ => {
+            error!("Failed to get current user info after 2FA: {:?}", res);
+            Err("errors.2faFail".into())
+        }
+    }
+}
 
-    let res = client
-        .post(format!(
-            "{VRCHAT_API_BASE_URL}/1/auth/twofactorauth/emailotp/verify"
-        ))
-        .json(&json!({"code": otp}))
-        .send()
-        .await?;
+/// 入力された、または保存済みのTOTPシークレットがあればコードを作って2要素認証まで済ませる。
+/// シークレットが無ければ `None`
+async fn login_with_totp_secret(
+    app_handle: &tauri::AppHandle,
+    user_name: &str,
+    totp_secret: Option<&str>,
+) -> Result<Option<LoginOutcome>, RustError> {
+    let code = match totp_secret {
+        Some(secret) => totp::generate_from_secret(secret)?,
+        None => {
+            let Some(user_id) = reauth::account_for_user_name(app_handle, user_name)? else {
+                return Ok(None);
+            };
+            let Some(code) = totp::generate_code(app_handle, &user_id)? else {
+                return Ok(None);
+            };
+            code
+        }
+    };
+    // 時計のずれや誤ったシークレットで拒否されたときは、コードの入力を求める
+    match post_two_factor_code(&client(), OtpMethod::Totp, &code).await {
+        Ok(true) => {}
+        Ok(false) => {
+            warn!("Generated TOTP code was not verified, asking for a code");
+            return Ok(None);
+        }
+        Err(e) if e.message() == "errors.2faFail" => {
+            warn!("Generated TOTP code was rejected, asking for a code");
+            return Ok(None);
+        }
+        Err(e) => return Err(e),
+    }
 
-    otp_verified_check(app_handle, res).await
+    let outcome = adopt_verified_session(app_handle).await?;
+    if let (Some(secret), LoginOutcome::LoggedIn { user_id, .. }) = (totp_secret, &outcome) {
+        totp::save_secret(app_handle, user_id, secret)?;
+    }
+    Ok(Some(outcome))
 }
 
+/// `totp_secret` を渡すと、認証アプリのコードを求められたときに自動で入力し、
+/// ログインしたアカウントのシークレットとしてキーリングに保存する
 #[tauri::command]
 #[specta::specta]
-async fn two_factor_auth(app_handle: tauri::AppHandle, otp: &str) -> Result<bool, RustError> {
-    debug!("Call two_factor_auth {:?}", otp);
+async fn login(
+    app_handle: tauri::AppHandle,
+    user_name: &str,
+    password: &str,
+    totp_secret: Option<String>,
+) -> Result<LoginOutcome, RustError> {
+    redaction::register_secret(SecretKind::Credential, password);
+    if let Some(secret) = &totp_secret {
+        redaction::register_secret(SecretKind::Credential, secret);
+    }
+    debug!("Call login {:?}", user_name);
 
-    let client = CLIENT.clone();
+    let client = client();
 
     let res = client
-        .post(format!(
-            "{VRCHAT_API_BASE_URL}/1/auth/twofactorauth/totp/verify"
-        ))
-        .json(&json!({"code": otp}))
+        .get(format!("{VRCHAT_API_BASE_URL}/1/auth/user"))
+        .basic_auth(user_name, Some(password))
         .send()
         .await?;
 
-    otp_verified_check(app_handle, res).await
-}
-
-async fn otp_verified_check(app_handle: tauri::AppHandle, r: Response) -> Result<bool, RustError> {
-    match r.status() {
+    match res.status() {
         reqwest::StatusCode::OK => {
-            let res_text = r.error_for_status()?.text().await?;
-            let res_json: serde_json::Value = serde_json::from_str(&res_text).unwrap();
-            if res_json["verified"].is_boolean() {
-                if res_json["verified"].as_bool().unwrap() {
-                    let res = get_current_user_info_inner().await;
-                    match res.status() {
-                        reqwest::StatusCode::OK => {
-                            let rt = res.error_for_status()?.text().await?;
-                            let rj: serde_json::Value = serde_json::from_str(&rt).unwrap();
-                            save_cookies(&app_handle, rj["id"].as_str().unwrap()).await?;
-                            return Ok(true);
-                        },
-                        _ => {
-                            error!("Failed to get current user info after 2FA: {:?}", res);
-                            return Err("errors.2faFail".into());
+            let res_json: serde_json::Value = res.json().await?;
+            match parse_login_response(&res_json)? {
+                LoginOutcome::LoggedIn { user_id, display_name } => {
+                    accounts::adopt_session(&app_handle, &user_id).await?;
+                    Ok(LoginOutcome::LoggedIn { user_id, display_name })
+                }
+                LoginOutcome::TwoFactorRequired { methods } => {
+                    if methods.contains(&OtpMethod::Totp) {
+                        let totp_secret = totp_secret.as_deref().filter(|secret| !secret.trim().is_empty());
+                        if let Some(outcome) = login_with_totp_secret(&app_handle, user_name, totp_secret).await? {
+                            return Ok(outcome);
                         }
                     }
+                    Ok(LoginOutcome::TwoFactorRequired { methods })
                 }
             }
-            Ok(false)
         }
         reqwest::StatusCode::UNAUTHORIZED => {
-            error!("2FA failed: {:?}", r);
-            Err("errors.2faFail".into())
-        },
-        reqwest::StatusCode::BAD_REQUEST => {
-            error!("2FA failed: {:?}", r);
-            Err("errors.2faFail".into())
+            error!("Login failed: {:?}", res);
+            Err("errors.loginFail".into())
         },
         _ => {
-            error!("2FA failed: {:?}", r);
-            Err(r.status().into())
+            error!("Login failed: {:?}", res);
+            Err(res.status().into())
         },
     }
 }
 
+/// `login` で2要素認証が必要になったときに、選んだ方式のコードを送信する。
+/// `remember_device` が false なら、セッションが切れて再ログインするときに再びコードを求める
+#[tauri::command]
+#[specta::specta]
+async fn verify_two_factor(
+    app_handle: tauri::AppHandle,
+    method: OtpMethod,
+    code: &str,
+    remember_device: bool,
+) -> Result<bool, RustError> {
+    redaction::register_secret(SecretKind::Otp, code);
+    debug!("Call verify_two_factor {:?} {:?}", method, remember_device);
+
+    if !post_two_factor_code(&client(), method, code).await? {
+        return Ok(false);
+    }
+    adopt_verified_session(&app_handle).await?;
+    if !remember_device {
+        accounts::forget_trusted_device(&app_handle)?;
+    }
+    Ok(true)
+}
+
 #[tauri::command]
 #[specta::specta]
 async fn verify_auth_token() -> Result<bool, RustError> {
     debug!("Call verify_auth_token");
 
-    let client = CLIENT.clone();
+    let client = client();
 
     let res = client
         .get(format!("{VRCHAT_API_BASE_URL}/1/auth"))
@@ -277,11 +549,9 @@ async fn verify_auth_token() -> Result<bool, RustError> {
         reqwest::StatusCode::OK => {
             let res_text = res.error_for_status()?.text().await?;
             let res_json: serde_json::Value = serde_json::from_str(&res_text).unwrap();
-            if res_json["ok"].is_boolean() {
-                if res_json["ok"].as_bool().unwrap() {
-                    APP_STATE.write().await.is_login = true;
-                    return Ok(true);
-                }
+            if res_json["ok"].as_bool() == Some(true) {
+                APP_STATE.write().await.is_login = true;
+                return Ok(true);
             }
             Ok(false)
         }
@@ -301,40 +571,80 @@ async fn verify_auth_token() -> Result<bool, RustError> {
 async fn get_current_user_info() -> Result<String, RustError> {
     debug!("Call get_current_user_info");
 
-    let res = get_current_user_info_inner().await;
+    let res = get_current_user_info_inner().await?;
 
     handle_raw_response!(res)
 }
 
-async fn get_current_user_info_inner() -> Response {
-    let client = CLIENT.clone();
+async fn get_current_user_info_inner() -> Result<Response, RustError> {
+    let client = client();
 
     let res = client
         .get(format!("{VRCHAT_API_BASE_URL}/1/auth/user"))
-        .send()
-        .await.unwrap();
+        .send_with_reauth()
+        .await?;
 
-    res
+    Ok(res)
 }
 
 #[tauri::command]
 #[specta::specta]
-async fn get_current_user_friends(offset: i32, n: i32, offline: bool) -> Result<String, RustError> {
+async fn get_current_user_friends(
+    app_handle: tauri::AppHandle,
+    offset: i32,
+    n: i32,
+    offline: bool,
+) -> Result<String, RustError> {
     debug!(
         "Call get_current_user_friends {:?} {:?} {:?}",
         offset, n, offline
     );
 
-    let client = CLIENT.clone();
+    let client = client();
 
     let res = client
         .get(format!("{VRCHAT_API_BASE_URL}/1/auth/user/friends"))
         .query(&[("offset", offset), ("n", n)])
         .query(&[("offline", offline)])
-        .send()
+        .send_with_reauth()
         .await?;
 
-    handle_raw_response!(res)
+    let res_text = read_raw_response(res).await?;
+    collect_friends_page(&app_handle, offset, offline, &res_text).await;
+
+    Ok(res_text)
+}
+
+// オンライン→オフラインの順に全ページ読み込まれたらフレンド一覧のスナップショットを記録する
+async fn collect_friends_page(app_handle: &tauri::AppHandle, offset: i32, offline: bool, res_text: &str) {
+    let page: Vec<serde_json::Value> = match serde_json::from_str(res_text) {
+        Ok(page) => page,
+        Err(e) => {
+            error!("Failed to parse friends page {:?}", e);
+            return;
+        }
+    };
+
+    let finished = {
+        let mut state = APP_STATE.write().await;
+        if !offline && offset == 0 {
+            state.loading_friends = Some(HashMap::new());
+        }
+        if let Some(loading) = state.loading_friends.as_mut() {
+            loading.extend(friend_history::collect_names(&page));
+        }
+        if offline && page.is_empty() {
+            state.loading_friends.take()
+        } else {
+            None
+        }
+    };
+
+    if let Some(friends) = finished {
+        if let Err(e) = friend_history::record_snapshot(app_handle, friends).await {
+            error!("Failed to record friend snapshot: {:?}", e);
+        }
+    }
 }
 
 #[tauri::command]
@@ -342,26 +652,38 @@ async fn get_current_user_friends(offset: i32, n: i32, offline: bool) -> Result<
 async fn get_user_by_id(user_id: &str) -> Result<String, RustError> {
     debug!("Call get_user_by_id {:?}", user_id);
 
-    let clinet = CLIENT.clone();
+    let clinet = client();
 
     let res = clinet
         .get(format!("{VRCHAT_API_BASE_URL}/1/users/{user_id}"))
-        .send()
+        .send_with_reauth()
         .await?;
 
     handle_raw_response!(res)
 }
 
+pub(crate) async fn get_user_json(user_id: &str) -> Result<serde_json::Value, RustError> {
+    let client = client();
+
+    let res = client
+        .get(format!("{VRCHAT_API_BASE_URL}/1/users/{user_id}"))
+        .send_with_reauth()
+        .await?;
+    let res_text = read_raw_response(res).await?;
+
+    Ok(serde_json::from_str(&res_text)?)
+}
+
 #[tauri::command]
 #[specta::specta]
 async fn get_group_by_id(group_id: &str) -> Result<String, RustError> {
     debug!("Call get_group_by_id {:?}", group_id);
 
-    let clinet = CLIENT.clone();
+    let clinet = client();
 
     let res = clinet
         .get(format!("{VRCHAT_API_BASE_URL}/1/groups/{group_id}"))
-        .send()
+        .send_with_reauth()
         .await?;
 
     handle_raw_response!(res)
@@ -369,7 +691,7 @@ async fn get_group_by_id(group_id: &str) -> Result<String, RustError> {
 
 #[tauri::command]
 #[specta::specta]
-async fn get_world_by_id(worldid: &str) -> Result<String, RustError> {
+pub(crate) async fn get_world_by_id(worldid: &str) -> Result<String, RustError> {
     debug!("Call get_world_by_id {:?}", worldid);
 
     if worldid == "private" {
@@ -416,14 +738,14 @@ async fn get_world_by_id(worldid: &str) -> Result<String, RustError> {
                 worldid,
                 now.elapsed()
             );
-            return Ok(serde_json::to_string(&result).unwrap());
+            Ok(serde_json::to_string(&result).unwrap())
         }
         None => {
-            let client = CLIENT.clone();
+            let client = client();
 
             let res = client
                 .get(format!("{VRCHAT_API_BASE_URL}/1/worlds/{worldid}"))
-                .send()
+                .send_with_reauth()
                 .await?;
 
             match res.status() {
@@ -467,11 +789,11 @@ async fn get_world_by_id(worldid: &str) -> Result<String, RustError> {
 async fn get_raw_world_by_id(worldid: &str) -> Result<String, RustError> {
     debug!("Call get_raw_world_by_id {:?}", worldid);
 
-    let client = CLIENT.clone();
+    let client = client();
 
     let res = client
         .get(format!("{VRCHAT_API_BASE_URL}/1/worlds/{worldid}"))
-        .send()
+        .send_with_reauth()
         .await?;
 
     handle_raw_response!(res)
@@ -485,13 +807,13 @@ async fn get_instance(worldid: &str, instanceid: &str) -> Result<String, RustErr
         worldid, instanceid
     );
 
-    let client = CLIENT.clone();
+    let client = client();
 
     let res = client
         .get(format!(
             "{VRCHAT_API_BASE_URL}/1/instances/{worldid}:{instanceid}"
         ))
-        .send()
+        .send_with_reauth()
         .await?;
 
     handle_raw_response!(res)
@@ -499,19 +821,19 @@ async fn get_instance(worldid: &str, instanceid: &str) -> Result<String, RustErr
 
 #[tauri::command]
 #[specta::specta]
-async fn invite_myself_to_instance(world_id: &str, instance_id: &str) -> Result<bool, RustError> {
+pub(crate) async fn invite_myself_to_instance(world_id: &str, instance_id: &str) -> Result<bool, RustError> {
     debug!(
         "Call invite_myself_to_instance {:?} {:?}",
         world_id, instance_id
     );
 
-    let client = CLIENT.clone();
+    let client = client();
 
     let res = client
         .post(format!(
             "{VRCHAT_API_BASE_URL}/1/invite/myself/to/{world_id}:{instance_id}"
         ))
-        .send()
+        .send_with_reauth()
         .await?;
 
     match res.status() {
@@ -546,7 +868,7 @@ pub async fn get_licenses(app_handle: tauri::AppHandle) -> Result<ApiResponse, R
 #[specta::specta]
 pub async fn get_release_note(tag_name: &str) -> Result<String, RustError> {
     debug!("Call get_release_note {:?}", tag_name);
-    let client = CLIENT.clone();
+    let client = client();
 
     let res = client
         .get(format!(
@@ -570,7 +892,7 @@ pub struct DebugApiRequest {
 #[tauri::command]
 #[specta::specta]
 async fn debug_api_request(request: DebugApiRequest) -> Result<ApiResponse, RustError> {
-    let client = CLIENT.clone();
+    let client = client();
 
     let mut req = match request.method.to_uppercase().as_str() {
         "GET" => client.get(format!("{VRCHAT_API_BASE_URL}{}", request.endpoint)),
@@ -614,7 +936,7 @@ async fn get_user_group_instances() -> Result<String, RustError> {
     debug!("Call get_user_group_instances");
 
     // 現在のユーザー情報を取得してIDを抽出
-    let current_user_res = get_current_user_info_inner().await;
+    let current_user_res = get_current_user_info_inner().await?;
     
     match current_user_res.status() {
         reqwest::StatusCode::OK => {
@@ -626,10 +948,10 @@ async fn get_user_group_instances() -> Result<String, RustError> {
                 .ok_or("User ID not found in response")?;
             
             // ユーザーのグループインスタンスを取得
-            let client = CLIENT.clone();
+            let client = client();
             let res = client
                 .get(format!("{VRCHAT_API_BASE_URL}/1/users/{user_id}/instances/groups"))
-                .send()
+                .send_with_reauth()
                 .await?;
             
             handle_raw_response!(res)
@@ -644,3 +966,144 @@ async fn get_user_group_instances() -> Result<String, RustError> {
         }
     }
 }
+
+#[tauri::command]
+#[specta::specta]
+async fn get_own_avatars(offset: i32, n: i32) -> Result<String, RustError> {
+    debug!("Call get_own_avatars {:?} {:?}", offset, n);
+
+    let client = client();
+
+    let res = client
+        .get(format!("{VRCHAT_API_BASE_URL}/1/avatars"))
+        .query(&[("user", "me"), ("releaseStatus", "all"), ("sort", "updated"), ("order", "descending")])
+        .query(&[("offset", offset), ("n", n)])
+        .send_with_reauth()
+        .await?;
+
+    handle_raw_response!(res)
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn get_favorite_avatars(offset: i32, n: i32) -> Result<String, RustError> {
+    debug!("Call get_favorite_avatars {:?} {:?}", offset, n);
+
+    let client = client();
+
+    let res = client
+        .get(format!("{VRCHAT_API_BASE_URL}/1/avatars/favorites"))
+        .query(&[("offset", offset), ("n", n)])
+        .send_with_reauth()
+        .await?;
+
+    handle_raw_response!(res)
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn get_avatar_by_id(avatarid: &str) -> Result<String, RustError> {
+    debug!("Call get_avatar_by_id {:?}", avatarid);
+
+    let now = time::Instant::now();
+
+    if let Some(result) = get_avatar(avatarid.to_string()).await {
+        trace!(
+            "Receive cached get_avatar_by_id {:?} time: {:?}",
+            avatarid,
+            now.elapsed()
+        );
+        return Ok(result);
+    }
+
+    let client = client();
+
+    let res = client
+        .get(format!("{VRCHAT_API_BASE_URL}/1/avatars/{avatarid}"))
+        .send_with_reauth()
+        .await?;
+
+    let res_text = read_raw_response(res).await?;
+    insert_avatar(avatarid.to_string(), res_text.clone()).await;
+    trace!(
+        "Receive get_avatar_by_id {:?} time: {:?}",
+        avatarid,
+        now.elapsed()
+    );
+
+    Ok(res_text)
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn select_avatar(avatar_id: &str) -> Result<String, RustError> {
+    debug!("Call select_avatar {:?}", avatar_id);
+
+    let client = client();
+
+    let res = client
+        .put(format!("{VRCHAT_API_BASE_URL}/1/avatars/{avatar_id}/select"))
+        .send_with_reauth()
+        .await?;
+
+    handle_raw_response!(res)
+}
+
+pub(crate) async fn get_current_user_json() -> Result<serde_json::Value, RustError> {
+    let res = get_current_user_info_inner().await?;
+    let res_text = read_raw_response(res).await?;
+
+    Ok(serde_json::from_str(&res_text)?)
+}
+
+pub(crate) async fn get_current_user_id() -> Result<String, RustError> {
+    let res_json = get_current_user_json().await?;
+
+    match res_json["id"].as_str() {
+        Some(user_id) => Ok(user_id.to_string()),
+        None => Err("User ID not found in response".into()),
+    }
+}
+
+pub(crate) async fn update_current_user_inner(update: &CurrentUserUpdate) -> Result<String, RustError> {
+    update.validate()?;
+
+    let user_id = get_current_user_id().await?;
+    let client = client();
+
+    let res = client
+        .put(format!("{VRCHAT_API_BASE_URL}/1/users/{user_id}"))
+        .json(update)
+        .send_with_reauth()
+        .await?;
+
+    read_raw_response(res).await
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn update_current_user_profile(update: CurrentUserUpdate) -> Result<String, RustError> {
+    debug!("Call update_current_user_profile {:?}", update);
+
+    update_current_user_inner(&update).await
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn set_current_user_status(
+    status: UserStatus,
+    status_description: Option<String>,
+) -> Result<String, RustError> {
+    debug!(
+        "Call set_current_user_status {:?} {:?}",
+        status, status_description
+    );
+
+    let update = CurrentUserUpdate {
+        status: Some(status),
+        status_description,
+        ..Default::default()
+    };
+
+    update_current_user_inner(&update).await
+}
@@ -0,0 +1,510 @@
+use crate::commands::RustError;
+use crate::headless::AppDirs;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use keyring::Entry;
+use log::{error, warn};
+use reqwest_cookie_store::CookieStore;
+use std::path::{Path, PathBuf};
+
+const VAULT_DIR: &str = "cookies";
+/// ファイル保存用の鍵のキーリング上の名前。ユーザーIDの項目と重ならない
+const KEY_ENTRY: &str = "cookie-vault-key";
+/// キーリングが使えないときに鍵を置くファイル。以前の版もここに鍵を置いていた
+const KEY_FILE: &str = "vault.key";
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+
+/// Cookieと鍵を入れるキーリング。テストでは使えないキーリングに差し替える
+trait Keyring {
+    fn get_password(&self, name: &str) -> keyring::Result<String>;
+    fn set_password(&self, name: &str, password: &str) -> keyring::Result<()>;
+    fn get_secret(&self, name: &str) -> keyring::Result<Vec<u8>>;
+    fn set_secret(&self, name: &str, secret: &[u8]) -> keyring::Result<()>;
+    fn delete(&self, name: &str) -> keyring::Result<()>;
+}
+
+/// OSのキーリング。アプリのIDをサービス名にする
+struct OsKeyring<'a>(&'a str);
+
+impl Keyring for OsKeyring<'_> {
+    fn get_password(&self, name: &str) -> keyring::Result<String> {
+        Entry::new(self.0, name)?.get_password()
+    }
+
+    fn set_password(&self, name: &str, password: &str) -> keyring::Result<()> {
+        Entry::new(self.0, name)?.set_password(password)
+    }
+
+    fn get_secret(&self, name: &str) -> keyring::Result<Vec<u8>> {
+        Entry::new(self.0, name)?.get_secret()
+    }
+
+    fn set_secret(&self, name: &str, secret: &[u8]) -> keyring::Result<()> {
+        Entry::new(self.0, name)?.set_secret(secret)
+    }
+
+    fn delete(&self, name: &str) -> keyring::Result<()> {
+        Entry::new(self.0, name)?.delete_credential()
+    }
+}
+
+/// 鍵ファイルの中身をOSで保護する。WindowsではDPAPIで今のユーザーにしか戻せないようにする。
+/// ほかのOSではそのまま書き、所有者だけが読めるパーミッション（0600）で守る
+#[cfg(windows)]
+mod protection {
+    use crate::commands::RustError;
+    use std::ffi::c_void;
+
+    #[repr(C)]
+    struct DataBlob {
+        len: u32,
+        data: *mut u8,
+    }
+
+    /// 確認のダイアログを出さない
+    const CRYPTPROTECT_UI_FORBIDDEN: u32 = 0x1;
+
+    #[link(name = "crypt32")]
+    extern "system" {
+        fn CryptProtectData(
+            data_in: *const DataBlob,
+            description: *const u16,
+            entropy: *const DataBlob,
+            reserved: *mut c_void,
+            prompt: *mut c_void,
+            flags: u32,
+            data_out: *mut DataBlob,
+        ) -> i32;
+        fn CryptUnprotectData(
+            data_in: *const DataBlob,
+            description: *mut *mut u16,
+            entropy: *const DataBlob,
+            reserved: *mut c_void,
+            prompt: *mut c_void,
+            flags: u32,
+            data_out: *mut DataBlob,
+        ) -> i32;
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn LocalFree(memory: *mut c_void) -> *mut c_void;
+    }
+
+    fn transform(bytes: &[u8], protect: bool) -> Result<Vec<u8>, RustError> {
+        let input = DataBlob {
+            len: u32::try_from(bytes.len())?,
+            data: bytes.as_ptr() as *mut u8,
+        };
+        let mut output = DataBlob {
+            len: 0,
+            data: std::ptr::null_mut(),
+        };
+        // SAFETY: 入力は呼び出しの間だけ有効なスライスを指し、DPAPIは書き換えない。
+        // 出力はDPAPIが確保したメモリなので、コピーしてから `LocalFree` で解放する
+        unsafe {
+            let null: *mut c_void = std::ptr::null_mut();
+            let flags = CRYPTPROTECT_UI_FORBIDDEN;
+            let succeeded = if protect {
+                CryptProtectData(&input, std::ptr::null(), std::ptr::null(), null, null, flags, &mut output)
+            } else {
+                CryptUnprotectData(&input, std::ptr::null_mut(), std::ptr::null(), null, null, flags, &mut output)
+            };
+            if succeeded == 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            let result = std::slice::from_raw_parts(output.data, output.len as usize).to_vec();
+            LocalFree(output.data.cast());
+            Ok(result)
+        }
+    }
+
+    pub(super) fn protect(bytes: &[u8]) -> Result<Vec<u8>, RustError> {
+        transform(bytes, true)
+    }
+
+    pub(super) fn unprotect(bytes: &[u8]) -> Result<Vec<u8>, RustError> {
+        transform(bytes, false)
+    }
+}
+
+#[cfg(not(windows))]
+mod protection {
+    use crate::commands::RustError;
+
+    pub(super) fn protect(bytes: &[u8]) -> Result<Vec<u8>, RustError> {
+        Ok(bytes.to_vec())
+    }
+
+    pub(super) fn unprotect(bytes: &[u8]) -> Result<Vec<u8>, RustError> {
+        Ok(bytes.to_vec())
+    }
+}
+
+fn write_private(path: &Path, bytes: &[u8]) -> Result<(), RustError> {
+    // 書き込み途中で落ちても壊れないよう、一時ファイルに書いてから置き換える
+    let temp = path.with_extension("tmp");
+    std::fs::write(&temp, bytes)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&temp, std::fs::Permissions::from_mode(0o600))?;
+    }
+    std::fs::rename(&temp, path)?;
+    Ok(())
+}
+
+/// 鍵ファイルの鍵。保護する前の鍵をそのまま置いていた以前の版のファイルも読む
+fn read_key_file(path: &Path) -> Option<Key<Aes256Gcm>> {
+    let bytes = std::fs::read(path).ok()?;
+    let key = protection::unprotect(&bytes)
+        .ok()
+        .filter(|key| key.len() == KEY_LENGTH)
+        .or_else(|| (bytes.len() == KEY_LENGTH).then_some(bytes))?;
+    Some(*Key::<Aes256Gcm>::from_slice(&key))
+}
+
+fn write_key_file(path: &Path, key: &Key<Aes256Gcm>) -> Result<(), RustError> {
+    write_private(path, &protection::protect(key)?)
+}
+
+fn remove_key_file(path: &Path) {
+    if let Err(e) = std::fs::remove_file(path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!("Failed to remove cookie vault key file: {}", e);
+        }
+    }
+}
+
+/// Cookieの保存先。キーリングと、キーリングに入らないときの暗号化したファイル
+struct Vault<K> {
+    keyring: K,
+    dir: PathBuf,
+}
+
+impl<'a> Vault<OsKeyring<'a>> {
+    fn open(app: &'a impl AppDirs) -> Result<Self, RustError> {
+        let dir = app.app_local_data_dir()?.join(VAULT_DIR);
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            keyring: OsKeyring(app.identifier()),
+            dir,
+        })
+    }
+}
+
+impl<K: Keyring> Vault<K> {
+    fn jar_path(&self, user_id: &str) -> PathBuf {
+        self.dir.join(format!("{user_id}.jar"))
+    }
+
+    /// ファイル保存用の鍵。キーリングに置けば、バックアップや同期フォルダ経由でファイルだけ漏れても読めない。
+    /// キーリングが使えないときは、OSで保護した鍵ファイルに置く。使えるようになったらキーリングへ移す
+    fn key(&self) -> Result<Key<Aes256Gcm>, RustError> {
+        let keyring_available = match self.keyring.get_secret(KEY_ENTRY) {
+            Ok(bytes) if bytes.len() == KEY_LENGTH => return Ok(*Key::<Aes256Gcm>::from_slice(&bytes)),
+            Ok(_) => {
+                warn!("Cookie vault key is broken, creating a new one");
+                true
+            }
+            Err(keyring::Error::NoEntry) => true,
+            Err(e) => {
+                warn!("Keyring is unavailable, using the cookie vault key file: {}", e);
+                false
+            }
+        };
+
+        let key_path = self.dir.join(KEY_FILE);
+        let from_file = read_key_file(&key_path);
+        let key = from_file.unwrap_or_else(|| Aes256Gcm::generate_key(OsRng));
+        if keyring_available {
+            match self.keyring.set_secret(KEY_ENTRY, &key) {
+                Ok(()) => {
+                    remove_key_file(&key_path);
+                    return Ok(key);
+                }
+                Err(e) => warn!("Failed to save cookie vault key to keyring, using a key file: {}", e),
+            }
+        }
+        if from_file.is_none() {
+            write_key_file(&key_path, &key)?;
+        }
+        Ok(key)
+    }
+
+    fn save_file(&self, user_id: &str, json: &str) -> Result<(), RustError> {
+        let bytes = encrypt_jar(&self.key()?, user_id, json)?;
+        write_private(&self.jar_path(user_id), &bytes)
+    }
+
+    /// キーリングが一時的に使えなかったときは鍵ファイルの鍵で暗号化されているので、そちらでも試す
+    fn decrypt(&self, user_id: &str, bytes: &[u8]) -> Result<CookieStore, RustError> {
+        let store = parse_jar(&self.key()?, user_id, bytes);
+        if store.iter_any().next().is_some() {
+            return Ok(store);
+        }
+        Ok(read_key_file(&self.dir.join(KEY_FILE))
+            .map(|key| parse_jar(&key, user_id, bytes))
+            .unwrap_or(store))
+    }
+
+    fn save(&self, user_id: &str, store: &CookieStore) -> Result<(), RustError> {
+        let json = serde_json::to_string(store)?;
+        match self.keyring.set_password(user_id, &json) {
+            Ok(()) => {
+                // キーリングの方が新しくなったので、古いファイルは消す
+                let _ = std::fs::remove_file(self.jar_path(user_id));
+                Ok(())
+            }
+            Err(e) => {
+                warn!("Failed to save cookies to keyring, falling back to file: {}", e);
+                self.save_file(user_id, &json)
+            }
+        }
+    }
+
+    /// ファイルが残っているのはキーリングへの保存に失敗したときだけなので、ファイルを優先する
+    fn load(&self, user_id: &str) -> Result<CookieStore, RustError> {
+        let from_file = match std::fs::read(self.jar_path(user_id)) {
+            Ok(bytes) => Some(self.decrypt(user_id, &bytes)?),
+            Err(_) => None,
+        };
+        if let Some(store) = from_file.as_ref().filter(|store| store.iter_any().next().is_some()) {
+            return Ok(store.clone());
+        }
+
+        // 読めなかったファイルしか無ければ、空のセッションとして再ログインさせる
+        let json = match self.keyring.get_password(user_id) {
+            Ok(json) => json,
+            Err(keyring::Error::NoEntry) => return from_file.ok_or_else(|| "errors.accountSessionNotFound".into()),
+            Err(e) => {
+                error!("Failed to read cookies from keyring: {}", e);
+                return from_file.ok_or_else(|| "errors.accountSessionNotFound".into());
+            }
+        };
+        match parse_cookies(&json) {
+            Some(store) => Ok(store),
+            None => {
+                // 読めないデータを残しておくと毎回失敗するので消す。再ログインが必要になる
+                warn!("Stored cookies are corrupted, discarding them");
+                let _ = self.keyring.delete(user_id);
+                Ok(CookieStore::default())
+            }
+        }
+    }
+
+    fn delete(&self, user_id: &str) -> Result<(), RustError> {
+        let _ = std::fs::remove_file(self.jar_path(user_id));
+        match self.keyring.delete(user_id) {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+fn encrypt_jar(key: &Key<Aes256Gcm>, user_id: &str, json: &str) -> Result<Vec<u8>, RustError> {
+    let cipher = Aes256Gcm::new(key);
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let payload = Payload {
+        msg: json.as_bytes(),
+        aad: user_id.as_bytes(),
+    };
+    let encrypted = cipher.encrypt(&nonce, payload).map_err(|_| "Failed to encrypt cookies")?;
+
+    let mut bytes = nonce.to_vec();
+    bytes.extend_from_slice(&encrypted);
+    Ok(bytes)
+}
+
+fn decrypt_jar(key: &Key<Aes256Gcm>, user_id: &str, bytes: &[u8]) -> Result<String, RustError> {
+    if bytes.len() < NONCE_LENGTH {
+        return Err("Stored cookie file is truncated".into());
+    }
+
+    let cipher = Aes256Gcm::new(key);
+    let (nonce, encrypted) = bytes.split_at(NONCE_LENGTH);
+    let payload = Payload {
+        msg: encrypted,
+        aad: user_id.as_bytes(),
+    };
+    let decrypted = cipher
+        .decrypt(Nonce::from_slice(nonce), payload)
+        .map_err(|_| "Failed to decrypt stored cookies")?;
+    Ok(String::from_utf8(decrypted)?)
+}
+
+/// 保存されたJSONからCookieストアを復元する。一部のCookieが壊れていても読めたものだけで復元する
+fn parse_cookies(json: &str) -> Option<CookieStore> {
+    if let Ok(store) = serde_json::from_str(json) {
+        return Some(store);
+    }
+
+    let cookies: Vec<serde_json::Value> = serde_json::from_str(json).ok()?;
+    let total = cookies.len();
+    let valid: Vec<serde_json::Value> = cookies
+        .into_iter()
+        .filter(|cookie| serde_json::from_value::<CookieStore>(serde_json::json!([cookie])).is_ok())
+        .collect();
+    warn!("Recovered {} of {} stored cookies", valid.len(), total);
+    serde_json::from_value(serde_json::Value::Array(valid)).ok()
+}
+
+/// 暗号化して保存したファイルからCookieストアを復元する。
+/// 途中で切れている・壊れている・別の鍵で暗号化されているときは空のストアにする。再ログインが必要になる
+fn parse_jar(key: &Key<Aes256Gcm>, user_id: &str, bytes: &[u8]) -> CookieStore {
+    let json = match decrypt_jar(key, user_id, bytes) {
+        Ok(json) => json,
+        Err(e) => {
+            warn!("Discarding unreadable cookie file: {}", e.message());
+            return CookieStore::default();
+        }
+    };
+    parse_cookies(&json).unwrap_or_else(|| {
+        warn!("Discarding corrupted cookie file");
+        CookieStore::default()
+    })
+}
+
+/// Cookieストアを保存する。キーリングに保存できなければ暗号化したファイルに保存する
+pub(crate) fn save_jar(app: &impl AppDirs, user_id: &str, store: &CookieStore) -> Result<(), RustError> {
+    Vault::open(app)?.save(user_id, store)
+}
+
+/// 保存されたCookieストアを読み込む
+pub(crate) fn load_jar(app: &impl AppDirs, user_id: &str) -> Result<CookieStore, RustError> {
+    Vault::open(app)?.load(user_id)
+}
+
+pub(crate) fn delete_jar(app: &impl AppDirs, user_id: &str) -> Result<(), RustError> {
+    Vault::open(app)?.delete(user_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    const USER_ID: &str = "usr_12345678-abcd-ef01-2345-6789abcdef01";
+
+    fn sample_json() -> String {
+        let url = reqwest::Url::parse("https://api.vrchat.cloud/api/1/auth").unwrap();
+        let mut store = CookieStore::default();
+        store.parse("auth=authcookie_0123; Path=/; Max-Age=3600", &url).unwrap();
+        serde_json::to_string(&store).unwrap()
+    }
+
+    fn auth_cookie(store: &CookieStore) -> Option<String> {
+        store
+            .get("api.vrchat.cloud", "/", "auth")
+            .map(|cookie| cookie.value().to_string())
+    }
+
+    #[test]
+    fn encrypted_jar_round_trips() {
+        let key = Aes256Gcm::generate_key(OsRng);
+        let bytes = encrypt_jar(&key, USER_ID, &sample_json()).unwrap();
+
+        let store = parse_jar(&key, USER_ID, &bytes);
+        assert_eq!(auth_cookie(&store).as_deref(), Some("authcookie_0123"));
+    }
+
+    #[test]
+    fn truncated_jar_becomes_empty_store() {
+        let key = Aes256Gcm::generate_key(OsRng);
+        let bytes = encrypt_jar(&key, USER_ID, &sample_json()).unwrap();
+
+        // ノンスより短いものと、暗号文の途中で切れたもの
+        for truncated in [&bytes[..NONCE_LENGTH - 1], &bytes[..bytes.len() - 4]] {
+            assert!(parse_jar(&key, USER_ID, truncated).iter_any().next().is_none());
+        }
+    }
+
+    #[test]
+    fn garbage_jar_becomes_empty_store() {
+        let key = Aes256Gcm::generate_key(OsRng);
+        let garbage: Vec<u8> = (0..64).map(|i| (i * 37) as u8).collect();
+
+        assert!(parse_jar(&key, USER_ID, &garbage).iter_any().next().is_none());
+        assert!(parse_jar(&key, USER_ID, &[]).iter_any().next().is_none());
+    }
+
+    #[test]
+    fn jar_encrypted_with_another_key_or_user_becomes_empty_store() {
+        let key = Aes256Gcm::generate_key(OsRng);
+        let other_key = Aes256Gcm::generate_key(OsRng);
+        let bytes = encrypt_jar(&key, USER_ID, &sample_json()).unwrap();
+
+        assert!(parse_jar(&other_key, USER_ID, &bytes).iter_any().next().is_none());
+        // 別のアカウントのファイルを差し替えても読まない
+        assert!(parse_jar(&key, "usr_other", &bytes).iter_any().next().is_none());
+    }
+
+    #[test]
+    fn partially_broken_cookie_list_keeps_valid_cookies() {
+        let mut cookies: Vec<serde_json::Value> = serde_json::from_str(&sample_json()).unwrap();
+        cookies.push(serde_json::json!({"broken": true}));
+        let json = serde_json::to_string(&cookies).unwrap();
+
+        let store = parse_cookies(&json).unwrap();
+        assert_eq!(auth_cookie(&store).as_deref(), Some("authcookie_0123"));
+        assert!(parse_cookies("not json").is_none());
+    }
+
+    /// ロックされたSecret Serviceなど、何を頼んでも失敗するキーリング
+    struct BrokenKeyring;
+
+    fn unavailable() -> keyring::Error {
+        keyring::Error::NoStorageAccess("keyring is locked".into())
+    }
+
+    impl Keyring for BrokenKeyring {
+        fn get_password(&self, _: &str) -> keyring::Result<String> {
+            Err(unavailable())
+        }
+
+        fn set_password(&self, _: &str, _: &str) -> keyring::Result<()> {
+            Err(unavailable())
+        }
+
+        fn get_secret(&self, _: &str) -> keyring::Result<Vec<u8>> {
+            Err(unavailable())
+        }
+
+        fn set_secret(&self, _: &str, _: &[u8]) -> keyring::Result<()> {
+            Err(unavailable())
+        }
+
+        fn delete(&self, _: &str) -> keyring::Result<()> {
+            Err(unavailable())
+        }
+    }
+
+    #[test]
+    fn jar_round_trips_without_keyring() {
+        let dir = TempDir::new("cookie-vault");
+        let vault = Vault {
+            keyring: BrokenKeyring,
+            dir: dir.to_path_buf(),
+        };
+        let store: CookieStore = serde_json::from_str(&sample_json()).unwrap();
+
+        vault.save(USER_ID, &store).unwrap();
+        assert!(dir.join(KEY_FILE).exists());
+        assert!(vault.jar_path(USER_ID).exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(dir.join(KEY_FILE)).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // 次に起動したときも同じ鍵ファイルで読める
+        let reopened = Vault {
+            keyring: BrokenKeyring,
+            dir: dir.to_path_buf(),
+        };
+        let loaded = reopened.load(USER_ID).unwrap();
+        assert_eq!(auth_cookie(&loaded).as_deref(), Some("authcookie_0123"));
+    }
+}
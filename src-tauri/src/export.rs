@@ -0,0 +1,572 @@
+use crate::accounts::current_session;
+use crate::commands::{get_world_by_id, RustError};
+use crate::friends::fetch_all_friends;
+use chrono::Local;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    Markdown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportColumn {
+    WorldName,
+    WorldId,
+    InstanceId,
+    /// Public / Friends+ / Invite などのインスタンスの種類
+    InstanceType,
+    Region,
+    /// インスタンスIDの `~` 以降のタグ
+    InstanceTags,
+    FriendCount,
+    FriendName,
+    FriendId,
+    Status,
+    StatusDescription,
+    Platform,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// 出力する列。並び順もこの順になる
+    pub columns: Vec<ExportColumn>,
+    pub include_offline: bool,
+    /// インスタンスごとに1行にまとめる。フレンドの列は1つのセルに並べる
+    pub group_by_instance: bool,
+    /// 出力先のファイル。未指定ならダウンロードフォルダに日時付きのファイル名で保存する
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportSummary {
+    pub path: String,
+    pub rows: u32,
+}
+
+impl ExportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+            ExportFormat::Markdown => "md",
+        }
+    }
+}
+
+impl ExportColumn {
+    fn header(self) -> &'static str {
+        match self {
+            ExportColumn::WorldName => "World",
+            ExportColumn::WorldId => "World ID",
+            ExportColumn::InstanceId => "Instance",
+            ExportColumn::InstanceType => "Type",
+            ExportColumn::Region => "Region",
+            ExportColumn::InstanceTags => "Tags",
+            ExportColumn::FriendCount => "Friends",
+            ExportColumn::FriendName => "Name",
+            ExportColumn::FriendId => "User ID",
+            ExportColumn::Status => "Status",
+            ExportColumn::StatusDescription => "Status Message",
+            ExportColumn::Platform => "Platform",
+        }
+    }
+
+    /// JSONのキー。シリアライズした名前と同じにする
+    fn key(self) -> String {
+        serde_json::to_value(self)
+            .ok()
+            .and_then(|value| value.as_str().map(str::to_string))
+            .unwrap_or_default()
+    }
+
+    fn is_friend_column(self) -> bool {
+        matches!(
+            self,
+            ExportColumn::FriendName
+                | ExportColumn::FriendId
+                | ExportColumn::Status
+                | ExportColumn::StatusDescription
+                | ExportColumn::Platform
+        )
+    }
+}
+
+struct Friend {
+    id: String,
+    display_name: String,
+    status: String,
+    status_description: String,
+    platform: String,
+}
+
+pub(crate) struct Instance {
+    location: String,
+    world_id: String,
+    world_name: String,
+    instance_id: String,
+    tags: Vec<String>,
+    friends: Vec<Friend>,
+}
+
+impl Instance {
+    /// ローカルAPIで返すJSON
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        let friends: Vec<serde_json::Value> = self
+            .friends
+            .iter()
+            .map(|friend| {
+                serde_json::json!({
+                    "id": friend.id,
+                    "displayName": friend.display_name,
+                    "status": friend.status,
+                    "statusDescription": friend.status_description,
+                    "platform": friend.platform,
+                })
+            })
+            .collect();
+        serde_json::json!({
+            "location": self.location,
+            "worldId": self.world_id,
+            "worldName": self.world_name,
+            "instanceId": self.instance_id,
+            "type": instance_type(self),
+            "region": region(self),
+            "tags": self.tags,
+            "friends": friends,
+        })
+    }
+}
+
+/// `wrld_xxx:12345~hidden(usr_xxx)~region(jp)` のインスタンス部分からIDとタグを取り出す
+pub(crate) fn split_instance(instance: &str) -> (String, Vec<String>) {
+    let mut parts = instance.split('~');
+    let id = parts.next().unwrap_or_default().to_string();
+    (id, parts.map(str::to_string).collect())
+}
+
+fn tag_value<'a>(tags: &'a [String], name: &str) -> Option<&'a str> {
+    tags.iter().find_map(|tag| tag.strip_prefix(name)?.strip_prefix('(')?.strip_suffix(')'))
+}
+
+/// インスタンスIDとタグから Public / Friends+ などの種類を求める
+pub(crate) fn instance_type_label(instance_id: &str, tags: &[String]) -> String {
+    if instance_id.is_empty() {
+        return String::new();
+    }
+    let has_tag = |name: &str| tags.iter().any(|tag| tag == name || tag.starts_with(&format!("{name}(")));
+    let label = if has_tag("group") {
+        match tag_value(tags, "groupAccessType") {
+            Some("public") => "Group Public",
+            Some("plus") => "Group+",
+            _ => "Group",
+        }
+    } else if has_tag("hidden") {
+        "Friends+"
+    } else if has_tag("friends") {
+        "Friends"
+    } else if has_tag("private") {
+        if has_tag("canRequestInvite") {
+            "Invite+"
+        } else {
+            "Invite"
+        }
+    } else {
+        "Public"
+    };
+    label.to_string()
+}
+
+fn instance_type(instance: &Instance) -> String {
+    instance_type_label(&instance.instance_id, &instance.tags)
+}
+
+pub(crate) fn region_label(instance_id: &str, tags: &[String]) -> String {
+    if instance_id.is_empty() {
+        return String::new();
+    }
+    // regionタグが無いインスタンスはUS West
+    tag_value(tags, "region").unwrap_or("us").to_string()
+}
+
+fn region(instance: &Instance) -> String {
+    region_label(&instance.instance_id, &instance.tags)
+}
+
+fn platform_label(platform: &str) -> String {
+    match platform {
+        "standalonewindows" => "PC".to_string(),
+        "android" => "Android".to_string(),
+        "ios" => "iOS".to_string(),
+        "web" => "Web".to_string(),
+        _ => platform.to_string(),
+    }
+}
+
+fn friend_value(friend: &Friend, column: ExportColumn) -> String {
+    match column {
+        ExportColumn::FriendName => friend.display_name.clone(),
+        ExportColumn::FriendId => friend.id.clone(),
+        ExportColumn::Status => friend.status.clone(),
+        ExportColumn::StatusDescription => friend.status_description.clone(),
+        ExportColumn::Platform => friend.platform.clone(),
+        _ => String::new(),
+    }
+}
+
+fn instance_value(instance: &Instance, column: ExportColumn) -> String {
+    match column {
+        ExportColumn::WorldName => instance.world_name.clone(),
+        ExportColumn::WorldId => instance.world_id.clone(),
+        ExportColumn::InstanceId => instance.instance_id.clone(),
+        ExportColumn::InstanceType => instance_type(instance),
+        ExportColumn::Region => region(instance),
+        ExportColumn::InstanceTags => instance.tags.join("~"),
+        ExportColumn::FriendCount => instance.friends.len().to_string(),
+        _ => String::new(),
+    }
+}
+
+/// 1行分の値。インスタンスごとにまとめた場合、フレンドの列は複数の値を持つ
+type Row = Vec<Vec<String>>;
+
+fn build_rows(instances: &[Instance], columns: &[ExportColumn], group_by_instance: bool) -> Vec<Row> {
+    let mut rows = Vec::new();
+    for instance in instances {
+        if group_by_instance {
+            rows.push(
+                columns
+                    .iter()
+                    .map(|&column| {
+                        if column.is_friend_column() {
+                            instance.friends.iter().map(|friend| friend_value(friend, column)).collect()
+                        } else {
+                            vec![instance_value(instance, column)]
+                        }
+                    })
+                    .collect(),
+            );
+            continue;
+        }
+        for friend in &instance.friends {
+            rows.push(
+                columns
+                    .iter()
+                    .map(|&column| {
+                        if column.is_friend_column() {
+                            vec![friend_value(friend, column)]
+                        } else {
+                            vec![instance_value(instance, column)]
+                        }
+                    })
+                    .collect(),
+            );
+        }
+    }
+    rows
+}
+
+/// 表計算ソフトが数式として解釈する先頭の文字
+const FORMULA_PREFIXES: [char; 6] = ['=', '+', '-', '@', '\t', '\r'];
+
+fn csv_field(value: &str) -> String {
+    // ステータスメッセージなどに数式を仕込まれても、開いたときに実行されないよう文字列にする
+    let value = if value.starts_with(FORMULA_PREFIXES) {
+        format!("'{value}")
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn markdown_cell(value: &str) -> String {
+    value.replace('|', "\\|").replace(['\r', '\n'], " ")
+}
+
+fn render_csv(columns: &[ExportColumn], rows: &[Row]) -> String {
+    let mut out = columns.iter().map(|column| csv_field(column.header())).collect::<Vec<_>>().join(",");
+    out.push_str("\r\n");
+    for row in rows {
+        let line: Vec<String> = row.iter().map(|values| csv_field(&values.join("; "))).collect();
+        out.push_str(&line.join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+fn render_markdown(columns: &[ExportColumn], rows: &[Row]) -> String {
+    let headers: Vec<&str> = columns.iter().map(|column| column.header()).collect();
+    let mut out = format!("| {} |\n", headers.join(" | "));
+    out.push_str(&format!("|{}\n", " --- |".repeat(columns.len())));
+    for row in rows {
+        let cells: Vec<String> = row.iter().map(|values| markdown_cell(&values.join(", "))).collect();
+        out.push_str(&format!("| {} |\n", cells.join(" | ")));
+    }
+    out
+}
+
+fn render_json(columns: &[ExportColumn], rows: &[Row], group_by_instance: bool) -> Result<String, RustError> {
+    let rows: Vec<serde_json::Map<String, serde_json::Value>> = rows
+        .iter()
+        .map(|row| {
+            columns
+                .iter()
+                .zip(row)
+                .map(|(column, values)| {
+                    let value = if group_by_instance && column.is_friend_column() {
+                        serde_json::json!(values)
+                    } else {
+                        serde_json::json!(values.first().cloned().unwrap_or_default())
+                    };
+                    (column.key(), value)
+                })
+                .collect()
+        })
+        .collect();
+    Ok(serde_json::to_string_pretty(&rows)?)
+}
+
+/// `get_world_by_id` のキャッシュを使ってワールド名を引く
+async fn world_name(world_id: &str) -> String {
+    get_world_by_id(world_id)
+        .await
+        .ok()
+        .and_then(|json| serde_json::from_str::<serde_json::Value>(&json).ok())
+        .and_then(|world| world["name"].as_str().map(str::to_string))
+        .unwrap_or_else(|| world_id.to_string())
+}
+
+/// フレンド一覧を取得し、フロントエンドのインスタンス一覧と同じようにロケーションごとにまとめる
+pub(crate) async fn collect_instances(include_offline: bool) -> Result<Vec<Instance>, RustError> {
+    let session = current_session();
+    let mut friends = fetch_all_friends(&session, false).await?;
+    if include_offline {
+        friends.extend(fetch_all_friends(&session, true).await?);
+    }
+
+    let mut instances: Vec<Instance> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    let mut world_names: HashMap<String, String> = HashMap::new();
+    for friend in &friends {
+        let status = friend["status"].as_str().unwrap_or_default();
+        let mut location = friend["location"].as_str().unwrap_or("offline").to_string();
+        if location == "offline" && status != "offline" {
+            location = "web_or_mobile".to_string();
+        }
+
+        let position = match index.get(&location) {
+            Some(&position) => position,
+            None => {
+                let (world_id, instance) = location.split_once(':').unwrap_or((&location, ""));
+                let (instance_id, tags) = split_instance(instance);
+                if !world_names.contains_key(world_id) {
+                    world_names.insert(world_id.to_string(), world_name(world_id).await);
+                }
+                instances.push(Instance {
+                    location: location.clone(),
+                    world_id: world_id.to_string(),
+                    world_name: world_names[world_id].clone(),
+                    instance_id,
+                    tags,
+                    friends: Vec::new(),
+                });
+                index.insert(location.clone(), instances.len() - 1);
+                instances.len() - 1
+            }
+        };
+        instances[position].friends.push(Friend {
+            id: friend["id"].as_str().unwrap_or_default().to_string(),
+            display_name: friend["displayName"].as_str().unwrap_or_default().to_string(),
+            status: status.to_string(),
+            status_description: friend["statusDescription"].as_str().unwrap_or_default().to_string(),
+            platform: platform_label(friend["platform"].as_str().unwrap_or_default()),
+        });
+    }
+
+    // 人数の多いインスタンスから並べ、オフラインなどの場所は最後にする
+    instances.sort_by(|a, b| {
+        a.instance_id
+            .is_empty()
+            .cmp(&b.instance_id.is_empty())
+            .then(b.friends.len().cmp(&a.friends.len()))
+            .then(a.location.cmp(&b.location))
+    });
+    Ok(instances)
+}
+
+fn default_path(format: ExportFormat) -> Result<PathBuf, RustError> {
+    let dir = dirs::download_dir()
+        .or_else(dirs::home_dir)
+        .ok_or("errors.exportPathNotFound")?;
+    let file_name = format!("vsm-friends-{}.{}", Local::now().format("%Y%m%d-%H%M%S"), format.extension());
+    Ok(dir.join(file_name))
+}
+
+/// フレンドとフレンドがいるインスタンスの一覧をCSV・JSON・Markdownの表でファイルに書き出す
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn export_friend_instances(options: ExportOptions) -> Result<ExportSummary, RustError> {
+    debug!("Call export_friend_instances {:?}", options);
+
+    if options.columns.is_empty() {
+        return Err("errors.exportNoColumns".into());
+    }
+
+    let instances = collect_instances(options.include_offline).await?;
+    let rows = build_rows(&instances, &options.columns, options.group_by_instance);
+    let content = match options.format {
+        ExportFormat::Csv => render_csv(&options.columns, &rows),
+        ExportFormat::Json => render_json(&options.columns, &rows, options.group_by_instance)?,
+        ExportFormat::Markdown => render_markdown(&options.columns, &rows),
+    };
+
+    let path = match options.path {
+        Some(path) if !path.trim().is_empty() => PathBuf::from(path),
+        _ => default_path(options.format)?,
+    };
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    // Excelで文字化けしないようにCSVはBOM付きにする
+    let bom = if options.format == ExportFormat::Csv { "\u{feff}" } else { "" };
+    std::fs::write(&path, format!("{bom}{content}"))?;
+    info!("Exported {} rows to {}", rows.len(), path.display());
+
+    Ok(ExportSummary {
+        path: path.to_string_lossy().to_string(),
+        rows: rows.len() as u32,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    fn sample_instance() -> Instance {
+        let friend = |id: &str, name: &str, description: &str| Friend {
+            id: id.to_string(),
+            display_name: name.to_string(),
+            status: "active".to_string(),
+            status_description: description.to_string(),
+            platform: "standalonewindows".to_string(),
+        };
+        Instance {
+            location: "wrld_1:12345~hidden(usr_x)~region(jp)".to_string(),
+            world_id: "wrld_1".to_string(),
+            world_name: "Home, Sweet | Home".to_string(),
+            instance_id: "12345".to_string(),
+            tags: tags(&["hidden(usr_x)", "region(jp)"]),
+            friends: vec![friend("usr_a", "Alice", "=HYPERLINK(\"x\")"), friend("usr_b", "Bob", "")],
+        }
+    }
+
+    #[test]
+    fn csv_field_quotes_only_when_needed() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn csv_field_neutralizes_formulas() {
+        assert_eq!(csv_field("=1+1"), "'=1+1");
+        assert_eq!(csv_field("+81"), "'+81");
+        assert_eq!(csv_field("-2"), "'-2");
+        assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(csv_field("\tcmd"), "'\tcmd");
+        assert_eq!(csv_field("\rcmd"), "\"'\rcmd\"");
+        assert_eq!(csv_field("=A1,B1"), "\"'=A1,B1\"");
+        // 途中の記号はそのまま
+        assert_eq!(csv_field("a=b"), "a=b");
+    }
+
+    #[test]
+    fn markdown_cell_escapes_pipes_and_newlines() {
+        assert_eq!(markdown_cell("a|b"), "a\\|b");
+        assert_eq!(markdown_cell("one\r\ntwo"), "one  two");
+    }
+
+    #[test]
+    fn instance_type_label_follows_tags() {
+        assert_eq!(instance_type_label("", &[]), "");
+        assert_eq!(instance_type_label("1", &[]), "Public");
+        assert_eq!(instance_type_label("1", &tags(&["hidden(usr_x)"])), "Friends+");
+        assert_eq!(instance_type_label("1", &tags(&["friends(usr_x)"])), "Friends");
+        assert_eq!(instance_type_label("1", &tags(&["private(usr_x)"])), "Invite");
+        assert_eq!(
+            instance_type_label("1", &tags(&["private(usr_x)", "canRequestInvite"])),
+            "Invite+"
+        );
+        assert_eq!(
+            instance_type_label("1", &tags(&["group(grp_x)", "groupAccessType(public)"])),
+            "Group Public"
+        );
+        assert_eq!(
+            instance_type_label("1", &tags(&["group(grp_x)", "groupAccessType(plus)"])),
+            "Group+"
+        );
+        assert_eq!(instance_type_label("1", &tags(&["group(grp_x)"])), "Group");
+    }
+
+    #[test]
+    fn render_csv_writes_one_row_per_friend() {
+        let columns = [ExportColumn::WorldName, ExportColumn::FriendName, ExportColumn::StatusDescription];
+        let rows = build_rows(&[sample_instance()], &columns, false);
+
+        assert_eq!(
+            render_csv(&columns, &rows),
+            "World,Name,Status Message\r\n\
+             \"Home, Sweet | Home\",Alice,\"'=HYPERLINK(\"\"x\"\")\"\r\n\
+             \"Home, Sweet | Home\",Bob,\r\n"
+        );
+    }
+
+    #[test]
+    fn render_markdown_groups_friends_in_one_cell() {
+        let columns = [ExportColumn::WorldName, ExportColumn::InstanceType, ExportColumn::FriendName];
+        let rows = build_rows(&[sample_instance()], &columns, true);
+
+        assert_eq!(
+            render_markdown(&columns, &rows),
+            "| World | Type | Name |\n| --- | --- | --- |\n| Home, Sweet \\| Home | Friends+ | Alice, Bob |\n"
+        );
+    }
+
+    #[test]
+    fn render_json_keeps_friend_columns_as_arrays_when_grouped() {
+        let columns = [ExportColumn::Region, ExportColumn::FriendCount, ExportColumn::FriendId];
+        let grouped = build_rows(&[sample_instance()], &columns, true);
+        let json: serde_json::Value = serde_json::from_str(&render_json(&columns, &grouped, true).unwrap()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([{"region": "jp", "friendCount": "2", "friendId": ["usr_a", "usr_b"]}])
+        );
+
+        let flat = build_rows(&[sample_instance()], &columns, false);
+        let json: serde_json::Value = serde_json::from_str(&render_json(&columns, &flat, false).unwrap()).unwrap();
+        assert_eq!(json[1], serde_json::json!({"region": "jp", "friendCount": "2", "friendId": "usr_b"}));
+    }
+}
@@ -0,0 +1,768 @@
+use crate::accounts::{client, current_session};
+use crate::commands::{get_current_user_json, get_world_by_id, is_login, RustError, VRCHAT_API_BASE_URL};
+use crate::friends::fetch_all_friends;
+use crate::reauth::SendWithReauth;
+use crate::vrchat_log::LogEvent;
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use log::{debug, error};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+/// お気に入りとフレンドのIDを取り直す間隔
+const PROFILE_REFRESH_INTERVAL: Duration = Duration::from_secs(10 * 60);
+const FAVORITES_PAGE_SIZE: usize = 100;
+const NOTIFICATIONS_PAGE_SIZE: usize = 50;
+/// 参加しているグループのイベントの一覧を取り直す間隔
+const CALENDAR_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const CALENDAR_PAGE_SIZE: usize = 100;
+/// グループのイベントが始まるこの時間前から通知する
+const GROUP_EVENT_LEAD_MINUTES: i64 = 10;
+/// インスタンスに入った直後の参加ログは、元からいたプレイヤーのもの
+const JOIN_GRACE_SECONDS: i64 = 10;
+/// 起動時に読み直したログの古い行では通知しない
+const STALE_LOG_SECONDS: i64 = 120;
+
+/// 購読者がいないときは送らずに捨てる
+static SENDER: Lazy<broadcast::Sender<FriendEvent>> = Lazy::new(|| broadcast::channel(64).0);
+
+/// ログのプレイヤーがフレンドかどうかの判定に使う
+static FRIEND_IDS: Lazy<RwLock<HashSet<String>>> = Lazy::new(|| RwLock::new(HashSet::new()));
+
+/// VRChatのログから分かる自分のいるインスタンス
+static MY_INSTANCE: Lazy<Mutex<Option<MyInstance>>> = Lazy::new(|| Mutex::new(None));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub enum FriendEventKind {
+    FriendOnline,
+    /// お気に入りに入れているフレンドがオンラインになった
+    FavoriteOnline,
+    FriendLocation,
+    /// 自分のいるインスタンスにフレンドが来た（VRChatのログから検出）
+    FriendJoinedInstance,
+    InviteReceived,
+    /// 参加しているグループのイベントがまもなく始まる
+    GroupEventStarting,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum FriendEvent {
+    FriendOnline {
+        user_id: String,
+        display_name: String,
+        favorite: bool,
+        location: String,
+        world_name: String,
+    },
+    FriendLocation {
+        user_id: String,
+        display_name: String,
+        location: String,
+        world_name: String,
+        previous_location: String,
+    },
+    FriendJoinedInstance {
+        user_id: String,
+        display_name: String,
+        location: String,
+        world_name: String,
+    },
+    InviteReceived {
+        notification_id: String,
+        sender_id: String,
+        sender_name: String,
+        location: String,
+        world_name: String,
+        message: String,
+    },
+    GroupEventStarting {
+        event_id: String,
+        group_id: String,
+        title: String,
+        starts_at: String,
+    },
+}
+
+struct MyInstance {
+    location: String,
+    world_name: Option<String>,
+    joined_at: NaiveDateTime,
+}
+
+impl FriendEvent {
+    pub(crate) fn matches(&self, kind: FriendEventKind) -> bool {
+        match (self, kind) {
+            (FriendEvent::FriendOnline { .. }, FriendEventKind::FriendOnline) => true,
+            (FriendEvent::FriendOnline { favorite, .. }, FriendEventKind::FavoriteOnline) => *favorite,
+            (FriendEvent::FriendLocation { .. }, FriendEventKind::FriendLocation) => true,
+            (FriendEvent::FriendJoinedInstance { .. }, FriendEventKind::FriendJoinedInstance) => true,
+            (FriendEvent::InviteReceived { .. }, FriendEventKind::InviteReceived) => true,
+            (FriendEvent::GroupEventStarting { .. }, FriendEventKind::GroupEventStarting) => true,
+            _ => false,
+        }
+    }
+
+    pub(crate) fn kind(&self) -> FriendEventKind {
+        match self {
+            FriendEvent::FriendOnline { .. } => FriendEventKind::FriendOnline,
+            FriendEvent::FriendLocation { .. } => FriendEventKind::FriendLocation,
+            FriendEvent::FriendJoinedInstance { .. } => FriendEventKind::FriendJoinedInstance,
+            FriendEvent::InviteReceived { .. } => FriendEventKind::InviteReceived,
+            FriendEvent::GroupEventStarting { .. } => FriendEventKind::GroupEventStarting,
+        }
+    }
+
+    /// テンプレートの `{name}` に入れる値
+    pub(crate) fn variables(&self) -> Vec<(&'static str, String)> {
+        match self {
+            FriendEvent::FriendOnline {
+                user_id,
+                display_name,
+                location,
+                world_name,
+                ..
+            }
+            | FriendEvent::FriendJoinedInstance {
+                user_id,
+                display_name,
+                location,
+                world_name,
+            } => vec![
+                ("userId", user_id.clone()),
+                ("displayName", display_name.clone()),
+                ("location", location.clone()),
+                ("worldName", world_name.clone()),
+            ],
+            FriendEvent::FriendLocation {
+                user_id,
+                display_name,
+                location,
+                world_name,
+                previous_location,
+            } => vec![
+                ("userId", user_id.clone()),
+                ("displayName", display_name.clone()),
+                ("location", location.clone()),
+                ("worldName", world_name.clone()),
+                ("previousLocation", previous_location.clone()),
+            ],
+            FriendEvent::InviteReceived {
+                notification_id,
+                sender_id,
+                sender_name,
+                location,
+                world_name,
+                message,
+            } => vec![
+                ("notificationId", notification_id.clone()),
+                ("userId", sender_id.clone()),
+                ("displayName", sender_name.clone()),
+                ("location", location.clone()),
+                ("worldName", world_name.clone()),
+                ("message", message.clone()),
+            ],
+            FriendEvent::GroupEventStarting {
+                event_id,
+                group_id,
+                title,
+                starts_at,
+            } => vec![
+                ("eventId", event_id.clone()),
+                ("groupId", group_id.clone()),
+                ("title", title.clone()),
+                ("startsAt", starts_at.clone()),
+            ],
+        }
+    }
+}
+
+/// テスト送信でテンプレートに入れる見本の値
+pub(crate) fn sample_variables() -> Vec<(&'static str, String)> {
+    vec![
+        ("userId", "usr_00000000-0000-0000-0000-000000000000".to_string()),
+        ("displayName", "Test Friend".to_string()),
+        ("location", "wrld_00000000-0000-0000-0000-000000000000:12345".to_string()),
+        ("worldName", "Test World".to_string()),
+        ("previousLocation", "offline".to_string()),
+        ("message", "Test message".to_string()),
+        ("notificationId", "not_00000000-0000-0000-0000-000000000000".to_string()),
+        ("eventId", "cal_00000000-0000-0000-0000-000000000000".to_string()),
+        ("groupId", "grp_00000000-0000-0000-0000-000000000000".to_string()),
+        ("title", "Test Event".to_string()),
+        ("startsAt", Utc::now().to_rfc3339()),
+    ]
+}
+
+/// テンプレートの `{name}` を `escape` した値に置き換える。知らない名前はそのまま残す。
+/// テンプレートは先頭から1回だけ走査するので、値の中に `{name}` があっても置き換えない
+pub(crate) fn render_template(template: &str, variables: &[(&str, String)], escape: impl Fn(&str) -> String) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let variable = after.find('}').and_then(|end| {
+            let name = &after[..end];
+            variables.iter().find(|(n, _)| *n == name).map(|(_, value)| (end, value))
+        });
+        match variable {
+            Some((end, value)) => {
+                output.push_str(&escape(value));
+                rest = &after[end + 1..];
+            }
+            None => {
+                output.push('{');
+                rest = after;
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+pub(crate) fn subscribe() -> broadcast::Receiver<FriendEvent> {
+    SENDER.subscribe()
+}
+
+fn publish(event: FriendEvent) {
+    debug!("Friend event: {:?}", event);
+    let _ = SENDER.send(event);
+}
+
+async fn world_name(location: &str) -> String {
+    let world_id = location.split(':').next().unwrap_or_default();
+    match get_world_by_id(world_id).await {
+        Ok(world) => serde_json::from_str::<serde_json::Value>(&world)
+            .ok()
+            .and_then(|world| world["name"].as_str().map(str::to_string))
+            .unwrap_or_default(),
+        Err(_) => String::new(),
+    }
+}
+
+async fn fetch_favorite_friends() -> Result<HashSet<String>, RustError> {
+    let client = client();
+    let mut favorites = HashSet::new();
+    let mut offset = 0;
+    loop {
+        let res = client
+            .get(format!("{VRCHAT_API_BASE_URL}/1/favorites"))
+            .query(&[("type", "friend")])
+            .query(&[("n", FAVORITES_PAGE_SIZE), ("offset", offset)])
+            .send_with_reauth()
+            .await?;
+        if res.status() != reqwest::StatusCode::OK {
+            return Err(res.status().into());
+        }
+        let page: Vec<serde_json::Value> = res.json().await?;
+        favorites.extend(page.iter().filter_map(|f| f["favoriteId"].as_str().map(str::to_string)));
+        if page.len() < FAVORITES_PAGE_SIZE {
+            return Ok(favorites);
+        }
+        offset += FAVORITES_PAGE_SIZE;
+    }
+}
+
+async fn fetch_invites() -> Result<Vec<serde_json::Value>, RustError> {
+    let res = client()
+        .get(format!("{VRCHAT_API_BASE_URL}/1/auth/user/notifications"))
+        .query(&[("type", "invite")])
+        .query(&[("n", NOTIFICATIONS_PAGE_SIZE)])
+        .send_with_reauth()
+        .await?;
+    if res.status() != reqwest::StatusCode::OK {
+        return Err(res.status().into());
+    }
+    Ok(res.json().await?)
+}
+
+async fn fetch_group_events() -> Result<Vec<serde_json::Value>, RustError> {
+    let res = client()
+        .get(format!("{VRCHAT_API_BASE_URL}/1/calendar"))
+        .query(&[("date", Utc::now().to_rfc3339())])
+        .query(&[("n", CALENDAR_PAGE_SIZE)])
+        .send_with_reauth()
+        .await?;
+    if res.status() != reqwest::StatusCode::OK {
+        return Err(res.status().into());
+    }
+    let res_json: serde_json::Value = res.json().await?;
+    Ok(res_json["results"].as_array().cloned().unwrap_or_default())
+}
+
+/// 通知の `details` はオブジェクトのことも、JSON文字列のこともある
+fn notification_details(notification: &serde_json::Value) -> serde_json::Value {
+    match &notification["details"] {
+        serde_json::Value::String(details) => serde_json::from_str(details).unwrap_or_default(),
+        details => details.clone(),
+    }
+}
+
+/// 前回の取得から変わったフレンドの状態。ワールド名は通知するときに取得する
+#[derive(Debug, PartialEq)]
+enum FriendChange {
+    Online {
+        user_id: String,
+        display_name: String,
+        location: String,
+    },
+    Moved {
+        user_id: String,
+        display_name: String,
+        location: String,
+        previous_location: String,
+    },
+}
+
+/// オンラインのフレンドの一覧から、フレンドごとの居場所と前回からの変化を求める。
+/// 前回が無い（最初の取得）ときは基準にするだけで、変化は返さない
+fn friend_changes(
+    previous: Option<&HashMap<String, String>>,
+    friends: &[serde_json::Value],
+) -> (HashMap<String, String>, Vec<FriendChange>) {
+    let mut locations = HashMap::new();
+    let mut changes = Vec::new();
+    for friend in friends {
+        let Some(user_id) = friend["id"].as_str() else {
+            continue;
+        };
+        let display_name = friend["displayName"].as_str().unwrap_or_default().to_string();
+        let location = friend["location"].as_str().unwrap_or("offline").to_string();
+        locations.insert(user_id.to_string(), location.clone());
+        let Some(previous) = previous else {
+            continue;
+        };
+
+        match previous.get(user_id) {
+            None => changes.push(FriendChange::Online {
+                user_id: user_id.to_string(),
+                display_name,
+                location,
+            }),
+            Some(previous) if *previous != location => changes.push(FriendChange::Moved {
+                user_id: user_id.to_string(),
+                display_name,
+                location,
+                previous_location: previous.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    (locations, changes)
+}
+
+#[derive(Default)]
+struct Watcher {
+    /// オンラインのフレンドの居場所
+    locations: HashMap<String, String>,
+    favorites: HashSet<String>,
+    seen_invites: HashSet<String>,
+    seen_group_events: HashSet<String>,
+    profile_refreshed: Option<Instant>,
+    calendar_checked: Option<Instant>,
+    /// 最初の取得は比較の基準にするだけで通知しない
+    initialized: bool,
+}
+
+impl Watcher {
+    async fn refresh_profile(&mut self) -> Result<(), RustError> {
+        if self
+            .profile_refreshed
+            .is_some_and(|at| at.elapsed() < PROFILE_REFRESH_INTERVAL)
+        {
+            return Ok(());
+        }
+        let user = get_current_user_json().await?;
+        let ids = user["friends"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|id| id.as_str().map(str::to_string))
+            .collect();
+        *FRIEND_IDS.write().unwrap() = ids;
+        self.favorites = fetch_favorite_friends().await?;
+        self.profile_refreshed = Some(Instant::now());
+        Ok(())
+    }
+
+    async fn poll_friends(&mut self) -> Result<(), RustError> {
+        let friends = fetch_all_friends(&current_session(), false).await?;
+        let (locations, changes) = friend_changes(self.initialized.then_some(&self.locations), &friends);
+        self.locations = locations;
+        for change in changes {
+            publish(match change {
+                FriendChange::Online {
+                    user_id,
+                    display_name,
+                    location,
+                } => FriendEvent::FriendOnline {
+                    favorite: self.favorites.contains(&user_id),
+                    user_id,
+                    display_name,
+                    world_name: world_name(&location).await,
+                    location,
+                },
+                FriendChange::Moved {
+                    user_id,
+                    display_name,
+                    location,
+                    previous_location,
+                } => FriendEvent::FriendLocation {
+                    user_id,
+                    display_name,
+                    world_name: world_name(&location).await,
+                    location,
+                    previous_location,
+                },
+            });
+        }
+        Ok(())
+    }
+
+    async fn poll_invites(&mut self) -> Result<(), RustError> {
+        for notification in fetch_invites().await? {
+            let Some(id) = notification["id"].as_str() else {
+                continue;
+            };
+            if !self.seen_invites.insert(id.to_string()) || !self.initialized {
+                continue;
+            }
+            let details = notification_details(&notification);
+            publish(FriendEvent::InviteReceived {
+                notification_id: id.to_string(),
+                sender_id: notification["senderUserId"].as_str().unwrap_or_default().to_string(),
+                sender_name: notification["senderUsername"].as_str().unwrap_or_default().to_string(),
+                location: details["worldId"].as_str().unwrap_or_default().to_string(),
+                world_name: details["worldName"].as_str().unwrap_or_default().to_string(),
+                message: details["inviteMessage"].as_str().unwrap_or_default().to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// 始まる少し前のイベントを1回だけ通知する。起動直後でも始まる前なら通知する
+    async fn poll_group_events(&mut self) -> Result<(), RustError> {
+        if self.calendar_checked.is_some_and(|at| at.elapsed() < CALENDAR_INTERVAL) {
+            return Ok(());
+        }
+        self.calendar_checked = Some(Instant::now());
+
+        let now = Utc::now();
+        for event in fetch_group_events().await? {
+            let (Some(id), Some(starts_at)) = (event["id"].as_str(), event["startsAt"].as_str()) else {
+                continue;
+            };
+            let Ok(start) = DateTime::parse_from_rfc3339(starts_at) else {
+                continue;
+            };
+            let minutes = (start.with_timezone(&Utc) - now).num_minutes();
+            // 次の確認までに始まってしまうイベントも含める
+            let lead = GROUP_EVENT_LEAD_MINUTES + CALENDAR_INTERVAL.as_secs() as i64 / 60;
+            if !(0..=lead).contains(&minutes) || !self.seen_group_events.insert(id.to_string()) {
+                continue;
+            }
+            publish(FriendEvent::GroupEventStarting {
+                event_id: id.to_string(),
+                group_id: event["ownerId"].as_str().unwrap_or_default().to_string(),
+                title: event["title"].as_str().unwrap_or_default().to_string(),
+                starts_at: starts_at.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// OSCやWebhookの購読者がいる間だけフレンドの状態・招待・グループのイベントを定期的に取得し、変化をイベントにする
+pub(crate) async fn run() {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    let mut watcher = Watcher::default();
+
+    loop {
+        interval.tick().await;
+
+        if SENDER.receiver_count() == 0 || !is_login().await {
+            // 再開したときに、止まっていた間の変化をまとめて通知しない
+            watcher = Watcher::default();
+            continue;
+        }
+
+        if let Err(e) = watcher.refresh_profile().await {
+            error!("Failed to refresh friends and favorites: {:?}", e);
+        }
+        if let Err(e) = watcher.poll_friends().await {
+            error!("Failed to poll friends: {:?}", e);
+            continue;
+        }
+        if let Err(e) = watcher.poll_invites().await {
+            error!("Failed to poll invites: {:?}", e);
+        }
+        if let Err(e) = watcher.poll_group_events().await {
+            error!("Failed to poll group events: {:?}", e);
+        }
+        watcher.initialized = true;
+    }
+}
+
+/// 履歴の記録で読んだVRChatのログから、自分のいるインスタンスに来たフレンドを見つける
+pub(crate) fn observe_log(events: &[LogEvent]) {
+    let joined = {
+        let friend_ids = FRIEND_IDS.read().unwrap();
+        let mut instance = MY_INSTANCE.lock().unwrap();
+        joined_friends(&mut instance, events, Local::now().naive_local(), &friend_ids)
+    };
+    for event in joined {
+        publish(event);
+    }
+}
+
+/// ログを順に読んで自分のいるインスタンスを更新し、あとから来たフレンドの参加をイベントにする。
+/// 入った直後の参加（元からいたプレイヤー）と、`now` から見て古すぎる行は除く
+fn joined_friends(
+    instance: &mut Option<MyInstance>,
+    events: &[LogEvent],
+    now: NaiveDateTime,
+    friend_ids: &HashSet<String>,
+) -> Vec<FriendEvent> {
+    let mut joined = Vec::new();
+    for event in events {
+        match event {
+            LogEvent::RoomJoin { timestamp, location } => {
+                *instance = Some(MyInstance {
+                    location: location.clone(),
+                    world_name: None,
+                    joined_at: *timestamp,
+                });
+            }
+            LogEvent::RoomName { name, .. } => {
+                if let Some(instance) = instance.as_mut() {
+                    instance.world_name.get_or_insert_with(|| name.clone());
+                }
+            }
+            LogEvent::LeftRoom { .. } => *instance = None,
+            LogEvent::PlayerJoined {
+                timestamp,
+                display_name,
+                user_id: Some(user_id),
+            } => {
+                let Some(current) = instance.as_ref() else {
+                    continue;
+                };
+                if (*timestamp - current.joined_at).num_seconds() < JOIN_GRACE_SECONDS
+                    || (now - *timestamp).num_seconds() > STALE_LOG_SECONDS
+                    || !friend_ids.contains(user_id)
+                {
+                    continue;
+                }
+                joined.push(FriendEvent::FriendJoinedInstance {
+                    user_id: user_id.clone(),
+                    display_name: display_name.clone(),
+                    location: current.location.clone(),
+                    world_name: current.world_name.clone().unwrap_or_default(),
+                });
+            }
+            _ => {}
+        }
+    }
+    joined
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    const LOCATION: &str = "wrld_a:12345~region(jp)";
+
+    fn at(seconds: i64) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str("2025-01-01 20:00:00", "%Y-%m-%d %H:%M:%S").unwrap() + TimeDelta::seconds(seconds)
+    }
+
+    fn room_join(seconds: i64) -> LogEvent {
+        LogEvent::RoomJoin {
+            timestamp: at(seconds),
+            location: LOCATION.to_string(),
+        }
+    }
+
+    fn player_joined(seconds: i64, user_id: &str) -> LogEvent {
+        LogEvent::PlayerJoined {
+            timestamp: at(seconds),
+            display_name: format!("Name of {user_id}"),
+            user_id: Some(user_id.to_string()),
+        }
+    }
+
+    fn friend_ids() -> HashSet<String> {
+        HashSet::from(["usr_friend".to_string()])
+    }
+
+    fn joined_ids(events: &[FriendEvent]) -> Vec<&str> {
+        events
+            .iter()
+            .map(|event| match event {
+                FriendEvent::FriendJoinedInstance { user_id, .. } => user_id.as_str(),
+                other => panic!("unexpected event: {other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn joins_right_after_entering_are_not_reported() {
+        let mut instance = None;
+        let events = [
+            room_join(0),
+            LogEvent::RoomName {
+                timestamp: at(1),
+                name: "Home".to_string(),
+            },
+            player_joined(JOIN_GRACE_SECONDS - 1, "usr_friend"),
+            player_joined(JOIN_GRACE_SECONDS, "usr_friend"),
+        ];
+
+        let joined = joined_friends(&mut instance, &events, at(60), &friend_ids());
+        assert_eq!(joined_ids(&joined), ["usr_friend"]);
+        let FriendEvent::FriendJoinedInstance { location, world_name, .. } = &joined[0] else {
+            unreachable!();
+        };
+        assert_eq!(location, LOCATION);
+        assert_eq!(world_name, "Home");
+    }
+
+    #[test]
+    fn stale_log_lines_are_not_reported() {
+        let mut instance = None;
+        let events = [room_join(0), player_joined(60, "usr_friend")];
+
+        let now = at(60 + STALE_LOG_SECONDS + 1);
+        assert!(joined_friends(&mut instance, &events, now, &friend_ids()).is_empty());
+        // 自分のいるインスタンスは古い行からでも分かる
+        assert_eq!(instance.as_ref().map(|i| i.location.as_str()), Some(LOCATION));
+
+        let mut instance = None;
+        let now = at(60 + STALE_LOG_SECONDS);
+        assert_eq!(joined_friends(&mut instance, &events, now, &friend_ids()).len(), 1);
+    }
+
+    #[test]
+    fn only_friends_are_reported() {
+        let mut instance = None;
+        let events = [
+            room_join(0),
+            player_joined(30, "usr_stranger"),
+            player_joined(31, "usr_friend"),
+            // 古いバージョンのログにはIDが無い
+            LogEvent::PlayerJoined {
+                timestamp: at(32),
+                display_name: "Name of usr_friend".to_string(),
+                user_id: None,
+            },
+        ];
+
+        let joined = joined_friends(&mut instance, &events, at(60), &friend_ids());
+        assert_eq!(joined_ids(&joined), ["usr_friend"]);
+    }
+
+    #[test]
+    fn leaving_the_room_forgets_the_instance() {
+        let mut instance = None;
+        let events = [room_join(0), LogEvent::LeftRoom { timestamp: at(20) }, player_joined(30, "usr_friend")];
+
+        assert!(joined_friends(&mut instance, &events, at(60), &friend_ids()).is_empty());
+        assert!(instance.is_none());
+
+        // 前のログで入ったインスタンスは次のログに引き継ぐ
+        let mut instance = None;
+        joined_friends(&mut instance, &[room_join(0)], at(60), &friend_ids());
+        let joined = joined_friends(&mut instance, &[player_joined(30, "usr_friend")], at(60), &friend_ids());
+        assert_eq!(joined_ids(&joined), ["usr_friend"]);
+    }
+
+    fn online(favorite: bool) -> FriendEvent {
+        FriendEvent::FriendOnline {
+            user_id: "usr_friend".to_string(),
+            display_name: "Friend".to_string(),
+            favorite,
+            location: LOCATION.to_string(),
+            world_name: "Home".to_string(),
+        }
+    }
+
+    #[test]
+    fn favorite_online_only_matches_favorites() {
+        // お気に入りでもフレンドのオンラインとして通知する
+        assert!(online(true).matches(FriendEventKind::FriendOnline));
+        assert!(online(true).matches(FriendEventKind::FavoriteOnline));
+        assert!(online(false).matches(FriendEventKind::FriendOnline));
+        assert!(!online(false).matches(FriendEventKind::FavoriteOnline));
+        assert!(!online(true).matches(FriendEventKind::FriendLocation));
+        assert_eq!(online(true).kind(), FriendEventKind::FriendOnline);
+    }
+
+    #[test]
+    fn notification_details_accepts_string_and_object() {
+        let object = serde_json::json!({"details": {"worldId": LOCATION, "worldName": "Home"}});
+        let string = serde_json::json!({"details": format!(r#"{{"worldId":"{LOCATION}","worldName":"Home"}}"#)});
+
+        for notification in [object, string] {
+            let details = notification_details(&notification);
+            assert_eq!(details["worldId"], LOCATION);
+            assert_eq!(details["worldName"], "Home");
+        }
+        // 読めない文字列や無い場合は空
+        assert!(notification_details(&serde_json::json!({"details": "{}}"})).is_null());
+        assert!(notification_details(&serde_json::json!({}))["worldId"].is_null());
+    }
+
+    fn friend(id: &str, location: &str) -> serde_json::Value {
+        serde_json::json!({"id": id, "displayName": format!("Name of {id}"), "location": location})
+    }
+
+    #[test]
+    fn first_poll_is_silent() {
+        let (locations, changes) = friend_changes(None, &[friend("usr_a", LOCATION), friend("usr_b", "private")]);
+
+        assert!(changes.is_empty());
+        assert_eq!(locations.get("usr_a").map(String::as_str), Some(LOCATION));
+        assert_eq!(locations.get("usr_b").map(String::as_str), Some("private"));
+    }
+
+    #[test]
+    fn online_and_moved_friends_are_reported() {
+        let (previous, _) = friend_changes(None, &[friend("usr_a", LOCATION), friend("usr_b", "private")]);
+
+        let current = [
+            friend("usr_a", LOCATION),
+            friend("usr_b", "wrld_b:1"),
+            friend("usr_c", "private"),
+        ];
+        let (locations, changes) = friend_changes(Some(&previous), &current);
+        assert_eq!(
+            changes,
+            [
+                FriendChange::Moved {
+                    user_id: "usr_b".to_string(),
+                    display_name: "Name of usr_b".to_string(),
+                    location: "wrld_b:1".to_string(),
+                    previous_location: "private".to_string(),
+                },
+                FriendChange::Online {
+                    user_id: "usr_c".to_string(),
+                    display_name: "Name of usr_c".to_string(),
+                    location: "private".to_string(),
+                },
+            ]
+        );
+
+        // オフラインになったフレンドは一覧から消え、戻ればまたオンラインとして通知する
+        let (without_a, changes) = friend_changes(Some(&locations), &current[1..]);
+        assert!(changes.is_empty());
+        let (_, changes) = friend_changes(Some(&without_a), &current);
+        assert!(matches!(&changes[..], [FriendChange::Online { user_id, .. }] if user_id == "usr_a"));
+    }
+}
@@ -0,0 +1,238 @@
+use crate::commands::{get_current_user_json, RustError};
+use chrono::Local;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tauri_plugin_store::StoreExt;
+
+pub(crate) const FRIEND_HISTORY_STORE: &str = "friend-history.json";
+const CHANGES_MAX_ENTRIES: usize = 5000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+pub enum FriendChangeKind {
+    Added,
+    Removed,
+    Renamed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct FriendChange {
+    pub timestamp: String,
+    pub kind: FriendChangeKind,
+    pub user_id: String,
+    pub display_name: String,
+    pub previous_display_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FriendSnapshot {
+    taken_at: String,
+    /// ユーザーID → 表示名
+    friends: HashMap<String, String>,
+}
+
+fn snapshot_key(user_id: &str) -> String {
+    format!("snapshot:{user_id}")
+}
+
+fn changes_key(user_id: &str) -> String {
+    format!("changes:{user_id}")
+}
+
+fn diff_snapshots(
+    previous: &HashMap<String, String>,
+    current: &HashMap<String, String>,
+    timestamp: &str,
+) -> Vec<FriendChange> {
+    let mut changes: Vec<FriendChange> = Vec::new();
+
+    for (id, name) in current {
+        match previous.get(id) {
+            None => changes.push(FriendChange {
+                timestamp: timestamp.to_string(),
+                kind: FriendChangeKind::Added,
+                user_id: id.clone(),
+                display_name: name.clone(),
+                previous_display_name: None,
+            }),
+            Some(previous_name) if previous_name != name => changes.push(FriendChange {
+                timestamp: timestamp.to_string(),
+                kind: FriendChangeKind::Renamed,
+                user_id: id.clone(),
+                display_name: name.clone(),
+                previous_display_name: Some(previous_name.clone()),
+            }),
+            _ => {}
+        }
+    }
+    for (id, name) in previous {
+        if !current.contains_key(id) {
+            changes.push(FriendChange {
+                timestamp: timestamp.to_string(),
+                kind: FriendChangeKind::Removed,
+                user_id: id.clone(),
+                display_name: name.clone(),
+                previous_display_name: None,
+            });
+        }
+    }
+
+    changes.sort_by(|a, b| a.display_name.cmp(&b.display_name));
+    changes
+}
+
+/// 取得し終えたフレンド一覧（ユーザーID → 表示名）を前回のスナップショットと比較し、差分を履歴に追記する
+pub(crate) async fn record_snapshot(
+    app_handle: &tauri::AppHandle,
+    mut friends: HashMap<String, String>,
+) -> Result<Vec<FriendChange>, RustError> {
+    // ページングの取りこぼしで解除扱いにしないよう、`/auth/user` のフレンドID一覧と突き合わせる
+    let current_user = get_current_user_json().await?;
+    let user_id = current_user["id"]
+        .as_str()
+        .ok_or("User ID not found in response")?
+        .to_string();
+    let friend_ids: Vec<&str> = current_user["friends"]
+        .as_array()
+        .map(|ids| ids.iter().filter_map(|id| id.as_str()).collect())
+        .unwrap_or_default();
+
+    let store = app_handle.store(FRIEND_HISTORY_STORE)?;
+    let previous: Option<FriendSnapshot> = store
+        .get(snapshot_key(&user_id))
+        .and_then(|value| serde_json::from_value(value).ok());
+
+    if let Some(previous) = &previous {
+        for id in &friend_ids {
+            if !friends.contains_key(*id) {
+                if let Some(name) = previous.friends.get(*id) {
+                    friends.insert(id.to_string(), name.clone());
+                }
+            }
+        }
+    }
+    if !friend_ids.is_empty() {
+        friends.retain(|id, _| friend_ids.contains(&id.as_str()));
+    }
+
+    let now = Local::now().to_rfc3339();
+    let changes = match &previous {
+        Some(previous) => diff_snapshots(&previous.friends, &friends, &now),
+        // 初回は比較対象がないので基準として保存するだけ
+        None => Vec::new(),
+    };
+
+    if !changes.is_empty() {
+        info!("Friend list changed: {} change(s)", changes.len());
+        let mut history: Vec<FriendChange> = store
+            .get(changes_key(&user_id))
+            .and_then(|value| serde_json::from_value(value).ok())
+            .unwrap_or_default();
+        history.extend(changes.iter().cloned());
+        if history.len() > CHANGES_MAX_ENTRIES {
+            history.drain(..history.len() - CHANGES_MAX_ENTRIES);
+        }
+        store.set(changes_key(&user_id), serde_json::to_value(history)?);
+    }
+
+    let snapshot = FriendSnapshot {
+        taken_at: now,
+        friends,
+    };
+    store.set(snapshot_key(&user_id), serde_json::to_value(snapshot)?);
+    store.save()?;
+
+    Ok(changes)
+}
+
+/// フレンドのJSON配列からユーザーID → 表示名の対応表を作る
+pub(crate) fn collect_names(friends: &[serde_json::Value]) -> HashMap<String, String> {
+    friends
+        .iter()
+        .filter_map(|f| {
+            let id = f["id"].as_str()?;
+            let name = f["displayName"].as_str().unwrap_or_default();
+            Some((id.to_string(), name.to_string()))
+        })
+        .collect()
+}
+
+/// 新しい順にフレンドの追加・解除・表示名変更の履歴を返す
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn get_friend_changes(
+    app_handle: tauri::AppHandle,
+    kind: Option<FriendChangeKind>,
+    limit: Option<u32>,
+) -> Result<Vec<FriendChange>, RustError> {
+    debug!("Call get_friend_changes {:?} {:?}", kind, limit);
+
+    let user_id = crate::commands::get_current_user_id().await?;
+    let store = app_handle.store(FRIEND_HISTORY_STORE)?;
+    let history: Vec<FriendChange> = match store.get(changes_key(&user_id)) {
+        Some(value) => serde_json::from_value(value)?,
+        None => Vec::new(),
+    };
+
+    Ok(history
+        .into_iter()
+        .rev()
+        .filter(|c| kind.is_none_or(|k| c.kind == k))
+        .take(limit.map(|l| l as usize).unwrap_or(usize::MAX))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMESTAMP: &str = "2025-01-01T20:00:00+09:00";
+
+    fn friends(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries.iter().map(|(id, name)| (id.to_string(), name.to_string())).collect()
+    }
+
+    fn summary(changes: &[FriendChange]) -> Vec<(FriendChangeKind, &str, &str, Option<&str>)> {
+        changes
+            .iter()
+            .map(|change| {
+                (
+                    change.kind,
+                    change.user_id.as_str(),
+                    change.display_name.as_str(),
+                    change.previous_display_name.as_deref(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn added_removed_and_renamed_friends_are_sorted_by_name() {
+        let previous = friends(&[("usr_a", "Alice"), ("usr_b", "Bob"), ("usr_c", "Carol")]);
+        let current = friends(&[("usr_a", "Alice"), ("usr_c", "Ann"), ("usr_d", "Dave"), ("usr_e", "Bea")]);
+
+        let changes = diff_snapshots(&previous, &current, TIMESTAMP);
+        assert_eq!(
+            summary(&changes),
+            [
+                (FriendChangeKind::Renamed, "usr_c", "Ann", Some("Carol")),
+                (FriendChangeKind::Added, "usr_e", "Bea", None),
+                (FriendChangeKind::Removed, "usr_b", "Bob", None),
+                (FriendChangeKind::Added, "usr_d", "Dave", None),
+            ]
+        );
+        assert!(changes.iter().all(|change| change.timestamp == TIMESTAMP));
+    }
+
+    #[test]
+    fn unchanged_friends_produce_no_changes() {
+        let snapshot = friends(&[("usr_a", "Alice"), ("usr_b", "Bob")]);
+
+        assert!(diff_snapshots(&snapshot, &snapshot, TIMESTAMP).is_empty());
+        assert!(diff_snapshots(&HashMap::new(), &HashMap::new(), TIMESTAMP).is_empty());
+    }
+}
@@ -0,0 +1,329 @@
+use crate::commands::{RustError, VRCHAT_API_BASE_URL};
+use crate::friend_history::{collect_names, record_snapshot};
+use crate::notes::{load_notes, UserNote};
+use crate::accounts::{account_session, current_session, load_accounts, Session};
+use crate::reauth::SendWithReauth;
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::task::JoinSet;
+
+const FRIENDS_PAGE_SIZE: i32 = 100;
+const FRIENDS_PAGE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct FriendFilter {
+    /// 指定したタグをすべて持つフレンドに絞り込む
+    pub tags: Vec<String>,
+    /// 表示名・ローカルメモ・タグに対する部分一致（大文字小文字は区別しない）
+    pub query: Option<String>,
+    pub include_offline: bool,
+}
+
+#[derive(Debug, Clone, Serialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct MergedFriends {
+    /// フレンドのJSON配列。各要素の `seenBy` に、そのフレンドが見えているアカウントIDが入る
+    pub friends: String,
+    /// 取得に失敗したアカウントID
+    pub failed_accounts: Vec<String>,
+}
+
+/// オンライン（`offline` が true ならオフライン）のフレンドをページングしながらすべて取得する
+pub(crate) async fn fetch_all_friends(session: &Session, offline: bool) -> Result<Vec<serde_json::Value>, RustError> {
+    fetch_friend_pages(session, offline, None).await
+}
+
+/// 複数のアカウントやオンライン・オフラインをまとめて集計するとき用に、ページの間を空けて取得する
+pub(crate) async fn fetch_all_friends_paced(
+    session: &Session,
+    offline: bool,
+) -> Result<Vec<serde_json::Value>, RustError> {
+    fetch_friend_pages(session, offline, Some(FRIENDS_PAGE_INTERVAL)).await
+}
+
+/// `session` のアカウントとして取得する。セッションが切れていればそのアカウントを再認証する
+async fn fetch_friend_pages(
+    session: &Session,
+    offline: bool,
+    page_interval: Option<Duration>,
+) -> Result<Vec<serde_json::Value>, RustError> {
+    let mut friends: Vec<serde_json::Value> = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        let res = session
+            .client
+            .get(format!("{VRCHAT_API_BASE_URL}/1/auth/user/friends"))
+            .query(&[("offset", offset), ("n", FRIENDS_PAGE_SIZE)])
+            .query(&[("offline", offline)])
+            .send_with_reauth_as(session)
+            .await?;
+
+        if res.status() != reqwest::StatusCode::OK {
+            error!("Failed to get friends {:?}", res);
+            return Err(res.status().into());
+        }
+
+        let page: Vec<serde_json::Value> = res.json().await?;
+        let count = page.len() as i32;
+        // ページ数より少なくても続きが返ってくることがあるため、空になるまで取得する
+        if count == 0 {
+            break;
+        }
+        friends.extend(page);
+        offset += count;
+        if let Some(page_interval) = page_interval {
+            tokio::time::sleep(page_interval).await;
+        }
+    }
+
+    Ok(friends)
+}
+
+fn matches_filter(friend: &serde_json::Value, note: Option<&UserNote>, filter: &FriendFilter) -> bool {
+    let tags = note.map(|n| n.tags.as_slice()).unwrap_or_default();
+    if !filter.tags.iter().all(|t| tags.contains(t)) {
+        return false;
+    }
+
+    match filter.query.as_deref().map(str::trim) {
+        Some(query) if !query.is_empty() => {
+            let query = query.to_lowercase();
+            let display_name = friend["displayName"].as_str().unwrap_or_default();
+            display_name.to_lowercase().contains(&query)
+                || note.is_some_and(|n| {
+                    n.note.to_lowercase().contains(&query)
+                        || n.official_note.as_ref().is_some_and(|o| o.to_lowercase().contains(&query))
+                })
+                || tags.iter().any(|t| t.to_lowercase().contains(&query))
+        }
+        _ => true,
+    }
+}
+
+/// フレンド一覧を集計し、ローカルのメモとタグを付与したうえでフィルタして返す
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn search_friends(
+    app_handle: tauri::AppHandle,
+    filter: FriendFilter,
+) -> Result<String, RustError> {
+    debug!("Call search_friends {:?}", filter);
+
+    let session = current_session();
+    let mut friends = fetch_all_friends_paced(&session, false).await?;
+    if filter.include_offline {
+        friends.extend(fetch_all_friends_paced(&session, true).await?);
+        if let Err(e) = record_snapshot(&app_handle, collect_names(&friends)).await {
+            error!("Failed to record friend snapshot: {:?}", e);
+        }
+    }
+
+    let notes = load_notes(&app_handle)?;
+    let result: Vec<serde_json::Value> = friends
+        .into_iter()
+        .filter_map(|mut friend| {
+            let note = friend["id"].as_str().and_then(|id| notes.get(id));
+            if !matches_filter(&friend, note, &filter) {
+                return None;
+            }
+            if let Some(note) = note {
+                friend["localNote"] = serde_json::Value::String(note.note.clone());
+                friend["localTags"] = serde_json::json!(note.tags);
+            }
+            Some(friend)
+        })
+        .collect();
+
+    Ok(serde_json::to_string(&result)?)
+}
+
+fn is_location_hidden(friend: &serde_json::Value) -> bool {
+    matches!(friend["location"].as_str(), None | Some("private") | Some("offline") | Some(""))
+}
+
+/// アカウントごとのフレンド一覧を1つにまとめる。
+/// 同じフレンドが複数のアカウントから見える場合は、居場所が見えているほうの情報を使う
+fn merge_friends(lists: Vec<(String, Vec<serde_json::Value>)>) -> Vec<serde_json::Value> {
+    let mut merged: Vec<serde_json::Value> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+
+    for (account_id, friends) in lists {
+        for mut friend in friends {
+            let Some(id) = friend["id"].as_str().map(|id| id.to_string()) else {
+                continue;
+            };
+            match index.get(&id) {
+                Some(i) => {
+                    let mut seen_by = merged[*i]["seenBy"].take();
+                    if let Some(seen_by) = seen_by.as_array_mut() {
+                        seen_by.push(serde_json::Value::String(account_id.clone()));
+                    }
+                    if is_location_hidden(&merged[*i]) && !is_location_hidden(&friend) {
+                        merged[*i] = friend;
+                    }
+                    merged[*i]["seenBy"] = seen_by;
+                }
+                None => {
+                    friend["seenBy"] = serde_json::json!([account_id]);
+                    index.insert(id, merged.len());
+                    merged.push(friend);
+                }
+            }
+        }
+    }
+
+    merged
+}
+
+/// 登録済みのすべてのアカウントのフレンド一覧を同時に取得し、どのアカウントから見えているかを付けてまとめる
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn get_merged_friends(
+    app_handle: tauri::AppHandle,
+    include_offline: bool,
+) -> Result<MergedFriends, RustError> {
+    debug!("Call get_merged_friends {:?}", include_offline);
+
+    // 現在のアカウントを先頭にして、重複時にその情報が優先されるようにする
+    let current_id = current_session().user_id.clone();
+    let mut accounts = load_accounts(&app_handle)?;
+    accounts.sort_by_key(|a| Some(&a.id) != current_id.as_ref());
+
+    let mut failed_accounts: Vec<String> = Vec::new();
+    let mut tasks = JoinSet::new();
+    for (order, account) in accounts.into_iter().enumerate() {
+        let session = match account_session(&app_handle, &account.id).await {
+            Ok(session) => session,
+            Err(e) => {
+                error!("Failed to load session of {}: {:?}", account.id, e);
+                failed_accounts.push(account.id);
+                continue;
+            }
+        };
+        tasks.spawn(async move {
+            let mut friends = fetch_all_friends_paced(&session, false).await;
+            if include_offline {
+                if let Ok(friends) = friends.as_mut() {
+                    match fetch_all_friends_paced(&session, true).await {
+                        Ok(offline) => friends.extend(offline),
+                        Err(e) => return (order, account.id, Err(e)),
+                    }
+                }
+            }
+            (order, account.id, friends)
+        });
+    }
+
+    let mut lists: Vec<(usize, String, Vec<serde_json::Value>)> = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        let (order, account_id, result) = joined?;
+        match result {
+            Ok(friends) => lists.push((order, account_id, friends)),
+            Err(e) => {
+                error!("Failed to get friends of {}: {:?}", account_id, e);
+                failed_accounts.push(account_id);
+            }
+        }
+    }
+    lists.sort_by_key(|(order, _, _)| *order);
+
+    let friends = merge_friends(lists.into_iter().map(|(_, id, friends)| (id, friends)).collect());
+    Ok(MergedFriends {
+        friends: serde_json::to_string(&friends)?,
+        failed_accounts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn friend(id: &str, display_name: &str, location: &str) -> serde_json::Value {
+        serde_json::json!({"id": id, "displayName": display_name, "location": location})
+    }
+
+    fn note(text: &str, tags: &[&str]) -> UserNote {
+        UserNote {
+            note: text.to_string(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn filter(tags: &[&str], query: Option<&str>) -> FriendFilter {
+        FriendFilter {
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            query: query.map(str::to_string),
+            include_offline: false,
+        }
+    }
+
+    #[test]
+    fn filter_requires_every_tag() {
+        let alice = friend("usr_a", "Alice", "private");
+        let tagged = note("", &["close", "jp"]);
+
+        assert!(matches_filter(&alice, Some(&tagged), &filter(&["close", "jp"], None)));
+        assert!(!matches_filter(&alice, Some(&tagged), &filter(&["close", "event"], None)));
+        assert!(!matches_filter(&alice, None, &filter(&["close"], None)));
+        assert!(matches_filter(&alice, None, &filter(&[], Some("  "))));
+    }
+
+    #[test]
+    fn filter_combines_tags_with_text() {
+        let alice = friend("usr_a", "Alice", "private");
+        let tagged = UserNote {
+            official_note: Some("Met at the Meetup".to_string()),
+            ..note("Plays piano", &["close"])
+        };
+
+        // 表示名・メモ・公式メモ・タグのどれかに大文字小文字を区別せず一致すればよい
+        for query in ["ALI", "piano", "meetup", "clo"] {
+            assert!(matches_filter(&alice, Some(&tagged), &filter(&["close"], Some(query))), "{query}");
+        }
+        // 文字が一致してもタグが足りなければ除く
+        assert!(!matches_filter(&alice, Some(&tagged), &filter(&["jp"], Some("alice"))));
+        assert!(!matches_filter(&alice, Some(&tagged), &filter(&["close"], Some("bob"))));
+    }
+
+    #[test]
+    fn friend_seen_by_two_accounts_is_merged_once() {
+        let merged = merge_friends(vec![
+            ("usr_me".to_string(), vec![friend("usr_a", "Alice", "wrld_a:1"), friend("usr_b", "Bob", "private")]),
+            ("usr_alt".to_string(), vec![friend("usr_a", "Alice", "wrld_a:1")]),
+        ]);
+
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0]["id"], "usr_a");
+        assert_eq!(merged[0]["seenBy"], serde_json::json!(["usr_me", "usr_alt"]));
+        assert_eq!(merged[1]["seenBy"], serde_json::json!(["usr_me"]));
+    }
+
+    #[test]
+    fn visible_location_wins_over_hidden_one() {
+        for hidden in ["private", "offline", ""] {
+            let merged = merge_friends(vec![
+                ("usr_me".to_string(), vec![friend("usr_a", "Alice", hidden)]),
+                ("usr_alt".to_string(), vec![friend("usr_a", "Alice", "wrld_a:1")]),
+                ("usr_third".to_string(), vec![friend("usr_a", "Alice", "private")]),
+            ]);
+
+            assert_eq!(merged.len(), 1);
+            assert_eq!(merged[0]["location"], "wrld_a:1", "{hidden:?}");
+            assert_eq!(merged[0]["seenBy"], serde_json::json!(["usr_me", "usr_alt", "usr_third"]));
+        }
+
+        // どちらも見えていれば先のアカウント（現在のアカウント）の情報を使う
+        let merged = merge_friends(vec![
+            ("usr_me".to_string(), vec![friend("usr_a", "Alice", "wrld_a:1")]),
+            ("usr_alt".to_string(), vec![friend("usr_a", "Alice", "wrld_b:2")]),
+        ]);
+        assert_eq!(merged[0]["location"], "wrld_a:1");
+    }
+}
@@ -0,0 +1,107 @@
+use crate::commands::RustError;
+use std::path::PathBuf;
+use tauri::Manager;
+
+/// キーリングとCookieの保存先を決める情報。
+/// GUIでは `AppHandle` から、CLIではTauriを起動せずにOSの標準のフォルダから求める
+pub(crate) trait AppDirs {
+    /// キーリングのサービス名にも使うアプリのID
+    fn identifier(&self) -> &str;
+    fn app_local_data_dir(&self) -> Result<PathBuf, RustError>;
+}
+
+impl AppDirs for tauri::AppHandle {
+    fn identifier(&self) -> &str {
+        &self.config().identifier
+    }
+
+    fn app_local_data_dir(&self) -> Result<PathBuf, RustError> {
+        Ok(self.path().app_local_data_dir()?)
+    }
+}
+
+/// イベントループ（Linuxではディスプレイ）を使わずにGUIと同じデータを読むための場所。
+/// フォルダはTauriの `PathResolver` と同じ規則で求める
+#[derive(Clone)]
+pub(crate) struct HeadlessApp {
+    identifier: String,
+    data_dir: PathBuf,
+    local_data_dir: PathBuf,
+}
+
+impl HeadlessApp {
+    pub(crate) fn new(identifier: &str) -> Result<Self, RustError> {
+        let data_dir = dirs::data_dir().ok_or("Data directory not found")?;
+        let local_data_dir = dirs::data_local_dir().ok_or("Local data directory not found")?;
+        Ok(Self::with_dirs(identifier, data_dir.join(identifier), local_data_dir.join(identifier)))
+    }
+
+    pub(crate) fn with_dirs(identifier: &str, data_dir: PathBuf, local_data_dir: PathBuf) -> Self {
+        Self {
+            identifier: identifier.to_string(),
+            data_dir,
+            local_data_dir,
+        }
+    }
+
+    /// `tauri-plugin-log` の `LogDir` と同じ場所
+    pub(crate) fn log_dir(&self) -> Result<PathBuf, RustError> {
+        #[cfg(target_os = "macos")]
+        {
+            let home = dirs::home_dir().ok_or("Home directory not found")?;
+            Ok(home.join("Library").join("Logs").join(&self.identifier))
+        }
+        #[cfg(not(target_os = "macos"))]
+        {
+            Ok(self.local_data_dir.join("logs"))
+        }
+    }
+
+    /// `tauri-plugin-store` のストアの中身を読む。ファイルが無ければ空のストアとして扱う
+    pub(crate) fn read_store(&self, name: &str) -> Result<serde_json::Map<String, serde_json::Value>, RustError> {
+        match std::fs::read(self.data_dir.join(name)) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(serde_json::Map::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub(crate) fn store_value(&self, name: &str, key: &str) -> Result<Option<serde_json::Value>, RustError> {
+        Ok(self.read_store(name)?.remove(key))
+    }
+}
+
+impl AppDirs for HeadlessApp {
+    fn identifier(&self) -> &str {
+        &self.identifier
+    }
+
+    fn app_local_data_dir(&self) -> Result<PathBuf, RustError> {
+        Ok(self.local_data_dir.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn reads_store_files_written_by_the_plugin() {
+        let dir = TempDir::new("headless");
+        let app = HeadlessApp::with_dirs("com.example.test", dir.to_path_buf(), dir.to_path_buf());
+
+        // プラグインは整形したJSONのオブジェクトで保存する
+        std::fs::write(dir.join("store.json"), b"{\n  \"current-user-id\": \"usr_a\"\n}").unwrap();
+        assert_eq!(
+            app.store_value("store.json", "current-user-id").unwrap(),
+            Some(serde_json::json!("usr_a"))
+        );
+        assert_eq!(app.store_value("store.json", "missing").unwrap(), None);
+        // まだ作られていないストアは空
+        assert!(app.read_store("accounts.json").unwrap().is_empty());
+
+        std::fs::write(dir.join("broken.json"), b"{").unwrap();
+        assert!(app.read_store("broken.json").is_err());
+    }
+}
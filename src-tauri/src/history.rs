@@ -0,0 +1,512 @@
+use crate::commands::RustError;
+use crate::friend_events;
+use crate::presence;
+use crate::vrchat_log::{default_log_dir, LogEvent, LogTailer};
+use chrono::{Local, NaiveDateTime, TimeZone};
+use log::{debug, error, info};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use tauri::Emitter;
+use tauri_plugin_store::StoreExt;
+use tokio::sync::Mutex;
+
+pub(crate) const HISTORY_STORE: &str = "history.json";
+const VISITS_KEY: &str = "visits";
+const LOG_DIR_KEY: &str = "vrchat-log-dir";
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// 滞在中の記録を保存する間隔。終わった滞在はすぐに保存する
+const CURRENT_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+pub(crate) const INSTANCE_UPDATED_EVENT: &str = "vrchat-instance-updated";
+
+/// 読み込み済みの履歴。読み書きはこのロックで直列化する（ログ監視と一括インポートが同時に書き込むため）。
+/// `None` のときは次に使うときにストアから読み込む
+static HISTORY: Lazy<Mutex<Option<VisitIndex>>> = Lazy::new(|| Mutex::new(None));
+
+static CURRENT_VISIT: Lazy<Mutex<Option<InstanceVisit>>> = Lazy::new(|| Mutex::new(None));
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerEncounter {
+    pub display_name: String,
+    pub user_id: Option<String>,
+    pub joined_at: String,
+    pub left_at: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoPlay {
+    pub url: String,
+    pub played_at: String,
+}
+
+/// ログから復元した1回分のインスタンス滞在
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct InstanceVisit {
+    /// `参加時刻@ロケーション`。再インポート時の重複判定に使う
+    pub id: String,
+    pub world_id: String,
+    pub instance_id: String,
+    pub world_name: Option<String>,
+    pub joined_at: String,
+    pub left_at: Option<String>,
+    pub players: Vec<PlayerEncounter>,
+    pub videos: Vec<VideoPlay>,
+    pub portals: Vec<String>,
+}
+
+pub(crate) fn format_timestamp(timestamp: NaiveDateTime) -> String {
+    match Local.from_local_datetime(&timestamp).earliest() {
+        Some(datetime) => datetime.to_rfc3339(),
+        None => timestamp.format("%Y-%m-%dT%H:%M:%S").to_string(),
+    }
+}
+
+/// ログイベントを順に受け取り、インスタンスごとの滞在記録を組み立てる
+#[derive(Debug, Default)]
+pub(crate) struct Timeline {
+    current: Option<InstanceVisit>,
+}
+
+impl Timeline {
+    pub(crate) fn current(&self) -> Option<&InstanceVisit> {
+        self.current.as_ref()
+    }
+
+    /// イベントを反映する。滞在が終わった場合はその記録を返す
+    pub(crate) fn apply(&mut self, event: LogEvent) -> Option<InstanceVisit> {
+        match event {
+            LogEvent::RoomJoin { timestamp, location } => {
+                let at = format_timestamp(timestamp);
+                let finished = self.finish(Some(at.clone()));
+                let (world_id, instance_id) = location.split_once(':').unwrap_or((&location, ""));
+                self.current = Some(InstanceVisit {
+                    id: format!("{at}@{location}"),
+                    world_id: world_id.to_string(),
+                    instance_id: instance_id.to_string(),
+                    world_name: None,
+                    joined_at: at,
+                    left_at: None,
+                    players: Vec::new(),
+                    videos: Vec::new(),
+                    portals: Vec::new(),
+                });
+                finished
+            }
+            LogEvent::RoomName { name, .. } => {
+                if let Some(visit) = self.current.as_mut() {
+                    visit.world_name.get_or_insert(name);
+                }
+                None
+            }
+            LogEvent::PlayerJoined {
+                timestamp,
+                display_name,
+                user_id,
+            } => {
+                if let Some(visit) = self.current.as_mut() {
+                    visit.players.push(PlayerEncounter {
+                        display_name,
+                        user_id,
+                        joined_at: format_timestamp(timestamp),
+                        left_at: None,
+                    });
+                }
+                None
+            }
+            LogEvent::PlayerLeft {
+                timestamp,
+                display_name,
+                user_id,
+            } => {
+                if let Some(visit) = self.current.as_mut() {
+                    let player = visit.players.iter_mut().rev().find(|p| {
+                        p.left_at.is_none()
+                            && match (&user_id, &p.user_id) {
+                                (Some(a), Some(b)) => a == b,
+                                _ => p.display_name == display_name,
+                            }
+                    });
+                    if let Some(player) = player {
+                        player.left_at = Some(format_timestamp(timestamp));
+                    }
+                }
+                None
+            }
+            LogEvent::VideoUrl { timestamp, url } => {
+                if let Some(visit) = self.current.as_mut() {
+                    visit.videos.push(VideoPlay {
+                        url,
+                        played_at: format_timestamp(timestamp),
+                    });
+                }
+                None
+            }
+            LogEvent::PortalDropped { timestamp } => {
+                if let Some(visit) = self.current.as_mut() {
+                    visit.portals.push(format_timestamp(timestamp));
+                }
+                None
+            }
+            LogEvent::LeftRoom { timestamp } => self.finish(Some(format_timestamp(timestamp))),
+        }
+    }
+
+    /// 現在の滞在を終了させる。`at` が無い場合（ログが途切れた場合など）は最後のイベント時刻を使う
+    pub(crate) fn finish(&mut self, at: Option<String>) -> Option<InstanceVisit> {
+        let mut visit = self.current.take()?;
+        let at = at.unwrap_or_else(|| {
+            visit
+                .players
+                .iter()
+                .flat_map(|p| p.left_at.iter().chain(std::iter::once(&p.joined_at)))
+                .chain(visit.videos.iter().map(|v| &v.played_at))
+                .chain(visit.portals.iter())
+                .max()
+                .unwrap_or(&visit.joined_at)
+                .clone()
+        });
+        for player in visit.players.iter_mut().filter(|p| p.left_at.is_none()) {
+            player.left_at = Some(at.clone());
+        }
+        visit.left_at = Some(at);
+        Some(visit)
+    }
+}
+
+/// ログの並びをそのまま滞在記録に変換する
+pub(crate) fn build_visits(events: Vec<LogEvent>) -> Vec<InstanceVisit> {
+    let mut timeline = Timeline::default();
+    let mut visits: Vec<InstanceVisit> = events.into_iter().filter_map(|e| timeline.apply(e)).collect();
+    visits.extend(timeline.finish(None));
+    visits
+}
+
+/// 参加時刻順に並べた滞在履歴と、IDから位置を引く索引
+#[derive(Debug, Default)]
+pub(crate) struct VisitIndex {
+    visits: Vec<InstanceVisit>,
+    positions: HashMap<String, usize>,
+}
+
+impl VisitIndex {
+    fn new(mut visits: Vec<InstanceVisit>) -> Self {
+        visits.sort_by(|a, b| a.joined_at.cmp(&b.joined_at));
+        let mut index = Self {
+            visits,
+            positions: HashMap::new(),
+        };
+        index.reindex();
+        index
+    }
+
+    fn load(app_handle: &tauri::AppHandle) -> Result<Self, RustError> {
+        let store = app_handle.store(HISTORY_STORE)?;
+        match store.get(VISITS_KEY) {
+            Some(value) => Ok(Self::new(serde_json::from_value(value)?)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    fn reindex(&mut self) {
+        self.positions = self
+            .visits
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (v.id.clone(), i))
+            .collect();
+    }
+
+    pub(crate) fn visits(&self) -> &[InstanceVisit] {
+        &self.visits
+    }
+
+    /// 同じIDの記録は上書きし、無いものは追加する。変わった件数と新たに追加された件数を返す
+    pub(crate) fn merge(&mut self, visits: Vec<InstanceVisit>) -> (usize, usize) {
+        let (mut changed, mut added) = (0, 0);
+        let mut unsorted = false;
+        for visit in visits {
+            match self.positions.get(&visit.id) {
+                // IDに参加時刻が入っているので、上書きしても並び順は変わらない
+                Some(&i) => {
+                    if self.visits[i] != visit {
+                        self.visits[i] = visit;
+                        changed += 1;
+                    }
+                }
+                None => {
+                    unsorted |= self.visits.last().is_some_and(|last| last.joined_at > visit.joined_at);
+                    self.positions.insert(visit.id.clone(), self.visits.len());
+                    self.visits.push(visit);
+                    added += 1;
+                }
+            }
+        }
+        // ログ監視では末尾に足すだけなので、並べ直すのは古いログを取り込んだときだけ
+        if unsorted {
+            self.visits.sort_by(|a, b| a.joined_at.cmp(&b.joined_at));
+            self.reindex();
+        }
+        (changed, added)
+    }
+}
+
+/// 履歴のストアを他の処理と競合せずに読み書きするためのロック。
+/// ストアを直接書き換えたときは `None` にして読み直させる
+pub(crate) async fn lock_history() -> tokio::sync::MutexGuard<'static, Option<VisitIndex>> {
+    HISTORY.lock().await
+}
+
+fn loaded<'a>(
+    app_handle: &tauri::AppHandle,
+    history: &'a mut Option<VisitIndex>,
+) -> Result<&'a mut VisitIndex, RustError> {
+    if history.is_none() {
+        *history = Some(VisitIndex::load(app_handle)?);
+    }
+    Ok(history.get_or_insert_with(VisitIndex::default))
+}
+
+/// 保存済みの滞在履歴を参加時刻順に返す
+pub(crate) async fn get_visits(app_handle: &tauri::AppHandle) -> Result<Vec<InstanceVisit>, RustError> {
+    let mut history = HISTORY.lock().await;
+    Ok(loaded(app_handle, &mut history)?.visits().to_vec())
+}
+
+/// 同じIDの記録は上書きし、参加時刻順に並べて保存する。何も変わらなければ書き込まない。
+/// 新たに追加された件数を返す
+pub(crate) async fn upsert_visits(
+    app_handle: &tauri::AppHandle,
+    visits: Vec<InstanceVisit>,
+) -> Result<usize, RustError> {
+    if visits.is_empty() {
+        return Ok(0);
+    }
+
+    let mut history = HISTORY.lock().await;
+    let index = loaded(app_handle, &mut history)?;
+    let (changed, added) = index.merge(visits);
+    if changed == 0 && added == 0 {
+        return Ok(0);
+    }
+
+    let store = app_handle.store(HISTORY_STORE)?;
+    store.set(VISITS_KEY, serde_json::to_value(index.visits())?);
+    store.save()?;
+    Ok(added)
+}
+
+pub(crate) fn get_log_dir(app_handle: &tauri::AppHandle) -> Option<PathBuf> {
+    let configured = app_handle
+        .store("store.json")
+        .ok()
+        .and_then(|store| store.get(LOG_DIR_KEY))
+        .and_then(|value| value.as_str().map(PathBuf::from));
+    configured.or_else(default_log_dir)
+}
+
+/// VRChatのログを監視し、インスタンスの滞在履歴を記録し続ける
+pub(crate) async fn run(app_handle: tauri::AppHandle) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    let mut tailer: Option<LogTailer> = None;
+    let mut timeline = Timeline::default();
+    let mut current_saved_at: Option<tokio::time::Instant> = None;
+
+    loop {
+        interval.tick().await;
+
+        let Some(dir) = get_log_dir(&app_handle) else {
+            continue;
+        };
+        if tailer.as_ref().is_none_or(|t| t.dir() != dir) {
+            info!("Watching VRChat logs in {:?}", dir);
+            tailer = Some(LogTailer::new(dir));
+            timeline = Timeline::default();
+        }
+        let Some(tailer) = tailer.as_mut() else {
+            continue;
+        };
+
+        let batch = match tailer.poll() {
+            Ok(batch) => batch,
+            Err(e) => {
+                debug!("Failed to read VRChat log: {}", e);
+                continue;
+            }
+        };
+        if batch.events.is_empty() && !batch.rotated {
+            continue;
+        }
+
+        let mut finished: Vec<InstanceVisit> = Vec::new();
+        // 切り替わる前のファイルの残りを反映してから、前回の滞在を閉じる
+        if batch.rotated {
+            info!("VRChat log rotated: {:?}", tailer.current_file());
+            finished.extend(batch.previous.into_iter().filter_map(|e| timeline.apply(e)));
+            finished.extend(timeline.finish(None));
+        }
+        friend_events::observe_log(&batch.events);
+        finished.extend(batch.events.into_iter().filter_map(|e| timeline.apply(e)));
+
+        let current = timeline.current().cloned();
+        // 滞在中の記録はプレイヤーの出入りのたびに変わるので、保存は間隔を空ける
+        let save_current = !finished.is_empty()
+            || current_saved_at.is_none_or(|saved_at| saved_at.elapsed() >= CURRENT_SAVE_INTERVAL);
+        let mut visits = finished;
+        if save_current {
+            visits.extend(current.clone());
+            current_saved_at = Some(tokio::time::Instant::now());
+        }
+        if let Err(e) = upsert_visits(&app_handle, visits).await {
+            error!("Failed to save instance history: {:?}", e);
+        }
+
+        *CURRENT_VISIT.lock().await = current.clone();
+        presence::update(&app_handle, current.as_ref());
+        if let Err(e) = app_handle.emit(INSTANCE_UPDATED_EVENT, current) {
+            error!("Failed to emit instance update: {}", e);
+        }
+    }
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn get_vrchat_log_dir(app_handle: tauri::AppHandle) -> Option<String> {
+    debug!("Call get_vrchat_log_dir");
+
+    get_log_dir(&app_handle).map(|dir| dir.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn set_vrchat_log_dir(app_handle: tauri::AppHandle, dir: Option<String>) -> Result<(), RustError> {
+    debug!("Call set_vrchat_log_dir {:?}", dir);
+
+    let store = app_handle.store("store.json")?;
+    match dir {
+        Some(dir) => {
+            if !PathBuf::from(&dir).is_dir() {
+                return Err("errors.logDirNotFound".into());
+            }
+            store.set(LOG_DIR_KEY, dir);
+        }
+        None => {
+            store.delete(LOG_DIR_KEY);
+        }
+    }
+    store.save()?;
+    Ok(())
+}
+
+/// 新しい順にインスタンスの滞在履歴を返す
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn get_instance_history(
+    app_handle: tauri::AppHandle,
+    offset: u32,
+    n: u32,
+) -> Result<Vec<InstanceVisit>, RustError> {
+    debug!("Call get_instance_history {:?} {:?}", offset, n);
+
+    let mut history = HISTORY.lock().await;
+    Ok(loaded(&app_handle, &mut history)?
+        .visits()
+        .iter()
+        .rev()
+        .skip(offset as usize)
+        .take(n as usize)
+        .cloned()
+        .collect())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn get_current_instance_visit() -> Option<InstanceVisit> {
+    debug!("Call get_current_instance_visit");
+
+    CURRENT_VISIT.lock().await.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vrchat_log::parse_log_text;
+
+    const FIXTURE: &str = include_str!("../tests/fixtures/output_log_2024-01-15_21-00-00.txt");
+
+    #[test]
+    fn builds_visits_from_fixture() {
+        let visits = build_visits(parse_log_text(FIXTURE));
+        assert_eq!(visits.len(), 2);
+
+        let first = &visits[0];
+        assert_eq!(first.world_id, "wrld_4cf554b4-430c-4f8f-b53e-1f294eed230b");
+        assert_eq!(first.instance_id, "12345~region(jp)");
+        assert_eq!(first.world_name.as_deref(), Some("The Black Cat"));
+        let names: Vec<&str> = first.players.iter().map(|p| p.display_name.as_str()).collect();
+        assert_eq!(names, vec!["Me", "Alice", "Bob"]);
+        assert!(first.players.iter().all(|p| p.left_at.is_some()));
+        assert_eq!(first.videos.len(), 1);
+        assert_eq!(first.portals.len(), 1);
+
+        let second = &visits[1];
+        assert_eq!(second.world_name.as_deref(), Some("Japan Shrine"));
+        assert_eq!(second.players.len(), 2);
+        assert_eq!(second.left_at, Some(format_timestamp(
+            NaiveDateTime::parse_from_str("2024.01.15 21:45:00", "%Y.%m.%d %H:%M:%S").unwrap()
+        )));
+    }
+
+    #[test]
+    fn visit_ids_are_stable_across_parses() {
+        let a = build_visits(parse_log_text(FIXTURE));
+        let b = build_visits(parse_log_text(FIXTURE));
+        assert_eq!(
+            a.iter().map(|v| &v.id).collect::<Vec<_>>(),
+            b.iter().map(|v| &v.id).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn unfinished_visit_closes_at_last_event() {
+        let log = "2024.01.15 21:03:12 Log        -  [Behaviour] Joining wrld_a:1\n\
+                   2024.01.15 21:03:20 Log        -  [Behaviour] OnPlayerJoined Alice (usr_1111)\n";
+        let visits = build_visits(parse_log_text(log));
+        assert_eq!(visits.len(), 1);
+        assert_eq!(visits[0].left_at, Some(visits[0].players[0].joined_at.clone()));
+    }
+
+    #[test]
+    fn merge_skips_unchanged_visits_and_updates_changed_ones() {
+        let visits = build_visits(parse_log_text(FIXTURE));
+        let mut index = VisitIndex::new(visits.clone());
+        assert_eq!(index.merge(visits.clone()), (0, 0));
+
+        let mut updated = visits[1].clone();
+        updated.portals.push("wrld_other".to_string());
+        assert_eq!(index.merge(vec![updated.clone()]), (1, 0));
+        assert_eq!(index.visits().len(), 2);
+        assert_eq!(index.visits()[1], updated);
+    }
+
+    #[test]
+    fn merge_keeps_visits_sorted_when_older_ones_are_added() {
+        let visits = build_visits(parse_log_text(FIXTURE));
+        let mut index = VisitIndex::new(vec![visits[1].clone()]);
+        assert_eq!(index.merge(vec![visits[0].clone()]), (0, 1));
+        assert_eq!(index.visits(), visits.as_slice());
+
+        // 並べ直した後も位置の索引が正しい
+        let mut updated = visits[0].clone();
+        updated.world_name = None;
+        assert_eq!(index.merge(vec![updated.clone()]), (1, 0));
+        assert_eq!(index.visits()[0], updated);
+    }
+}
@@ -0,0 +1,841 @@
+use crate::accounts::{client, current_session};
+use crate::commands::{invite_myself_to_instance, is_login, RustError, VRCHAT_API_BASE_URL};
+use crate::friends::fetch_all_friends;
+use crate::reauth::SendWithReauth;
+use chrono::{DateTime, Local, TimeDelta};
+use log::{debug, error, info, warn};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tauri::async_runtime::JoinHandle;
+use tauri::Emitter;
+use tokio::sync::Mutex;
+
+pub(crate) const INSTANCE_WATCH_UPDATED_EVENT: &str = "instance-watch-updated";
+pub(crate) const INSTANCE_WATCH_EVENT: &str = "instance-watch-event";
+
+const DEFAULT_INTERVAL_SECONDS: u32 = 30;
+/// APIの負荷を抑えるため、これより短い間隔では確認しない
+const MIN_INTERVAL_SECONDS: u32 = 15;
+const MAX_SAMPLES: usize = 240;
+const MAX_AUTO_INVITE_MINUTES: u32 = 12 * 60;
+/// フレンド一覧を取り直すまでの時間。監視の間隔より短くし、同じ頃の確認ではすべての監視で1回の取得を使い回す
+const ROSTER_MAX_AGE: Duration = Duration::from_secs(10);
+
+/// 監視中のインスタンス。キーはロケーション
+static WATCHES: Lazy<Mutex<HashMap<String, WatchEntry>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 最後に取得したフレンド一覧。失敗したときも取り直すまでは失敗として使い回す
+static ROSTER: Lazy<Mutex<Option<Roster>>> = Lazy::new(|| Mutex::new(None));
+
+struct Roster {
+    user_id: Option<String>,
+    fetched_at: Instant,
+    friends: Option<Arc<Vec<RosterFriend>>>,
+}
+
+struct RosterFriend {
+    id: String,
+    display_name: String,
+    location: String,
+}
+
+struct WatchEntry {
+    watch: InstanceWatch,
+    task: JoinHandle<()>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct InstanceSample {
+    pub timestamp: String,
+    pub user_count: u32,
+    pub capacity: u32,
+    pub queue_size: u32,
+    pub closed_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct InstanceWatch {
+    pub location: String,
+    pub interval_seconds: u32,
+    pub started_at: String,
+    /// 古いものから順の取得結果。多くなったら古いものから消す
+    pub samples: Vec<InstanceSample>,
+    /// インスタンスにいるフレンドのID
+    pub friends: Vec<String>,
+    /// 自分が入れる空きがあるか
+    pub has_room: bool,
+    /// インスタンスが閉じられたか、無くなった。監視はそこで終わる
+    pub closed: bool,
+    pub auto_invite: Option<AutoInvite>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(tag = "type")]
+pub enum AutoInviteTrigger {
+    /// 自分が入れる空きがある
+    RoomAvailable,
+    /// 指定したフレンドがインスタンスにいる
+    #[serde(rename_all = "camelCase")]
+    FriendJoined { user_id: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+pub enum AutoInviteStatus {
+    /// 条件を満たすのを待っている
+    Pending,
+    Sent,
+    /// 招待に失敗した。再送はしない
+    Failed,
+    /// 条件を満たさないまま期限を過ぎた
+    Expired,
+    /// インスタンスが閉じたか、取り消された
+    Cancelled,
+}
+
+/// 条件を満たしたときに自分をインスタンスへ招待する。1回だけ送る
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoInvite {
+    pub trigger: AutoInviteTrigger,
+    pub expires_at: String,
+    pub status: AutoInviteStatus,
+    pub sent_at: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(tag = "type")]
+pub enum InstanceWatchEvent {
+    /// 満員か順番待ちだったインスタンスに入れるようになった
+    #[serde(rename_all = "camelCase")]
+    RoomAvailable {
+        location: String,
+        user_count: u32,
+        capacity: u32,
+    },
+    #[serde(rename_all = "camelCase")]
+    FriendJoined {
+        location: String,
+        user_id: String,
+        display_name: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Closed {
+        location: String,
+        closed_at: Option<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    AutoInviteSent { location: String },
+}
+
+/// 1回の確認結果
+struct Snapshot {
+    sample: InstanceSample,
+    has_room: bool,
+    /// インスタンスにいるフレンドのIDと表示名。フレンド一覧を取得できなかったときは `None`
+    friends: Option<Vec<(String, String)>>,
+    closed: bool,
+}
+
+fn as_u32(value: &serde_json::Value) -> u32 {
+    value.as_u64().unwrap_or_default().min(u32::MAX as u64) as u32
+}
+
+/// ログイン中のアカウントのフレンド一覧。新しい取得結果があればそれを使い、取得中は他の監視を待たせる
+async fn roster() -> Option<Arc<Vec<RosterFriend>>> {
+    let session = current_session();
+    let mut roster = ROSTER.lock().await;
+    if let Some(roster) = roster
+        .as_ref()
+        .filter(|roster| roster.user_id == session.user_id && roster.fetched_at.elapsed() < ROSTER_MAX_AGE)
+    {
+        return roster.friends.clone();
+    }
+
+    let friends = match fetch_all_friends(&session, false).await {
+        Ok(friends) => Some(Arc::new(
+            friends
+                .iter()
+                .filter_map(|friend| {
+                    Some(RosterFriend {
+                        id: friend["id"].as_str()?.to_string(),
+                        display_name: friend["displayName"].as_str().unwrap_or_default().to_string(),
+                        location: friend["location"].as_str().unwrap_or_default().to_string(),
+                    })
+                })
+                .collect(),
+        )),
+        Err(e) => {
+            warn!("Failed to fetch friends for instance watches: {:?}", e);
+            None
+        }
+    };
+    *roster = Some(Roster {
+        user_id: session.user_id.clone(),
+        fetched_at: Instant::now(),
+        friends: friends.clone(),
+    });
+    friends
+}
+
+fn friends_at(roster: &[RosterFriend], location: &str) -> Vec<(String, String)> {
+    roster
+        .iter()
+        .filter(|friend| friend.location == location)
+        .map(|friend| (friend.id.clone(), friend.display_name.clone()))
+        .collect()
+}
+
+async fn poll(location: &str) -> Result<Snapshot, RustError> {
+    let res = client()
+        .get(format!("{VRCHAT_API_BASE_URL}/1/instances/{location}"))
+        .send_with_reauth()
+        .await?;
+    let timestamp = Local::now().to_rfc3339();
+    // 誰もいなくなったインスタンスは消える
+    if res.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(Snapshot {
+            sample: InstanceSample {
+                timestamp: timestamp.clone(),
+                user_count: 0,
+                capacity: 0,
+                queue_size: 0,
+                closed_at: Some(timestamp),
+            },
+            has_room: false,
+            friends: Some(Vec::new()),
+            closed: true,
+        });
+    }
+    if res.status() != reqwest::StatusCode::OK {
+        return Err(res.status().into());
+    }
+    let instance: serde_json::Value = res.json().await?;
+
+    let sample = InstanceSample {
+        timestamp,
+        user_count: as_u32(&instance["userCount"]),
+        capacity: as_u32(&instance["capacity"]),
+        queue_size: as_u32(&instance["queueSize"]),
+        closed_at: instance["closedAt"].as_str().map(str::to_string),
+    };
+    // `hasCapacityForYou` が無い古い応答は人数で判断する
+    let has_capacity = instance["hasCapacityForYou"]
+        .as_bool()
+        .unwrap_or(sample.user_count < sample.capacity);
+    let has_room = has_capacity && sample.queue_size == 0 && sample.closed_at.is_none();
+
+    // フレンド一覧を取得できなくても人数・定員の記録は続ける
+    let friends = roster().await.map(|roster| friends_at(&roster, location));
+
+    Ok(Snapshot {
+        closed: sample.closed_at.is_some(),
+        sample,
+        has_room,
+        friends,
+    })
+}
+
+/// 前回の確認結果と比べて通知するイベント。最初の確認では何も通知しない。
+/// フレンドは、今回と前回の両方で一覧を取得できたときだけ比べる
+fn transitions(
+    location: &str,
+    previous: Option<&Snapshot>,
+    current: &Snapshot,
+) -> Vec<InstanceWatchEvent> {
+    let mut events = Vec::new();
+    if current.closed {
+        events.push(InstanceWatchEvent::Closed {
+            location: location.to_string(),
+            closed_at: current.sample.closed_at.clone(),
+        });
+        return events;
+    }
+    let Some(previous) = previous else {
+        return events;
+    };
+    if current.has_room && !previous.has_room {
+        events.push(InstanceWatchEvent::RoomAvailable {
+            location: location.to_string(),
+            user_count: current.sample.user_count,
+            capacity: current.sample.capacity,
+        });
+    }
+    let (Some(friends), Some(previous_friends)) = (&current.friends, &previous.friends) else {
+        return events;
+    };
+    for (user_id, display_name) in friends {
+        if !previous_friends.iter().any(|(id, _)| id == user_id) {
+            events.push(InstanceWatchEvent::FriendJoined {
+                location: location.to_string(),
+                user_id: user_id.clone(),
+                display_name: display_name.clone(),
+            });
+        }
+    }
+    events
+}
+
+impl InstanceWatch {
+    /// 確認結果を記録する。フレンド一覧を取得できなかった回は、前のフレンドを残す
+    fn record(&mut self, snapshot: &Snapshot) {
+        self.samples.push(snapshot.sample.clone());
+        if self.samples.len() > MAX_SAMPLES {
+            self.samples.drain(..self.samples.len() - MAX_SAMPLES);
+        }
+        if let Some(friends) = &snapshot.friends {
+            self.friends = friends.iter().map(|(id, _)| id.clone()).collect();
+        }
+        self.has_room = snapshot.has_room;
+        self.closed = snapshot.closed;
+    }
+}
+
+async fn record(location: &str, snapshot: &Snapshot) -> Option<InstanceWatch> {
+    let mut watches = WATCHES.lock().await;
+    let watch = &mut watches.get_mut(location)?.watch;
+    watch.record(snapshot);
+    Some(watch.clone())
+}
+
+impl AutoInvite {
+    fn is_expired(&self, now: DateTime<Local>) -> bool {
+        DateTime::parse_from_rfc3339(&self.expires_at)
+            .map(|expires_at| expires_at <= now)
+            .unwrap_or(true)
+    }
+
+    /// 待っている間に期限を過ぎていれば期限切れにする。変えたかを返す
+    fn expire(&mut self, now: DateTime<Local>) -> bool {
+        if self.status != AutoInviteStatus::Pending || !self.is_expired(now) {
+            return false;
+        }
+        self.status = AutoInviteStatus::Expired;
+        true
+    }
+
+    /// 確認結果が条件を満たしていれば、招待する前に送信済みにして `true` を返す。
+    /// 送信済み・失敗・期限切れ・取り消し済みなら何度確認しても `false` のまま
+    fn take(&mut self, snapshot: &Snapshot, now: DateTime<Local>) -> bool {
+        if self.status != AutoInviteStatus::Pending {
+            return false;
+        }
+        if self.expire(now) {
+            return false;
+        }
+        if snapshot.closed {
+            self.status = AutoInviteStatus::Cancelled;
+            return false;
+        }
+        let triggered = match &self.trigger {
+            AutoInviteTrigger::RoomAvailable => snapshot.has_room,
+            AutoInviteTrigger::FriendJoined { user_id } => {
+                snapshot.friends.iter().flatten().any(|(id, _)| id == user_id)
+            }
+        };
+        if triggered {
+            self.status = AutoInviteStatus::Sent;
+            self.sent_at = Some(now.to_rfc3339());
+        }
+        triggered
+    }
+
+    /// 招待に失敗した。再送はしない
+    fn fail(&mut self, error: &RustError) {
+        self.status = AutoInviteStatus::Failed;
+        self.error = Some(error.message().to_string());
+    }
+}
+
+/// 期限を過ぎた自動招待を期限切れにする。変えたときは監視の状態を返す
+async fn expire_auto_invite(location: &str) -> Option<InstanceWatch> {
+    let mut watches = WATCHES.lock().await;
+    let watch = &mut watches.get_mut(location)?.watch;
+    if !watch.auto_invite.as_mut()?.expire(Local::now()) {
+        return None;
+    }
+    info!("Auto invite to {} expired", location);
+    Some(watch.clone())
+}
+
+/// 条件を満たしていれば、招待する前に送信済みにする。
+/// ロックの中で状態を変えるので、確認が重なっても招待は1回しか送らない
+async fn take_auto_invite(location: &str, snapshot: &Snapshot) -> bool {
+    let mut watches = WATCHES.lock().await;
+    watches
+        .get_mut(location)
+        .and_then(|entry| entry.watch.auto_invite.as_mut())
+        .is_some_and(|auto_invite| auto_invite.take(snapshot, Local::now()))
+}
+
+async fn fail_auto_invite(location: &str, error: &RustError) {
+    let mut watches = WATCHES.lock().await;
+    if let Some(auto_invite) = watches
+        .get_mut(location)
+        .and_then(|entry| entry.watch.auto_invite.as_mut())
+    {
+        auto_invite.fail(error);
+    }
+}
+
+async fn watch_loop(
+    app_handle: tauri::AppHandle,
+    world_id: String,
+    instance_id: String,
+    interval: Duration,
+) {
+    let location = format!("{world_id}:{instance_id}");
+    let mut ticker = tokio::time::interval(interval);
+    let mut previous: Option<Snapshot> = None;
+
+    loop {
+        ticker.tick().await;
+        if let Some(watch) = expire_auto_invite(&location).await {
+            if let Err(e) = app_handle.emit(INSTANCE_WATCH_UPDATED_EVENT, &watch) {
+                warn!("Failed to emit instance watch update: {}", e);
+            }
+        }
+        if !is_login().await {
+            continue;
+        }
+
+        let mut snapshot = match poll(&location).await {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                warn!("Failed to poll watched instance {}: {:?}", location, e);
+                continue;
+            }
+        };
+        let mut events = transitions(&location, previous.as_ref(), &snapshot);
+
+        if take_auto_invite(&location, &snapshot).await {
+            info!("Sending auto invite to {}", location);
+            match invite_myself_to_instance(&world_id, &instance_id).await {
+                Ok(_) => events.push(InstanceWatchEvent::AutoInviteSent {
+                    location: location.clone(),
+                }),
+                Err(e) => {
+                    error!("Failed to send auto invite to {}: {:?}", location, e);
+                    fail_auto_invite(&location, &e).await;
+                }
+            }
+        }
+
+        let Some(watch) = record(&location, &snapshot).await else {
+            return;
+        };
+        if let Err(e) = app_handle.emit(INSTANCE_WATCH_UPDATED_EVENT, &watch) {
+            warn!("Failed to emit instance watch update: {}", e);
+        }
+        for event in &events {
+            debug!("Instance watch event: {:?}", event);
+            if let Err(e) = app_handle.emit(INSTANCE_WATCH_EVENT, event) {
+                warn!("Failed to emit instance watch event: {}", e);
+            }
+        }
+
+        if snapshot.closed {
+            info!("Watched instance {} closed", location);
+            return;
+        }
+        // フレンド一覧を取得できなかった回は、最後に分かった一覧と次回を比べる
+        if snapshot.friends.is_none() {
+            snapshot.friends = previous.and_then(|previous| previous.friends);
+        }
+        previous = Some(snapshot);
+    }
+}
+
+/// インスタンスを定期的に確認し、人数・定員・順番待ち・閉じた時刻を記録する。
+/// 空きができた・フレンドが入った・閉じたときに `instance-watch-event` を送る。
+/// 同じインスタンスは監視し直し、自動招待も解除される
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn watch_instance(
+    app_handle: tauri::AppHandle,
+    world_id: &str,
+    instance_id: &str,
+    interval_seconds: Option<u32>,
+) -> Result<InstanceWatch, RustError> {
+    debug!(
+        "Call watch_instance {:?} {:?} {:?}",
+        world_id, instance_id, interval_seconds
+    );
+
+    let location = format!("{world_id}:{instance_id}");
+    let interval_seconds = interval_seconds
+        .unwrap_or(DEFAULT_INTERVAL_SECONDS)
+        .max(MIN_INTERVAL_SECONDS);
+    let watch = InstanceWatch {
+        location: location.clone(),
+        interval_seconds,
+        started_at: Local::now().to_rfc3339(),
+        samples: Vec::new(),
+        friends: Vec::new(),
+        has_room: false,
+        closed: false,
+        auto_invite: None,
+    };
+
+    let mut watches = WATCHES.lock().await;
+    if let Some(previous) = watches.remove(&location) {
+        previous.task.abort();
+    }
+    let task = tauri::async_runtime::spawn(watch_loop(
+        app_handle,
+        world_id.to_string(),
+        instance_id.to_string(),
+        Duration::from_secs(interval_seconds as u64),
+    ));
+    watches.insert(
+        location,
+        WatchEntry {
+            watch: watch.clone(),
+            task,
+        },
+    );
+    Ok(watch)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn unwatch_instance(world_id: &str, instance_id: &str) -> Result<(), RustError> {
+    debug!("Call unwatch_instance {:?} {:?}", world_id, instance_id);
+
+    if let Some(entry) = WATCHES
+        .lock()
+        .await
+        .remove(&format!("{world_id}:{instance_id}"))
+    {
+        entry.task.abort();
+    }
+    Ok(())
+}
+
+/// 閉じて監視が終わったインスタンスも、解除するまで残る
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn get_instance_watches() -> Vec<InstanceWatch> {
+    debug!("Call get_instance_watches");
+
+    let watches = WATCHES.lock().await;
+    let mut watches: Vec<InstanceWatch> =
+        watches.values().map(|entry| entry.watch.clone()).collect();
+    watches.sort_by(|a, b| a.started_at.cmp(&b.started_at));
+    watches
+}
+
+/// 監視中のインスタンスに、条件を満たしたら自分を招待する自動招待を設定する。
+/// 前の自動招待は置き換え、期限までに条件を満たさなければ何もしない
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn set_auto_invite(
+    world_id: &str,
+    instance_id: &str,
+    trigger: AutoInviteTrigger,
+    timeout_minutes: u32,
+) -> Result<InstanceWatch, RustError> {
+    debug!(
+        "Call set_auto_invite {:?} {:?} {:?} {:?}",
+        world_id, instance_id, trigger, timeout_minutes
+    );
+
+    if timeout_minutes == 0 || timeout_minutes > MAX_AUTO_INVITE_MINUTES {
+        return Err("errors.invalidAutoInviteTimeout".into());
+    }
+    if matches!(&trigger, AutoInviteTrigger::FriendJoined { user_id } if user_id.is_empty()) {
+        return Err("errors.invalidAutoInviteTrigger".into());
+    }
+
+    let mut watches = WATCHES.lock().await;
+    let watch = &mut watches
+        .get_mut(&format!("{world_id}:{instance_id}"))
+        .ok_or("errors.instanceNotWatched")?
+        .watch;
+    if watch.closed {
+        return Err("errors.instanceNotWatched".into());
+    }
+    let expires_at = Local::now() + TimeDelta::minutes(timeout_minutes as i64);
+    watch.auto_invite = Some(AutoInvite {
+        trigger,
+        expires_at: expires_at.to_rfc3339(),
+        status: AutoInviteStatus::Pending,
+        sent_at: None,
+        error: None,
+    });
+    Ok(watch.clone())
+}
+
+/// 待っている自動招待を取り消す。送信済みなどの結果はそのまま残す
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn cancel_auto_invite(world_id: &str, instance_id: &str) -> Result<(), RustError> {
+    debug!("Call cancel_auto_invite {:?} {:?}", world_id, instance_id);
+
+    let mut watches = WATCHES.lock().await;
+    if let Some(auto_invite) = watches
+        .get_mut(&format!("{world_id}:{instance_id}"))
+        .and_then(|entry| entry.watch.auto_invite.as_mut())
+        .filter(|auto_invite| auto_invite.status == AutoInviteStatus::Pending)
+    {
+        auto_invite.status = AutoInviteStatus::Cancelled;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCATION: &str = "wrld_a:12345~region(jp)";
+
+    fn snapshot(has_room: bool, friends: Option<&[&str]>) -> Snapshot {
+        Snapshot {
+            sample: InstanceSample {
+                timestamp: "2025-01-01T20:00:00+09:00".to_string(),
+                user_count: if has_room { 15 } else { 16 },
+                capacity: 16,
+                queue_size: 0,
+                closed_at: None,
+            },
+            has_room,
+            friends: friends.map(|ids| ids.iter().map(|id| (id.to_string(), format!("Name of {id}"))).collect()),
+            closed: false,
+        }
+    }
+
+    fn closed() -> Snapshot {
+        Snapshot {
+            sample: InstanceSample {
+                closed_at: Some("2025-01-01T21:00:00+09:00".to_string()),
+                ..snapshot(false, None).sample
+            },
+            closed: true,
+            ..snapshot(false, Some(&[]))
+        }
+    }
+
+    fn names(events: &[InstanceWatchEvent]) -> Vec<String> {
+        events
+            .iter()
+            .map(|event| match event {
+                InstanceWatchEvent::RoomAvailable { user_count, capacity, .. } => {
+                    format!("room {user_count}/{capacity}")
+                }
+                InstanceWatchEvent::FriendJoined { user_id, display_name, .. } => {
+                    format!("joined {user_id} {display_name}")
+                }
+                InstanceWatchEvent::Closed { closed_at, .. } => format!("closed {closed_at:?}"),
+                InstanceWatchEvent::AutoInviteSent { .. } => "invite sent".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn first_poll_reports_nothing_unless_closed() {
+        assert!(transitions(LOCATION, None, &snapshot(true, Some(&["usr_a"]))).is_empty());
+        assert_eq!(
+            names(&transitions(LOCATION, None, &closed())),
+            ["closed Some(\"2025-01-01T21:00:00+09:00\")"]
+        );
+    }
+
+    #[test]
+    fn reports_room_only_when_it_opens_up() {
+        let full = snapshot(false, Some(&[]));
+        let open = snapshot(true, Some(&[]));
+        assert_eq!(names(&transitions(LOCATION, Some(&full), &open)), ["room 15/16"]);
+        assert!(transitions(LOCATION, Some(&open), &open).is_empty());
+        assert!(transitions(LOCATION, Some(&open), &full).is_empty());
+        assert!(transitions(LOCATION, Some(&full), &full).is_empty());
+    }
+
+    #[test]
+    fn reports_friends_who_were_not_there_before() {
+        let previous = snapshot(false, Some(&["usr_a"]));
+        let current = snapshot(false, Some(&["usr_a", "usr_b", "usr_c"]));
+        assert_eq!(
+            names(&transitions(LOCATION, Some(&previous), &current)),
+            ["joined usr_b Name of usr_b", "joined usr_c Name of usr_c"]
+        );
+        // 出て行ったフレンドは通知しない
+        assert!(transitions(LOCATION, Some(&current), &previous).is_empty());
+    }
+
+    #[test]
+    fn skips_friends_when_the_roster_is_unknown() {
+        let known = snapshot(false, Some(&["usr_a"]));
+        let unknown = snapshot(true, None);
+        // 空きは人数から分かるので通知する
+        assert_eq!(names(&transitions(LOCATION, Some(&known), &unknown)), ["room 15/16"]);
+        assert!(transitions(LOCATION, Some(&unknown), &snapshot(false, Some(&["usr_a", "usr_b"]))).is_empty());
+    }
+
+    #[test]
+    fn closing_reports_only_the_close() {
+        let previous = snapshot(false, Some(&[]));
+        let mut current = closed();
+        current.has_room = true;
+        current.friends = Some(vec![("usr_a".to_string(), "A".to_string())]);
+        assert_eq!(
+            names(&transitions(LOCATION, Some(&previous), &current)),
+            ["closed Some(\"2025-01-01T21:00:00+09:00\")"]
+        );
+    }
+
+    fn auto_invite(trigger: AutoInviteTrigger, now: DateTime<Local>) -> AutoInvite {
+        AutoInvite {
+            trigger,
+            expires_at: (now + TimeDelta::minutes(30)).to_rfc3339(),
+            status: AutoInviteStatus::Pending,
+            sent_at: None,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn sends_the_auto_invite_only_once() {
+        let now = Local::now();
+        let mut invite = auto_invite(AutoInviteTrigger::RoomAvailable, now);
+
+        assert!(!invite.take(&snapshot(false, Some(&[])), now));
+        assert_eq!(invite.status, AutoInviteStatus::Pending);
+
+        assert!(invite.take(&snapshot(true, Some(&[])), now));
+        assert_eq!(invite.status, AutoInviteStatus::Sent);
+        assert_eq!(invite.sent_at, Some(now.to_rfc3339()));
+        // 空きがあるままでも2回目以降は送らない
+        for _ in 0..3 {
+            assert!(!invite.take(&snapshot(true, Some(&[])), now));
+        }
+        assert_eq!(invite.status, AutoInviteStatus::Sent);
+    }
+
+    #[test]
+    fn expired_auto_invites_never_fire() {
+        let now = Local::now();
+        let later = now + TimeDelta::minutes(31);
+        let mut invite = auto_invite(AutoInviteTrigger::RoomAvailable, now);
+
+        assert!(!invite.expire(now));
+        assert!(!invite.take(&snapshot(true, Some(&[])), later));
+        assert_eq!(invite.status, AutoInviteStatus::Expired);
+        assert_eq!(invite.sent_at, None);
+        assert!(!invite.take(&snapshot(true, Some(&[])), now));
+
+        // 確認の前に期限切れにした場合も同じ
+        let mut invite = auto_invite(AutoInviteTrigger::RoomAvailable, now);
+        assert!(invite.expire(later));
+        assert!(!invite.expire(later));
+        assert!(!invite.take(&snapshot(true, Some(&[])), later));
+
+        // 期限が読めない自動招待は期限切れとみなす
+        let mut invite = AutoInvite {
+            expires_at: "broken".to_string(),
+            ..auto_invite(AutoInviteTrigger::RoomAvailable, now)
+        };
+        assert!(!invite.take(&snapshot(true, Some(&[])), now));
+        assert_eq!(invite.status, AutoInviteStatus::Expired);
+    }
+
+    #[test]
+    fn failed_auto_invites_are_not_retried() {
+        let now = Local::now();
+        let mut invite = auto_invite(AutoInviteTrigger::RoomAvailable, now);
+        assert!(invite.take(&snapshot(true, Some(&[])), now));
+
+        invite.fail(&RustError::from("HTTP 403 Forbidden"));
+        assert_eq!(invite.status, AutoInviteStatus::Failed);
+        assert_eq!(invite.error.as_deref(), Some("HTTP 403 Forbidden"));
+        assert!(!invite.take(&snapshot(true, Some(&[])), now));
+        // 失敗した結果は期限が来ても残す
+        assert!(!invite.expire(now + TimeDelta::minutes(31)));
+        assert_eq!(invite.status, AutoInviteStatus::Failed);
+    }
+
+    #[test]
+    fn friend_joined_fires_when_the_friend_is_in_the_instance() {
+        let now = Local::now();
+        let trigger = AutoInviteTrigger::FriendJoined {
+            user_id: "usr_b".to_string(),
+        };
+        let mut invite = auto_invite(trigger, now);
+
+        // 空きがあっても、指定したフレンドがいなければ送らない
+        assert!(!invite.take(&snapshot(true, Some(&["usr_a"])), now));
+        // フレンド一覧を取得できなかった回は判断しない
+        assert!(!invite.take(&snapshot(true, None), now));
+        assert_eq!(invite.status, AutoInviteStatus::Pending);
+
+        // 満員でもフレンドがいれば送る
+        assert!(invite.take(&snapshot(false, Some(&["usr_a", "usr_b"])), now));
+        assert_eq!(invite.status, AutoInviteStatus::Sent);
+    }
+
+    #[test]
+    fn closing_cancels_a_pending_auto_invite() {
+        let now = Local::now();
+        let mut invite = auto_invite(AutoInviteTrigger::RoomAvailable, now);
+        assert!(!invite.take(&closed(), now));
+        assert_eq!(invite.status, AutoInviteStatus::Cancelled);
+        assert!(!invite.take(&snapshot(true, Some(&[])), now));
+    }
+
+    #[test]
+    fn records_samples_and_keeps_friends_when_the_roster_is_unknown() {
+        let mut watch = InstanceWatch {
+            location: LOCATION.to_string(),
+            interval_seconds: DEFAULT_INTERVAL_SECONDS,
+            started_at: Local::now().to_rfc3339(),
+            samples: Vec::new(),
+            friends: Vec::new(),
+            has_room: false,
+            closed: false,
+            auto_invite: None,
+        };
+        watch.record(&snapshot(false, Some(&["usr_a"])));
+        watch.record(&snapshot(true, None));
+        assert_eq!(watch.samples.len(), 2);
+        assert_eq!(watch.friends, ["usr_a"]);
+        assert!(watch.has_room);
+
+        for _ in 0..MAX_SAMPLES {
+            watch.record(&snapshot(false, Some(&[])));
+        }
+        assert_eq!(watch.samples.len(), MAX_SAMPLES);
+        assert!(watch.friends.is_empty());
+
+        watch.record(&closed());
+        assert!(watch.closed);
+    }
+
+    #[test]
+    fn picks_friends_in_the_location_from_the_roster() {
+        let friend = |id: &str, location: &str| RosterFriend {
+            id: id.to_string(),
+            display_name: id.to_uppercase(),
+            location: location.to_string(),
+        };
+        let roster = [
+            friend("usr_a", LOCATION),
+            friend("usr_b", "wrld_a:12345~region(us)"),
+            friend("usr_c", "private"),
+            friend("usr_d", LOCATION),
+        ];
+        assert_eq!(
+            friends_at(&roster, LOCATION),
+            [("usr_a".to_string(), "USR_A".to_string()), ("usr_d".to_string(), "USR_D".to_string())]
+        );
+    }
+}
@@ -1,48 +1,40 @@
-use once_cell::sync::Lazy;
-use reqwest::Client;
-use reqwest_cookie_store::{CookieStore, CookieStoreMutex};
-use std::sync::Arc;
-use log::{debug, error, warn, LevelFilter};
+use log::{error, LevelFilter};
 use tauri::{Manager, WindowEvent};
-use tauri_plugin_store::StoreExt;
-use keyring::Entry;
 
+mod accounts;
+mod backup;
+mod cli;
 mod commands;
+mod cookie_vault;
+mod export;
+mod friend_events;
+mod friend_history;
+mod friends;
+mod headless;
+mod history;
+mod instance_watcher;
+mod local_api;
+mod log_import;
+mod notes;
+mod osc;
+mod presence;
+mod reauth;
+mod redaction;
+mod scheduler;
+mod screenshots;
+mod settings;
 mod structs;
+#[cfg(test)]
+mod test_util;
+mod totp;
+mod vrchat_log;
+mod webhooks;
 
-static COOKIE_STORE: Lazy<Arc<CookieStoreMutex>> = Lazy::new(|| {
-    // デフォルトのCookieStoreを作成
-    Arc::new(CookieStoreMutex::new(CookieStore::default()))
-});
+pub use cli::run as run_cli;
 
-static CLIENT: Lazy<Arc<Client>> = Lazy::new(|| {
-    let cookie_store = COOKIE_STORE.clone();
-    let client = Client::builder()
-        .cookie_provider(cookie_store)
-        .user_agent("VSM/1.0/meronmks.8914@gmail.com")
-        .build()
-        .unwrap();
-
-    Arc::new(client)
-});
-
-async fn save_cookies(app_handle: &tauri::AppHandle, user_id: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let cookie_store = COOKIE_STORE.lock()?;
-    let json_value = serde_json::to_string(&*cookie_store)?;
-    let entry = Entry::new(&app_handle.config().identifier, user_id)?;
-    entry.set_password(&json_value)?;
-    Ok(())
-}
-
-fn load_cookies(app_handle: &tauri::AppHandle, user_id: &str) -> Result<(), Box<dyn std::error::Error>> {
-    debug!("Loading cookies for user: {}", user_id);
-    let entry = Entry::new(&app_handle.config().identifier, user_id)?;
-    let cookie = entry.get_password()?;
-    let cookie_store = serde_json::from_str(&cookie)?;
-    let mut current_store = COOKIE_STORE.lock()?;
-    *current_store = cookie_store;
-
-    Ok(())
+/// GUIとCLIで同じ設定（identifier・ストアの場所）を使う
+fn context() -> tauri::Context {
+    tauri::generate_context!()
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -55,6 +47,8 @@ pub fn run() {
             tauri_plugin_log::Builder::new()
                 .clear_targets()
                 .timezone_strategy(tauri_plugin_log::TimezoneStrategy::UseLocal)
+                // 認証情報などを伏せてから出力する
+                .format(redaction::format_record)
                 .level(
                     #[cfg(debug_assertions)]
                     LevelFilter::Debug,
@@ -79,29 +73,26 @@ pub fn run() {
         .plugin(tauri_plugin_store::Builder::default().build())
         .invoke_handler(commands::handlers())
         .setup(move |app| {
-            let store = app.store("store.json").unwrap();
-
-            match store.get("user-data") {
-                Some(_) => {
-                    store.delete("user-data");
-                },
-                _ => {
-                    debug!("User data not found");
-                }
-            }
-            
-            match store.get("current-user-id") {
-                Some(user_id) if user_id.is_string() => {
-                    // アプリケーション起動時にCookieを読み込む
-                    if let Err(e) = load_cookies(&app.handle(), user_id.as_str().unwrap()) {
-                        error!("Failed to load cookies: {}", e);
-                    }
-                },
-                _ => {
-                    warn!("Current user ID is not set or not a string");
-                }
+            // 古い形式の設定を変換する
+            if let Err(e) = settings::migrate(app.handle()) {
+                error!("Failed to migrate settings: {:?}", e);
             }
 
+            redaction::load_policy(app.handle());
+            reauth::init(app.handle().clone());
+            local_api::init(app.handle().clone());
+            presence::init(app.handle().clone());
+
+            // アプリケーション起動時にCookieを読み込む
+            accounts::restore_session(app.handle());
+
+            tauri::async_runtime::spawn(accounts::run_cookie_persister(app.handle().clone()));
+            tauri::async_runtime::spawn(scheduler::run(app.handle().clone()));
+            tauri::async_runtime::spawn(history::run(app.handle().clone()));
+            tauri::async_runtime::spawn(friend_events::run());
+            tauri::async_runtime::spawn(osc::run(app.handle().clone()));
+            tauri::async_runtime::spawn(webhooks::run(app.handle().clone()));
+
             #[cfg(debug_assertions)]
             {
                 let window = app.get_webview_window("main").unwrap();
@@ -113,30 +104,19 @@ pub fn run() {
                 .plugin(tauri_plugin_updater::Builder::new().build())?;
             Ok(())
         })
-        .on_window_event(|window, event| match event {
-            WindowEvent::CloseRequested { .. } => {
+        .on_window_event(|window, event| {
+            if let WindowEvent::CloseRequested { .. } = event {
                 let app = window.app_handle();
                 // アプリケーション終了時にCookieを保存
-                tauri::async_runtime::block_on(async {
-                    let store = app.store("store.json").unwrap();
-                    match store.get("current-user-id") {
-                        Some(user_id) if user_id.is_string() => {
-                            if let Err(e) = save_cookies(&app, user_id.as_str().unwrap()).await {
-                                error!("Failed to save cookies: {}", e);
-                            }
-                        },
-                        _ => {
-                            debug!("Current user ID is not set or not a string");
-                        }
-                    }
-                });
-                let store = app.store("store.json").unwrap();
-                store.delete("instances-data");
-                let _ = store.save();
+                if let Err(e) = accounts::save_all_sessions(app) {
+                    error!("Failed to save cookies: {:?}", e);
+                }
+                if let Err(e) = settings::clear_session_cache(app) {
+                    error!("Failed to clear instances cache: {:?}", e);
+                }
             }
-            _ => {}
         })
         .plugin(tauri_plugin_shell::init())
-        .run(tauri::generate_context!())
+        .run(context())
         .expect("error while running tauri application");
 }
@@ -0,0 +1,786 @@
+use crate::accounts::current_session;
+use crate::commands::{get_current_user_json, invite_myself_to_instance, update_current_user_inner, RustError};
+use crate::export::collect_instances;
+use crate::friends::fetch_all_friends;
+use crate::presence;
+use crate::redaction::{register_secret, SecretKind};
+use crate::settings::{load_settings, Settings, SETTINGS_CHANGED_EVENT};
+use crate::structs::{CurrentUserUpdate, UserStatus};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use keyring::Entry;
+use log::{debug, error, info, warn};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::Duration;
+use tauri::async_runtime::JoinHandle;
+use tauri::Listener;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+
+const TOKEN_ENTRY: &str = "local-api-token";
+const TOKEN_BYTES: usize = 32;
+
+const MAX_HEADER_SIZE: usize = 8 * 1024;
+const MAX_BODY_SIZE: usize = 64 * 1024;
+/// ヘッダーと本文を読み終えるまでの制限時間
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// RESTのパスと、同じ処理をするJSON-RPCのメソッド
+const ROUTES: [(&str, &str, &str); 6] = [
+    ("GET", "/api/friends", "friends.list"),
+    ("GET", "/api/instances", "instances.list"),
+    ("GET", "/api/status", "status.get"),
+    ("POST", "/api/status", "status.set"),
+    ("POST", "/api/invite-myself", "invite.self"),
+    ("GET", "/api/presence", "presence.get"),
+];
+
+/// リクエストの認証に使うトークン。サーバーの起動時にキーリングから読み込む
+static TOKEN: Lazy<RwLock<Option<String>>> = Lazy::new(|| RwLock::new(None));
+
+static SERVER: Lazy<Mutex<ServerState>> = Lazy::new(|| Mutex::new(ServerState::default()));
+
+#[derive(Default)]
+struct ServerState {
+    task: Option<JoinHandle<()>>,
+    port: u16,
+    /// 最後に起動に失敗した理由
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalApiStatus {
+    pub enabled: bool,
+    pub running: bool,
+    pub port: u16,
+    /// 起動に失敗したときの理由（ポートが使用中など）
+    pub error: Option<String>,
+}
+
+enum ApiError {
+    MethodNotFound,
+    InvalidParams(String),
+    Failed(String),
+}
+
+impl ApiError {
+    fn rpc_code(&self) -> i32 {
+        match self {
+            ApiError::MethodNotFound => -32601,
+            ApiError::InvalidParams(_) => -32602,
+            ApiError::Failed(_) => -32000,
+        }
+    }
+
+    fn http_status(&self) -> u16 {
+        match self {
+            ApiError::MethodNotFound => 404,
+            ApiError::InvalidParams(_) => 400,
+            ApiError::Failed(_) => 502,
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            ApiError::MethodNotFound => "Method not found".to_string(),
+            ApiError::InvalidParams(message) | ApiError::Failed(message) => message.clone(),
+        }
+    }
+}
+
+impl From<RustError> for ApiError {
+    fn from(value: RustError) -> Self {
+        ApiError::Failed(value.message().to_string())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetStatusParams {
+    status: UserStatus,
+    status_description: Option<String>,
+}
+
+/// `location` か `worldId` と `instanceId` のどちらかで指定する
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InviteParams {
+    location: Option<String>,
+    world_id: Option<String>,
+    instance_id: Option<String>,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    body: Option<serde_json::Value>,
+}
+
+impl Response {
+    fn json(status: u16, body: serde_json::Value) -> Self {
+        Self { status, body: Some(body) }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self::json(status, json!({ "error": message }))
+    }
+
+    fn empty(status: u16) -> Self {
+        Self { status, body: None }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let body = self.body.as_ref().map(|body| body.to_string()).unwrap_or_default();
+        let reason = match self.status {
+            200 => "OK",
+            204 => "No Content",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            408 => "Request Timeout",
+            413 => "Payload Too Large",
+            431 => "Request Header Fields Too Large",
+            502 => "Bad Gateway",
+            _ => "Internal Server Error",
+        };
+        // オーバーレイをブラウザソースで表示できるようにCORSを許可する。認証はトークンで行う
+        format!(
+            "HTTP/1.1 {} {}\r\n\
+             Content-Type: application/json; charset=utf-8\r\n\
+             Content-Length: {}\r\n\
+             Access-Control-Allow-Origin: *\r\n\
+             Access-Control-Allow-Headers: Authorization, Content-Type\r\n\
+             Access-Control-Allow-Methods: GET, POST, OPTIONS\r\n\
+             Connection: close\r\n\r\n{}",
+            self.status,
+            reason,
+            body.len(),
+            body
+        )
+        .into_bytes()
+    }
+}
+
+fn token_entry(app_handle: &tauri::AppHandle) -> keyring::Result<Entry> {
+    Entry::new(&app_handle.config().identifier, TOKEN_ENTRY)
+}
+
+fn generate_token() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn store_token(app_handle: &tauri::AppHandle, token: &str) -> Result<(), RustError> {
+    token_entry(app_handle)?.set_password(token)?;
+    register_secret(SecretKind::Credential, token);
+    *TOKEN.write().unwrap() = Some(token.to_string());
+    Ok(())
+}
+
+/// キーリングのトークンを読み込む。まだ無ければ作る
+fn load_token(app_handle: &tauri::AppHandle) -> Result<String, RustError> {
+    let token = match token_entry(app_handle)?.get_password() {
+        Ok(token) => token,
+        Err(keyring::Error::NoEntry) => {
+            let token = generate_token();
+            store_token(app_handle, &token)?;
+            return Ok(token);
+        }
+        Err(e) => return Err(e.into()),
+    };
+    register_secret(SecretKind::Credential, &token);
+    *TOKEN.write().unwrap() = Some(token.clone());
+    Ok(token)
+}
+
+/// 長さ以外の情報が処理時間から漏れないように全体を比べる
+fn token_matches(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn is_authorized(request: &Request) -> bool {
+    let Some(expected) = TOKEN.read().unwrap().clone() else {
+        return false;
+    };
+    let bearer = request
+        .headers
+        .get("authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim);
+    // ヘッダーを付けられないブラウザソース向けにクエリでも受け付ける
+    let given = bearer.or(request.query.get("token").map(String::as_str));
+    given.is_some_and(|given| token_matches(given, &expected))
+}
+
+/// DNSリバインディングで外部のページから呼ばれないように、Hostがループバックのときだけ受け付ける
+fn is_local_host(request: &Request) -> bool {
+    let Some(host) = request.headers.get("host") else {
+        return false;
+    };
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if !port.is_empty() && port.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => host.as_str(),
+    };
+    // ホスト名は大文字小文字を区別しない。IPv6は `[::1]:39410` の形で届く
+    name.eq_ignore_ascii_case("localhost") || matches!(name, "127.0.0.1" | "[::1]")
+}
+
+pub(crate) fn friend_summary(friend: &serde_json::Value) -> serde_json::Value {
+    json!({
+        "id": friend["id"],
+        "displayName": friend["displayName"],
+        "status": friend["status"],
+        "statusDescription": friend["statusDescription"],
+        "location": friend["location"],
+        "platform": friend["platform"],
+    })
+}
+
+pub(crate) fn status_summary(user: &serde_json::Value) -> serde_json::Value {
+    json!({
+        "id": user["id"],
+        "displayName": user["displayName"],
+        "status": user["status"],
+        "statusDescription": user["statusDescription"],
+        "state": user["state"],
+    })
+}
+
+fn parse_params<T: for<'de> Deserialize<'de>>(params: serde_json::Value) -> Result<T, ApiError> {
+    serde_json::from_value(params).map_err(|e| ApiError::InvalidParams(e.to_string()))
+}
+
+async fn call(method: &str, params: serde_json::Value) -> Result<serde_json::Value, ApiError> {
+    match method {
+        "friends.list" => {
+            let friends = fetch_all_friends(&current_session(), false).await?;
+            Ok(friends.iter().map(friend_summary).collect())
+        }
+        "instances.list" => {
+            let instances = collect_instances(false).await?;
+            Ok(instances.iter().map(|instance| instance.to_json()).collect())
+        }
+        "status.get" => Ok(status_summary(&get_current_user_json().await?)),
+        // VRChatのログから分かる現在の場所。presence.json と同じ形式
+        "presence.get" => serde_json::to_value(presence::current()).map_err(|e| ApiError::Failed(e.to_string())),
+        "status.set" => {
+            let params: SetStatusParams = parse_params(params)?;
+            let update = CurrentUserUpdate {
+                status: Some(params.status),
+                status_description: params.status_description,
+                ..Default::default()
+            };
+            let res = update_current_user_inner(&update).await?;
+            let user: serde_json::Value = serde_json::from_str(&res).map_err(|e| ApiError::Failed(e.to_string()))?;
+            Ok(status_summary(&user))
+        }
+        "invite.self" => {
+            let params: InviteParams = parse_params(params)?;
+            let (world_id, instance_id) = match (params.location, params.world_id, params.instance_id) {
+                (Some(location), _, _) => match location.split_once(':') {
+                    Some((world_id, instance_id)) => (world_id.to_string(), instance_id.to_string()),
+                    None => return Err(ApiError::InvalidParams("Invalid location".to_string())),
+                },
+                (None, Some(world_id), Some(instance_id)) => (world_id, instance_id),
+                _ => return Err(ApiError::InvalidParams("location is required".to_string())),
+            };
+            let invited = invite_myself_to_instance(&world_id, &instance_id).await?;
+            Ok(json!({ "invited": invited }))
+        }
+        _ => Err(ApiError::MethodNotFound),
+    }
+}
+
+/// JSON-RPC 2.0。バッチには対応しない
+async fn handle_rpc(body: &[u8]) -> Response {
+    let Ok(message) = serde_json::from_slice::<serde_json::Value>(body) else {
+        return rpc_error(serde_json::Value::Null, -32700, "Parse error");
+    };
+    let id = message.get("id").cloned();
+    let method = match message["method"].as_str() {
+        Some(method) if message["jsonrpc"] == "2.0" => method,
+        _ => return rpc_error(id.unwrap_or_default(), -32600, "Invalid Request"),
+    };
+    let params = message.get("params").cloned().unwrap_or(json!({}));
+
+    let result = call(method, params).await;
+    // idの無い通知には結果を返さない
+    let Some(id) = id else {
+        return Response::empty(204);
+    };
+    match result {
+        Ok(result) => Response::json(200, json!({ "jsonrpc": "2.0", "id": id, "result": result })),
+        Err(e) => rpc_error(id, e.rpc_code(), &e.message()),
+    }
+}
+
+fn rpc_error(id: serde_json::Value, code: i32, message: &str) -> Response {
+    Response::json(
+        200,
+        json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }),
+    )
+}
+
+async fn handle_rest(request: &Request) -> Response {
+    let Some(&(_, _, method)) = ROUTES
+        .iter()
+        .find(|(method, path, _)| *method == request.method && *path == request.path)
+    else {
+        let status = if ROUTES.iter().any(|(_, path, _)| *path == request.path) { 405 } else { 404 };
+        return Response::error(status, "Not found");
+    };
+    let params = if request.body.is_empty() {
+        json!({})
+    } else {
+        match serde_json::from_slice(&request.body) {
+            Ok(params) => params,
+            Err(_) => return Response::error(400, "Invalid JSON"),
+        }
+    };
+    match call(method, params).await {
+        Ok(result) => Response::json(200, result),
+        Err(e) => Response::error(e.http_status(), &e.message()),
+    }
+}
+
+async fn route(request: Request) -> Response {
+    if !is_local_host(&request) {
+        return Response::error(403, "Forbidden");
+    }
+    // CORSのプリフライトにはトークンが付かない
+    if request.method == "OPTIONS" {
+        return Response::empty(204);
+    }
+    if !is_authorized(&request) {
+        return Response::error(401, "Unauthorized");
+    }
+    debug!("Local API {} {}", request.method, request.path);
+
+    if request.path == "/rpc" {
+        if request.method != "POST" {
+            return Response::error(405, "Method not allowed");
+        }
+        return handle_rpc(&request.body).await;
+    }
+    handle_rest(&request).await
+}
+
+async fn read_request(stream: &mut (impl AsyncRead + Unpin)) -> Result<Request, Response> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        let end = buffer.windows(4).position(|window| window == b"\r\n\r\n");
+        // 最後の読み込みで上限を超えた分も含めて、ヘッダーの大きさを確かめる
+        if end.unwrap_or(buffer.len()) > MAX_HEADER_SIZE {
+            return Err(Response::error(431, "Header too large"));
+        }
+        if let Some(position) = end {
+            break position;
+        }
+        let read = stream.read(&mut chunk).await.map_err(|_| Response::error(400, "Bad request"))?;
+        if read == 0 {
+            return Err(Response::error(400, "Bad request"));
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+
+    let head = std::str::from_utf8(&buffer[..header_end]).map_err(|_| Response::error(400, "Bad request"))?;
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else {
+        return Err(Response::error(400, "Bad request"));
+    };
+    let headers: HashMap<String, String> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+
+    let content_length = match headers.get("content-length") {
+        Some(value) => value.parse::<usize>().map_err(|_| Response::error(400, "Bad request"))?,
+        None => 0,
+    };
+    if content_length > MAX_BODY_SIZE {
+        return Err(Response::error(413, "Body too large"));
+    }
+    let mut body = buffer[header_end + 4..].to_vec();
+    while body.len() < content_length {
+        let read = stream.read(&mut chunk).await.map_err(|_| Response::error(400, "Bad request"))?;
+        if read == 0 {
+            return Err(Response::error(400, "Bad request"));
+        }
+        body.extend_from_slice(&chunk[..read]);
+    }
+    body.truncate(content_length);
+
+    Ok(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query,
+        headers,
+        body,
+    })
+}
+
+async fn handle_connection(mut stream: TcpStream) {
+    let response = match tokio::time::timeout(READ_TIMEOUT, read_request(&mut stream)).await {
+        Ok(Ok(request)) => route(request).await,
+        Ok(Err(response)) => response,
+        Err(_) => Response::error(408, "Request timeout"),
+    };
+    if let Err(e) = stream.write_all(&response.to_bytes()).await {
+        debug!("Failed to write local API response: {}", e);
+    }
+    let _ = stream.shutdown().await;
+}
+
+async fn serve(listener: TcpListener) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tauri::async_runtime::spawn(handle_connection(stream));
+            }
+            Err(e) => {
+                warn!("Failed to accept local API connection: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+    }
+}
+
+/// 設定に合わせてサーバーを起動・停止する。ポートが変わったら起動し直す
+async fn apply_settings(app_handle: &tauri::AppHandle, settings: &Settings) {
+    let mut state = SERVER.lock().await;
+    if settings.local_api_enabled && state.task.is_some() && state.port == settings.local_api_port {
+        return;
+    }
+    if let Some(task) = state.task.take() {
+        task.abort();
+        // 同じポートで起動し直せるように、待ち受けを閉じ終わるまで待つ
+        let _ = task.await;
+        info!("Stopped local API on port {}", state.port);
+    }
+    state.port = settings.local_api_port;
+    state.error = None;
+    if !settings.local_api_enabled {
+        return;
+    }
+
+    if let Err(e) = load_token(app_handle) {
+        error!("Failed to load local API token: {:?}", e);
+        state.error = Some(e.message().to_string());
+        return;
+    }
+    match TcpListener::bind(("127.0.0.1", settings.local_api_port)).await {
+        Ok(listener) => {
+            info!("Local API listening on 127.0.0.1:{}", settings.local_api_port);
+            state.task = Some(tauri::async_runtime::spawn(serve(listener)));
+        }
+        Err(e) => {
+            error!("Failed to start local API on port {}: {}", settings.local_api_port, e);
+            state.error = Some(e.to_string());
+        }
+    }
+}
+
+/// 起動時の設定でサーバーを起動し、設定が変わったら追従する
+pub(crate) fn init(app_handle: tauri::AppHandle) {
+    let handle = app_handle.clone();
+    app_handle.listen(SETTINGS_CHANGED_EVENT, move |event| {
+        let settings: Settings = match serde_json::from_str(event.payload()) {
+            Ok(settings) => settings,
+            Err(e) => {
+                error!("Failed to parse settings event: {}", e);
+                return;
+            }
+        };
+        let handle = handle.clone();
+        tauri::async_runtime::spawn(async move { apply_settings(&handle, &settings).await });
+    });
+
+    tauri::async_runtime::spawn(async move {
+        match load_settings(&app_handle) {
+            Ok(settings) => apply_settings(&app_handle, &settings).await,
+            Err(e) => error!("Failed to load settings for local API: {:?}", e),
+        }
+    });
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn get_local_api_status(app_handle: tauri::AppHandle) -> Result<LocalApiStatus, RustError> {
+    debug!("Call get_local_api_status");
+
+    let settings = load_settings(&app_handle)?;
+    let state = SERVER.lock().await;
+    Ok(LocalApiStatus {
+        enabled: settings.local_api_enabled,
+        running: state.task.is_some(),
+        port: if state.task.is_some() { state.port } else { settings.local_api_port },
+        error: state.error.clone(),
+    })
+}
+
+/// スクリプトなどに設定するトークン。まだ無ければ作る
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn get_local_api_token(app_handle: tauri::AppHandle) -> Result<String, RustError> {
+    debug!("Call get_local_api_token");
+
+    load_token(&app_handle)
+}
+
+/// トークンを作り直す。古いトークンを使っているクライアントはすぐに使えなくなる
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn regenerate_local_api_token(app_handle: tauri::AppHandle) -> Result<String, RustError> {
+    debug!("Call regenerate_local_api_token");
+
+    let token = generate_token();
+    store_token(&app_handle, &token)?;
+    info!("Regenerated local API token");
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// テストはすべて同じトークンを使う。並行して動いても値が変わらない
+    const TEST_TOKEN: &str = "0123456789abcdef0123456789abcdef";
+
+    fn use_test_token() {
+        *TOKEN.write().unwrap() = Some(TEST_TOKEN.to_string());
+    }
+
+    fn request(headers: &[(&str, &str)], query: &[(&str, &str)]) -> Request {
+        Request {
+            method: "GET".to_string(),
+            path: "/api/status".to_string(),
+            query: query.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            headers: headers.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            body: Vec::new(),
+        }
+    }
+
+    async fn parse(raw: &[u8]) -> Result<Request, u16> {
+        let mut reader = raw;
+        read_request(&mut reader).await.map_err(|response| response.status)
+    }
+
+    #[test]
+    fn token_matches_only_identical_tokens() {
+        assert!(token_matches(TEST_TOKEN, TEST_TOKEN));
+        assert!(!token_matches("0123456789abcdef0123456789abcdee", TEST_TOKEN));
+        assert!(!token_matches("0123", TEST_TOKEN));
+        assert!(!token_matches("", TEST_TOKEN));
+    }
+
+    #[test]
+    fn accepts_token_in_header_or_query() {
+        use_test_token();
+        let bearer = format!("Bearer {TEST_TOKEN}");
+        assert!(is_authorized(&request(&[("authorization", &bearer)], &[])));
+        assert!(is_authorized(&request(&[], &[("token", TEST_TOKEN)])));
+
+        assert!(!is_authorized(&request(&[], &[])));
+        assert!(!is_authorized(&request(&[("authorization", TEST_TOKEN)], &[])));
+        assert!(!is_authorized(&request(&[("authorization", "Bearer wrong")], &[])));
+        assert!(!is_authorized(&request(&[], &[("token", "wrong")])));
+    }
+
+    #[test]
+    fn accepts_only_loopback_hosts() {
+        let local = ["127.0.0.1", "127.0.0.1:39410", "localhost", "localhost:39410", "LocalHost:80", "[::1]", "[::1]:39410"];
+        for host in local {
+            assert!(is_local_host(&request(&[("host", host)], &[])), "{host}");
+        }
+        // DNSリバインディングでは攻撃者のドメイン名がHostに入る
+        let foreign = ["evil.example", "evil.example:39410", "localhost.evil.example", "127.0.0.1.nip.io", "[::2]:39410"];
+        for host in foreign {
+            assert!(!is_local_host(&request(&[("host", host)], &[])), "{host}");
+        }
+        assert!(!is_local_host(&request(&[], &[])));
+    }
+
+    #[tokio::test]
+    async fn reads_request_line_headers_and_body() {
+        let raw = b"POST /rpc?token=abc&x=1 HTTP/1.1\r\nHost: localhost:39410\r\nContent-Type: application/json\r\n\
+                    Content-Length: 7\r\n\r\n{\"a\":1}extra";
+        let request = parse(raw).await.unwrap();
+
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/rpc");
+        assert_eq!(request.query.get("token").map(String::as_str), Some("abc"));
+        assert_eq!(request.headers.get("host").map(String::as_str), Some("localhost:39410"));
+        assert_eq!(request.headers.get("content-type").map(String::as_str), Some("application/json"));
+        // Content-Length より後ろは読まない
+        assert_eq!(request.body, b"{\"a\":1}");
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_or_malformed_requests() {
+        let huge_header = format!("GET / HTTP/1.1\r\nX-Long: {}\r\n\r\n", "a".repeat(MAX_HEADER_SIZE + 1));
+        assert_eq!(parse(huge_header.as_bytes()).await.err(), Some(431));
+
+        let huge_body = format!("POST /rpc HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY_SIZE + 1);
+        assert_eq!(parse(huge_body.as_bytes()).await.err(), Some(413));
+
+        assert_eq!(parse(b"POST /rpc HTTP/1.1\r\nContent-Length: ten\r\n\r\n").await.err(), Some(400));
+        // 宣言より短いまま接続が切れた
+        assert_eq!(parse(b"POST /rpc HTTP/1.1\r\nContent-Length: 10\r\n\r\n{}").await.err(), Some(400));
+        // ヘッダーの終わりが来ないまま切れた
+        assert_eq!(parse(b"GET / HTTP/1.1\r\nHost: localhost").await.err(), Some(400));
+        assert_eq!(parse(b"GARBAGE\r\n\r\n").await.err(), Some(400));
+    }
+
+    fn api_request(method: &str, path: &str, body: &str) -> Request {
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: HashMap::new(),
+            headers: HashMap::new(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    async fn rest(method: &str, path: &str, body: &str) -> Response {
+        handle_rest(&api_request(method, path, body)).await
+    }
+
+    #[tokio::test]
+    async fn rest_distinguishes_unknown_paths_from_wrong_methods() {
+        // パスはあるがメソッドが違う
+        for (method, path) in [("DELETE", "/api/status"), ("POST", "/api/friends"), ("GET", "/api/invite-myself")] {
+            assert_eq!(rest(method, path, "").await.status, 405, "{method} {path}");
+        }
+        for (method, path) in [("GET", "/api/nowhere"), ("GET", "/status"), ("POST", "/api/status/extra")] {
+            assert_eq!(rest(method, path, "").await.status, 404, "{method} {path}");
+        }
+
+        // ルートに合えば本文を読んで呼び出す。ここまではVRChatに問い合わせない
+        assert_eq!(rest("POST", "/api/status", "{").await.status, 400);
+        let invalid = rest("POST", "/api/status", r#"{"status": "sleeping"}"#).await;
+        assert_eq!(invalid.status, 400);
+        assert!(invalid.body.unwrap()["error"].is_string());
+        assert_eq!(rest("GET", "/api/presence", "").await.status, 200);
+    }
+
+    async fn rpc(body: &str) -> Response {
+        handle_rpc(body.as_bytes()).await
+    }
+
+    fn rpc_error_of(response: &Response) -> (serde_json::Value, i64) {
+        assert_eq!(response.status, 200);
+        let body = response.body.as_ref().unwrap();
+        assert_eq!(body["jsonrpc"], "2.0");
+        (body["id"].clone(), body["error"]["code"].as_i64().unwrap())
+    }
+
+    #[tokio::test]
+    async fn rpc_reports_protocol_errors() {
+        assert_eq!(rpc_error_of(&rpc("{").await), (serde_json::Value::Null, -32700));
+        assert_eq!(rpc_error_of(&rpc("").await), (serde_json::Value::Null, -32700));
+
+        for invalid in [
+            r#"{"jsonrpc": "1.0", "id": 1, "method": "status.get"}"#,
+            r#"{"id": 1, "method": "status.get"}"#,
+            r#"{"jsonrpc": "2.0", "id": 1}"#,
+            r#"{"jsonrpc": "2.0", "id": 1, "method": 5}"#,
+        ] {
+            assert_eq!(rpc_error_of(&rpc(invalid).await), (json!(1), -32600), "{invalid}");
+        }
+        // バッチには対応しない
+        assert_eq!(rpc_error_of(&rpc("[]").await), (serde_json::Value::Null, -32600));
+
+        let unknown = rpc(r#"{"jsonrpc": "2.0", "id": "a", "method": "friends.delete"}"#).await;
+        assert_eq!(rpc_error_of(&unknown), (json!("a"), -32601));
+        let invalid_params = rpc(r#"{"jsonrpc": "2.0", "id": 2, "method": "invite.self", "params": {}}"#).await;
+        assert_eq!(rpc_error_of(&invalid_params), (json!(2), -32602));
+    }
+
+    #[tokio::test]
+    async fn rpc_answers_requests_but_not_notifications() {
+        let response = rpc(r#"{"jsonrpc": "2.0", "id": 7, "method": "presence.get"}"#).await;
+        assert_eq!(response.status, 200);
+        let body = response.body.unwrap();
+        assert_eq!(body["id"], 7);
+        assert!(body.get("result").is_some() && body.get("error").is_none(), "{body}");
+
+        // idの無い通知は、失敗しても結果を返さない
+        for notification in [
+            r#"{"jsonrpc": "2.0", "method": "presence.get"}"#,
+            r#"{"jsonrpc": "2.0", "method": "friends.delete"}"#,
+        ] {
+            let response = rpc(notification).await;
+            assert_eq!(response.status, 204, "{notification}");
+            assert!(response.body.is_none());
+        }
+    }
+
+    async fn send(port: u16, raw: String) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        stream.write_all(raw.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn server_checks_host_and_token() {
+        use_test_token();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(serve(listener));
+
+        let without_token = send(port, format!("GET /api/status HTTP/1.1\r\nHost: 127.0.0.1:{port}\r\n\r\n")).await;
+        assert!(without_token.starts_with("HTTP/1.1 401 "), "{without_token}");
+
+        let foreign_host = send(
+            port,
+            format!(
+                "GET /api/status HTTP/1.1\r\nHost: evil.example:{port}\r\nAuthorization: Bearer {TEST_TOKEN}\r\n\r\n"
+            ),
+        )
+        .await;
+        assert!(foreign_host.starts_with("HTTP/1.1 403 "), "{foreign_host}");
+
+        // 認証を通ればルーティングまで進む
+        let unknown = send(
+            port,
+            format!("GET /nowhere?token={TEST_TOKEN} HTTP/1.1\r\nHost: localhost:{port}\r\n\r\n"),
+        )
+        .await;
+        assert!(unknown.starts_with("HTTP/1.1 404 "), "{unknown}");
+        assert!(unknown.ends_with(r#"{"error":"Not found"}"#), "{unknown}");
+
+        server.abort();
+    }
+}
@@ -0,0 +1,196 @@
+use crate::commands::RustError;
+use crate::history::{build_visits, upsert_visits, InstanceVisit, HISTORY_STORE};
+use crate::vrchat_log::{find_log_files, parse_log_file};
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tauri::Emitter;
+use tauri_plugin_store::StoreExt;
+use tokio::task::JoinSet;
+
+const IMPORTED_FILES_KEY: &str = "imported-files";
+pub(crate) const IMPORT_PROGRESS_EVENT: &str = "vrchat-log-import-progress";
+
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct LogImportProgress {
+    pub processed: u32,
+    pub total: u32,
+    pub file_name: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct LogImportSummary {
+    pub files: u32,
+    /// 前回から変わっていないため読み飛ばしたファイル数
+    pub skipped_files: u32,
+    pub failed_files: u32,
+    pub visits: u32,
+    /// 既存の履歴に無かった滞在の数
+    pub new_visits: u32,
+    pub players: u32,
+}
+
+fn file_name(path: &std::path::Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// 取り込み済みのファイル名とサイズ。サイズが変わっていなければ再度読み込まない
+fn load_imported_files(app_handle: &tauri::AppHandle) -> Result<HashMap<String, u64>, RustError> {
+    let store = app_handle.store(HISTORY_STORE)?;
+    match store.get(IMPORTED_FILES_KEY) {
+        Some(value) => Ok(serde_json::from_value(value)?),
+        None => Ok(HashMap::new()),
+    }
+}
+
+/// 前回と同じサイズのファイルは読み飛ばし、それ以外を取り込み対象として返す
+fn pending_files(files: Vec<(PathBuf, u64)>, imported: &HashMap<String, u64>) -> Vec<(PathBuf, u64)> {
+    files
+        .into_iter()
+        .filter(|(path, size)| imported.get(&file_name(path)) != Some(size))
+        .collect()
+}
+
+fn save_imported_files(app_handle: &tauri::AppHandle, files: &HashMap<String, u64>) -> Result<(), RustError> {
+    let store = app_handle.store(HISTORY_STORE)?;
+    store.set(IMPORTED_FILES_KEY, serde_json::to_value(files)?);
+    store.save()?;
+    Ok(())
+}
+
+/// 過去のログファイルをまとめて解析し、インスタンス履歴に取り込む
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn import_vrchat_logs(
+    app_handle: tauri::AppHandle,
+    dir: String,
+    force: bool,
+) -> Result<LogImportSummary, RustError> {
+    debug!("Call import_vrchat_logs {:?} {:?}", dir, force);
+
+    let dir = PathBuf::from(dir);
+    if !dir.is_dir() {
+        return Err("errors.logDirNotFound".into());
+    }
+
+    let mut imported = if force {
+        HashMap::new()
+    } else {
+        load_imported_files(&app_handle)?
+    };
+    let mut summary = LogImportSummary::default();
+    let mut files: Vec<(PathBuf, u64)> = Vec::new();
+    for path in find_log_files(&dir)? {
+        let size = std::fs::metadata(&path)?.len();
+        files.push((path, size));
+    }
+    summary.files = files.len() as u32;
+    let targets = pending_files(files, &imported);
+    summary.skipped_files = summary.files - targets.len() as u32;
+
+    let total = targets.len() as u32;
+    let parallelism = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4);
+    let mut pending = targets.into_iter();
+    let mut tasks = JoinSet::new();
+    let mut visits: Vec<InstanceVisit> = Vec::new();
+    let mut processed = 0;
+
+    loop {
+        while tasks.len() < parallelism {
+            let Some((path, size)) = pending.next() else {
+                break;
+            };
+            tasks.spawn_blocking(move || {
+                let result = parse_log_file(&path).map(build_visits);
+                (path, size, result)
+            });
+        }
+        let Some(joined) = tasks.join_next().await else {
+            break;
+        };
+        let (path, size, result) = joined?;
+        processed += 1;
+
+        match result {
+            Ok(file_visits) => {
+                imported.insert(file_name(&path), size);
+                visits.extend(file_visits);
+            }
+            Err(e) => {
+                error!("Failed to parse VRChat log {:?}: {}", path, e);
+                summary.failed_files += 1;
+            }
+        }
+
+        let progress = LogImportProgress {
+            processed,
+            total,
+            file_name: file_name(&path),
+        };
+        if let Err(e) = app_handle.emit(IMPORT_PROGRESS_EVENT, progress) {
+            error!("Failed to emit import progress: {}", e);
+        }
+    }
+
+    summary.visits = visits.len() as u32;
+    summary.players = visits.iter().map(|v| v.players.len() as u32).sum();
+    summary.new_visits = upsert_visits(&app_handle, visits).await? as u32;
+    save_imported_files(&app_handle, &imported)?;
+
+    info!("Imported VRChat logs: {:?}", summary);
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use crate::history::VisitIndex;
+
+    const FIXTURE: &str = "tests/fixtures/output_log_2024-01-15_21-00-00.txt";
+
+    #[test]
+    fn skips_files_imported_with_the_same_size() {
+        let imported = HashMap::from([
+            ("output_log_a.txt".to_string(), 100),
+            ("output_log_b.txt".to_string(), 100),
+        ]);
+        let files = vec![
+            (PathBuf::from("logs/output_log_a.txt"), 100),
+            (PathBuf::from("logs/output_log_b.txt"), 250),
+            (PathBuf::from("logs/output_log_c.txt"), 10),
+        ];
+        let pending: Vec<String> = pending_files(files, &imported)
+            .iter()
+            .map(|(path, _)| file_name(path))
+            .collect();
+        assert_eq!(pending, vec!["output_log_b.txt", "output_log_c.txt"]);
+    }
+
+    #[test]
+    fn finds_and_parses_log_files_in_a_directory() {
+        let dir = TempDir::new("import");
+        std::fs::copy(FIXTURE, dir.join("output_log_2024-01-15_21-00-00.txt")).unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a log").unwrap();
+
+        let files = find_log_files(&dir).unwrap();
+        assert_eq!(files.len(), 1);
+        let visits = build_visits(parse_log_file(&files[0]).unwrap());
+        assert_eq!(visits.len(), 2);
+    }
+
+    #[test]
+    fn importing_the_same_log_twice_adds_nothing() {
+        let visits = build_visits(parse_log_file(std::path::Path::new(FIXTURE)).unwrap());
+        let mut index = VisitIndex::default();
+        assert_eq!(index.merge(visits.clone()), (0, 2));
+        assert_eq!(index.merge(visits), (0, 0));
+    }
+}
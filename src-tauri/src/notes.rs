@@ -0,0 +1,240 @@
+use crate::commands::{RustError, VRCHAT_API_BASE_URL};
+use crate::accounts::client;
+use crate::reauth::SendWithReauth;
+use chrono::Local;
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use tauri_plugin_store::StoreExt;
+
+pub(crate) const NOTES_STORE: &str = "notes.json";
+const NOTES_KEY: &str = "users";
+const TAG_MAX_LENGTH: usize = 32;
+const OFFICIAL_NOTE_MAX_LENGTH: usize = 256;
+const USER_NOTES_PAGE_SIZE: usize = 100;
+
+/// フレンドごとのローカルメモとタグ。公式のユーザーメモも同期して保持する
+#[derive(Debug, Clone, Default, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase", default)]
+pub struct UserNote {
+    pub note: String,
+    pub tags: Vec<String>,
+    pub official_note: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+pub(crate) fn load_notes(app_handle: &tauri::AppHandle) -> Result<HashMap<String, UserNote>, RustError> {
+    let store = app_handle.store(NOTES_STORE)?;
+    match store.get(NOTES_KEY) {
+        Some(value) => Ok(serde_json::from_value(value)?),
+        None => Ok(HashMap::new()),
+    }
+}
+
+fn save_notes(app_handle: &tauri::AppHandle, notes: &HashMap<String, UserNote>) -> Result<(), RustError> {
+    let store = app_handle.store(NOTES_STORE)?;
+    store.set(NOTES_KEY, serde_json::to_value(notes)?);
+    store.save()?;
+    Ok(())
+}
+
+fn update_note<F>(app_handle: &tauri::AppHandle, user_id: &str, f: F) -> Result<UserNote, RustError>
+where
+    F: FnOnce(&mut UserNote),
+{
+    let mut notes = load_notes(app_handle)?;
+    let note = notes.entry(user_id.to_string()).or_default();
+    f(note);
+    note.updated_at = Some(Local::now().to_rfc3339());
+    let note = note.clone();
+
+    // 中身が空になったエントリは残さない
+    notes.retain(|_, n| !n.note.is_empty() || !n.tags.is_empty() || n.official_note.is_some());
+    save_notes(app_handle, &notes)?;
+    Ok(note)
+}
+
+/// 前後の空白を除き、空文字と重複を取り除く
+fn normalize_tags(tags: Vec<String>) -> Result<Vec<String>, RustError> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim().to_string();
+        if tag.is_empty() || normalized.contains(&tag) {
+            continue;
+        }
+        if tag.chars().count() > TAG_MAX_LENGTH {
+            return Err("errors.tagTooLong".into());
+        }
+        normalized.push(tag);
+    }
+    Ok(normalized)
+}
+
+async fn post_official_note(user_id: &str, note: &str) -> Result<(), RustError> {
+    if note.chars().count() > OFFICIAL_NOTE_MAX_LENGTH {
+        return Err("errors.userNoteTooLong".into());
+    }
+
+    let client = client();
+
+    let res = client
+        .post(format!("{VRCHAT_API_BASE_URL}/1/userNotes"))
+        .json(&json!({"targetUserId": user_id, "note": note}))
+        .send_with_reauth()
+        .await?;
+
+    match res.status() {
+        reqwest::StatusCode::OK => Ok(()),
+        _ => {
+            error!("Failed to update user note {:?}", res);
+            Err(res.status().into())
+        }
+    }
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn get_user_notes(app_handle: tauri::AppHandle) -> Result<HashMap<String, UserNote>, RustError> {
+    debug!("Call get_user_notes");
+
+    load_notes(&app_handle)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn set_user_note(
+    app_handle: tauri::AppHandle,
+    user_id: &str,
+    note: &str,
+    sync_official: bool,
+) -> Result<UserNote, RustError> {
+    debug!("Call set_user_note {:?} {:?}", user_id, sync_official);
+
+    if sync_official {
+        post_official_note(user_id, note).await?;
+    }
+
+    update_note(&app_handle, user_id, |n| {
+        n.note = note.to_string();
+        if sync_official {
+            n.official_note = Some(note.to_string());
+        }
+    })
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn set_user_tags(
+    app_handle: tauri::AppHandle,
+    user_id: &str,
+    tags: Vec<String>,
+) -> Result<UserNote, RustError> {
+    debug!("Call set_user_tags {:?} {:?}", user_id, tags);
+
+    let tags = normalize_tags(tags)?;
+    update_note(&app_handle, user_id, |n| n.tags = tags)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn get_all_tags(app_handle: tauri::AppHandle) -> Result<Vec<String>, RustError> {
+    debug!("Call get_all_tags");
+
+    let mut tags: Vec<String> = load_notes(&app_handle)?
+        .into_values()
+        .flat_map(|n| n.tags)
+        .collect();
+    tags.sort();
+    tags.dedup();
+    Ok(tags)
+}
+
+/// `/userNotes` から公式のユーザーメモを取得してローカルに反映する。同期した件数を返す
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn sync_user_notes(app_handle: tauri::AppHandle) -> Result<u32, RustError> {
+    debug!("Call sync_user_notes");
+
+    let client = client();
+    let mut official: HashMap<String, String> = HashMap::new();
+    let mut offset = 0;
+
+    loop {
+        let res = client
+            .get(format!("{VRCHAT_API_BASE_URL}/1/userNotes"))
+            .query(&[("n", USER_NOTES_PAGE_SIZE), ("offset", offset)])
+            .send_with_reauth()
+            .await?;
+
+        if res.status() != reqwest::StatusCode::OK {
+            error!("Failed to get user notes {:?}", res);
+            return Err(res.status().into());
+        }
+
+        let page: Vec<serde_json::Value> = res.json().await?;
+        for note in &page {
+            if let (Some(user_id), Some(text)) = (note["targetUserId"].as_str(), note["note"].as_str()) {
+                official.insert(user_id.to_string(), text.to_string());
+            }
+        }
+        if page.len() < USER_NOTES_PAGE_SIZE {
+            break;
+        }
+        offset += USER_NOTES_PAGE_SIZE;
+    }
+    let synced = official.len() as u32;
+
+    let mut notes = load_notes(&app_handle)?;
+    let now = Local::now().to_rfc3339();
+    // 公式のメモは `official_note` にだけ持ち、ローカルのメモには書き写さない
+    for (user_id, note) in notes.iter_mut() {
+        let text = official.remove(user_id);
+        if note.official_note != text {
+            note.official_note = text;
+            note.updated_at = Some(now.clone());
+        }
+    }
+    for (user_id, text) in official {
+        notes.insert(
+            user_id,
+            UserNote {
+                official_note: Some(text),
+                updated_at: Some(now.clone()),
+                ..Default::default()
+            },
+        );
+    }
+    notes.retain(|_, n| !n.note.is_empty() || !n.tags.is_empty() || n.official_note.is_some());
+    save_notes(&app_handle, &notes)?;
+
+    Ok(synced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn tags_are_trimmed_and_deduplicated() {
+        let normalized = normalize_tags(tags(&["  Friend ", "VRC", "Friend", "", "   ", "vrc"])).unwrap();
+
+        // 大文字小文字が違うタグは別のタグとして残す
+        assert_eq!(normalized, tags(&["Friend", "VRC", "vrc"]));
+        assert!(normalize_tags(Vec::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn tags_longer_than_the_limit_are_rejected() {
+        let max = "あ".repeat(TAG_MAX_LENGTH);
+        assert_eq!(normalize_tags(vec![format!(" {max} ")]).unwrap(), [max.as_str()]);
+
+        let error = normalize_tags(vec![format!("{max}あ")]).unwrap_err();
+        assert_eq!(error.message(), "errors.tagTooLong");
+    }
+}
@@ -0,0 +1,365 @@
+use crate::commands::RustError;
+use crate::friend_events::{self, FriendEvent, FriendEventKind};
+use log::{debug, error, info, warn};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tauri_plugin_store::StoreExt;
+use tokio::net::UdpSocket;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Notify;
+
+pub(crate) const OSC_STORE: &str = "osc.json";
+const CONFIG_KEY: &str = "config";
+
+/// 設定が保存されたことを送信タスクに知らせる
+static CONFIG_CHANGED: Lazy<Notify> = Lazy::new(Notify::new);
+
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(tag = "type")]
+pub enum OscArgument {
+    Bool { value: bool },
+    Int { value: i32 },
+    Float { value: f32 },
+    /// `{displayName}` などのテンプレートを使える
+    String { value: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct OscMessageTemplate {
+    pub event: FriendEventKind,
+    /// 送信先のアドレス。`{displayName}` などのテンプレートを使える
+    pub address: String,
+    pub arguments: Vec<OscArgument>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct OscConfig {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    pub messages: Vec<OscMessageTemplate>,
+}
+
+impl Default for OscConfig {
+    /// VRChatのOSCの受信ポートに、チャットボックスへの表示を送る
+    fn default() -> Self {
+        let chatbox = |event, text: &str| OscMessageTemplate {
+            event,
+            address: "/chatbox/input".to_string(),
+            arguments: vec![
+                OscArgument::String { value: text.to_string() },
+                // キーボードを開かずにすぐ表示し、通知音は鳴らさない
+                OscArgument::Bool { value: true },
+                OscArgument::Bool { value: false },
+            ],
+        };
+        Self {
+            enabled: false,
+            host: "127.0.0.1".to_string(),
+            port: 9000,
+            messages: vec![
+                chatbox(FriendEventKind::FavoriteOnline, "{displayName} is online"),
+                chatbox(FriendEventKind::FriendJoinedInstance, "{displayName} joined"),
+                chatbox(FriendEventKind::InviteReceived, "Invite from {displayName}: {worldName}"),
+            ],
+        }
+    }
+}
+
+/// OSC 1.0の文字列。NUL終端して4バイト境界まで埋める
+fn push_padded(buffer: &mut Vec<u8>, value: &str) {
+    buffer.extend_from_slice(value.as_bytes());
+    let padding = 4 - value.len() % 4;
+    buffer.extend(std::iter::repeat_n(0, padding));
+}
+
+/// テンプレートを展開済みの値から1つのOSCメッセージを組み立てる
+pub(crate) fn encode_message(address: &str, arguments: &[OscArgument]) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    push_padded(&mut buffer, address);
+
+    let mut type_tags = ",".to_string();
+    let mut data = Vec::new();
+    for argument in arguments {
+        match argument {
+            OscArgument::Bool { value } => type_tags.push(if *value { 'T' } else { 'F' }),
+            OscArgument::Int { value } => {
+                type_tags.push('i');
+                data.extend_from_slice(&value.to_be_bytes());
+            }
+            OscArgument::Float { value } => {
+                type_tags.push('f');
+                data.extend_from_slice(&value.to_be_bytes());
+            }
+            OscArgument::String { value } => {
+                type_tags.push('s');
+                push_padded(&mut data, value);
+            }
+        }
+    }
+    push_padded(&mut buffer, &type_tags);
+    buffer.extend(data);
+    buffer
+}
+
+/// `{name}` を値に置き換える。知らない名前はそのまま残す
+fn render(template: &str, variables: &[(&str, String)]) -> String {
+    friend_events::render_template(template, variables, str::to_string)
+}
+
+fn render_message(template: &OscMessageTemplate, variables: &[(&str, String)]) -> Vec<u8> {
+    let arguments: Vec<OscArgument> = template
+        .arguments
+        .iter()
+        .map(|argument| match argument {
+            OscArgument::String { value } => OscArgument::String {
+                value: render(value, variables),
+            },
+            argument => argument.clone(),
+        })
+        .collect();
+    encode_message(&render(&template.address, variables), &arguments)
+}
+
+/// 組み立てたメッセージをまとめて送る。送った数を返す
+pub(crate) async fn send_packets(host: &str, port: u16, packets: &[Vec<u8>]) -> Result<u32, RustError> {
+    let target = tokio::net::lookup_host((host, port))
+        .await?
+        .next()
+        .ok_or("errors.oscHostNotFound")?;
+    let bind = if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = UdpSocket::bind(bind).await?;
+    for packet in packets {
+        socket.send_to(packet, target).await?;
+    }
+    Ok(packets.len() as u32)
+}
+
+async fn send_event(config: &OscConfig, event: &FriendEvent) -> Result<u32, RustError> {
+    let variables = event.variables();
+    let packets: Vec<Vec<u8>> = config
+        .messages
+        .iter()
+        .filter(|template| event.matches(template.event))
+        .map(|template| render_message(template, &variables))
+        .collect();
+    if packets.is_empty() {
+        return Ok(0);
+    }
+    send_packets(&config.host, config.port, &packets).await
+}
+
+fn validate_config(config: &OscConfig) -> Result<(), RustError> {
+    if config.host.trim().is_empty() || config.port == 0 {
+        return Err("errors.invalidOscTarget".into());
+    }
+    if config.messages.iter().any(|message| !message.address.starts_with('/')) {
+        return Err("errors.invalidOscAddress".into());
+    }
+    Ok(())
+}
+
+fn load_config(app_handle: &tauri::AppHandle) -> Result<OscConfig, RustError> {
+    let store = app_handle.store(OSC_STORE)?;
+    match store.get(CONFIG_KEY) {
+        Some(value) => Ok(serde_json::from_value(value)?),
+        None => Ok(OscConfig::default()),
+    }
+}
+
+/// 有効な間はフレンドのイベントを購読し、設定されたOSCメッセージを送る
+pub(crate) async fn run(app_handle: tauri::AppHandle) {
+    loop {
+        let config = match load_config(&app_handle) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("Failed to load OSC config: {:?}", e);
+                OscConfig::default()
+            }
+        };
+        if !config.enabled {
+            CONFIG_CHANGED.notified().await;
+            continue;
+        }
+
+        info!("Sending OSC messages to {}:{}", config.host, config.port);
+        let mut events = friend_events::subscribe();
+        loop {
+            tokio::select! {
+                _ = CONFIG_CHANGED.notified() => break,
+                event = events.recv() => match event {
+                    Ok(event) => match send_event(&config, &event).await {
+                        Ok(0) => {}
+                        Ok(sent) => debug!("Sent {} OSC messages", sent),
+                        Err(e) => warn!("Failed to send OSC messages: {:?}", e),
+                    },
+                    Err(RecvError::Lagged(skipped)) => warn!("Skipped {} friend events", skipped),
+                    Err(RecvError::Closed) => return,
+                },
+            }
+        }
+    }
+}
+
+/// ストアを直接書き換えたときに、送信中のループに設定を読み直させる
+pub(crate) fn reload_config() {
+    CONFIG_CHANGED.notify_one();
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn get_osc_config(app_handle: tauri::AppHandle) -> Result<OscConfig, RustError> {
+    debug!("Call get_osc_config");
+
+    load_config(&app_handle)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn set_osc_config(app_handle: tauri::AppHandle, config: OscConfig) -> Result<(), RustError> {
+    debug!("Call set_osc_config {:?}", config);
+
+    validate_config(&config)?;
+    let store = app_handle.store(OSC_STORE)?;
+    store.set(CONFIG_KEY, serde_json::to_value(config)?);
+    store.save()?;
+    CONFIG_CHANGED.notify_one();
+    Ok(())
+}
+
+/// 指定したイベントのメッセージを見本の値で送る。保存前の設定でも試せる。送った数を返す
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn send_test_osc_message(config: OscConfig, event: FriendEventKind) -> Result<u32, RustError> {
+    debug!("Call send_test_osc_message {:?} {:?}", config, event);
+
+    validate_config(&config)?;
+    let variables = friend_events::sample_variables();
+    let packets: Vec<Vec<u8>> = config
+        .messages
+        .iter()
+        .filter(|template| template.event == event)
+        .map(|template| render_message(template, &variables))
+        .collect();
+    send_packets(&config.host, config.port, &packets).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(value: &str) -> OscArgument {
+        OscArgument::String { value: value.to_string() }
+    }
+
+    #[test]
+    fn encodes_the_examples_from_the_osc_spec() {
+        // OSC 1.0 の仕様にある例
+        let packet = encode_message("/oscillator/4/frequency", &[OscArgument::Float { value: 440.0 }]);
+        let mut expected = b"/oscillator/4/frequency\0".to_vec();
+        expected.extend_from_slice(&[b',', b'f', 0, 0, 0x43, 0xdc, 0x00, 0x00]);
+        assert_eq!(packet, expected);
+
+        let packet = encode_message(
+            "/foo",
+            &[
+                OscArgument::Int { value: 1000 },
+                OscArgument::Int { value: -1 },
+                string("hello"),
+                OscArgument::Float { value: 1.234 },
+                OscArgument::Float { value: 5.678 },
+            ],
+        );
+        let expected: Vec<u8> = [
+            b"/foo\0\0\0\0".as_slice(),
+            b",iisff\0\0",
+            &[0x00, 0x00, 0x03, 0xe8],
+            &[0xff, 0xff, 0xff, 0xff],
+            b"hello\0\0\0",
+            &[0x3f, 0x9d, 0xf3, 0xb6],
+            &[0x40, 0xb5, 0xb2, 0x2d],
+        ]
+        .concat();
+        assert_eq!(packet, expected);
+    }
+
+    #[test]
+    fn encodes_booleans_as_type_tags_only() {
+        let packet = encode_message(
+            "/chatbox/input",
+            &[string("hi"), OscArgument::Bool { value: true }, OscArgument::Bool { value: false }],
+        );
+        let expected: Vec<u8> = [b"/chatbox/input\0\0".as_slice(), b",sTF\0\0\0\0", b"hi\0\0"].concat();
+        assert_eq!(packet, expected);
+    }
+
+    #[test]
+    fn pads_strings_to_four_bytes_with_at_least_one_nul() {
+        // 長さが4の倍数でも終端のNULのために4バイト足す
+        assert_eq!(encode_message("/abc", &[]), b"/abc\0\0\0\0,\0\0\0");
+        assert_eq!(encode_message("/ab", &[]), b"/ab\0,\0\0\0");
+        assert_eq!(encode_message("/a", &[string("")]), b"/a\0\0,s\0\0\0\0\0\0");
+        // 文字数ではなくUTF-8のバイト数で埋める
+        assert_eq!(encode_message("/a", &[string("é")]), b"/a\0\0,s\0\0\xc3\xa9\0\0");
+    }
+
+    #[test]
+    fn renders_templates() {
+        let variables = [("displayName", "Alice".to_string()), ("worldName", "Home".to_string())];
+        assert_eq!(render("{displayName} joined {worldName}", &variables), "Alice joined Home");
+        assert_eq!(render("{displayName}{displayName}", &variables), "AliceAlice");
+        // 知らない名前や閉じていない括弧はそのまま残す
+        assert_eq!(render("{unknown} {displayName", &variables), "{unknown} {displayName");
+        assert_eq!(render("no variables", &variables), "no variables");
+        assert_eq!(render("{{displayName}}", &variables), "{Alice}");
+        // 値の中の `{name}` は置き換えない
+        let variables = [("displayName", "{worldName}".to_string()), ("worldName", "Home".to_string())];
+        assert_eq!(render("{displayName} in {worldName}", &variables), "{worldName} in Home");
+    }
+
+    #[test]
+    fn renders_only_string_arguments_and_the_address() {
+        let template = OscMessageTemplate {
+            event: FriendEventKind::FavoriteOnline,
+            address: "/avatar/parameters/{displayName}".to_string(),
+            arguments: vec![string("{displayName} is online"), OscArgument::Int { value: 3 }],
+        };
+        let packet = render_message(&template, &[("displayName", "Bob".to_string())]);
+        let expected = encode_message(
+            "/avatar/parameters/Bob",
+            &[string("Bob is online"), OscArgument::Int { value: 3 }],
+        );
+        assert_eq!(packet, expected);
+    }
+
+    #[tokio::test]
+    async fn sends_each_packet_as_one_datagram() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = receiver.local_addr().unwrap().port();
+        let packets = vec![
+            encode_message("/chatbox/input", &[string("Alice is online"), OscArgument::Bool { value: true }]),
+            encode_message("/avatar/parameters/Friends", &[OscArgument::Int { value: 2 }]),
+        ];
+
+        assert_eq!(send_packets("127.0.0.1", port, &packets).await.unwrap(), 2);
+
+        let mut buffer = [0u8; 1024];
+        for packet in &packets {
+            let (len, _) = tokio::time::timeout(std::time::Duration::from_secs(5), receiver.recv_from(&mut buffer))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(&buffer[..len], packet.as_slice());
+        }
+    }
+
+    #[tokio::test]
+    async fn sending_nothing_does_not_fail() {
+        assert_eq!(send_packets("127.0.0.1", 9, &[]).await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,283 @@
+use crate::commands::RustError;
+use crate::export::{instance_type_label, region_label, split_instance};
+use crate::history::InstanceVisit;
+use crate::settings::{load_settings, Settings, SETTINGS_CHANGED_EVENT};
+use chrono::Local;
+use log::{debug, error, info};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tauri::{Listener, Manager};
+
+/// presence.json の形式のバージョン。項目の意味を変えたり消したりしたら上げる
+const PRESENCE_VERSION: u32 = 1;
+const PRESENCE_FILE_NAME: &str = "presence.json";
+
+static CURRENT: Lazy<RwLock<Presence>> = Lazy::new(|| RwLock::new(Presence::from_visit(None)));
+
+/// 外部のプレゼンス表示ツール向けに書き出す、自分がいまいる場所。
+/// ワールドにいないときはワールドの項目がすべて `null` になる
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct Presence {
+    pub version: u32,
+    pub in_world: bool,
+    pub world_id: Option<String>,
+    pub world_name: Option<String>,
+    /// `wrld_xxx:12345~region(jp)` 形式のロケーション
+    pub location: Option<String>,
+    /// インスタンス番号（`~` より前の部分）
+    pub instance_name: Option<String>,
+    /// Public / Friends+ / Friends / Invite+ / Invite / Group Public / Group+ / Group
+    pub instance_type: Option<String>,
+    pub region: Option<String>,
+    /// 自分を含めた、いまインスタンスにいる人数
+    pub player_count: u32,
+    pub joined_at: Option<String>,
+    pub updated_at: String,
+}
+
+impl Presence {
+    fn from_visit(visit: Option<&InstanceVisit>) -> Self {
+        let updated_at = Local::now().to_rfc3339();
+        let Some(visit) = visit.filter(|visit| visit.left_at.is_none()) else {
+            return Self {
+                version: PRESENCE_VERSION,
+                in_world: false,
+                world_id: None,
+                world_name: None,
+                location: None,
+                instance_name: None,
+                instance_type: None,
+                region: None,
+                player_count: 0,
+                joined_at: None,
+                updated_at,
+            };
+        };
+        let (instance_name, tags) = split_instance(&visit.instance_id);
+        Self {
+            version: PRESENCE_VERSION,
+            in_world: true,
+            world_id: Some(visit.world_id.clone()),
+            world_name: visit.world_name.clone(),
+            location: Some(format!("{}:{}", visit.world_id, visit.instance_id)),
+            instance_type: Some(instance_type_label(&instance_name, &tags)),
+            region: Some(region_label(&instance_name, &tags)),
+            instance_name: Some(instance_name),
+            player_count: visit.players.iter().filter(|player| player.left_at.is_none()).count() as u32,
+            joined_at: Some(visit.joined_at.clone()),
+            updated_at,
+        }
+    }
+
+    /// 更新時刻以外が同じか
+    fn same_place(&self, other: &Presence) -> bool {
+        Presence {
+            updated_at: other.updated_at.clone(),
+            ..self.clone()
+        } == *other
+    }
+}
+
+fn file_path(app_handle: &tauri::AppHandle, settings: &Settings) -> Result<PathBuf, RustError> {
+    if !settings.presence_file_path.trim().is_empty() {
+        return Ok(PathBuf::from(&settings.presence_file_path));
+    }
+    Ok(app_handle.path().app_data_dir()?.join(PRESENCE_FILE_NAME))
+}
+
+/// 読み取り側が書きかけのファイルを読まないように、一時ファイルに書いてから置き換える
+fn write_file(path: &Path, presence: &Presence) -> Result<(), RustError> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    let temp = path.with_extension("json.tmp");
+    std::fs::write(&temp, serde_json::to_vec_pretty(presence)?)?;
+    std::fs::rename(&temp, path)?;
+    Ok(())
+}
+
+fn write_if_enabled(app_handle: &tauri::AppHandle, settings: &Settings, presence: &Presence) {
+    if !settings.presence_file_enabled {
+        return;
+    }
+    let result = file_path(app_handle, settings).and_then(|path| write_file(&path, presence));
+    if let Err(e) = result {
+        error!("Failed to write presence file: {:?}", e);
+    }
+}
+
+pub(crate) fn current() -> Presence {
+    CURRENT.read().unwrap().clone()
+}
+
+/// VRChatのログから分かった現在の滞在でプレゼンスを更新し、変わっていればファイルに書き出す
+pub(crate) fn update(app_handle: &tauri::AppHandle, visit: Option<&InstanceVisit>) {
+    let presence = Presence::from_visit(visit);
+    {
+        let mut current = CURRENT.write().unwrap();
+        if current.same_place(&presence) {
+            return;
+        }
+        *current = presence.clone();
+    }
+    debug!("Presence changed: {:?}", presence.location);
+
+    match load_settings(app_handle) {
+        Ok(settings) => write_if_enabled(app_handle, &settings, &presence),
+        Err(e) => error!("Failed to load settings for presence: {:?}", e),
+    }
+}
+
+/// 書き出しを有効にしたときや書き出し先を変えたときに、すぐに今のプレゼンスを書く
+pub(crate) fn init(app_handle: tauri::AppHandle) {
+    let handle = app_handle.clone();
+    app_handle.listen(SETTINGS_CHANGED_EVENT, move |event| {
+        match serde_json::from_str::<Settings>(event.payload()) {
+            Ok(settings) => write_if_enabled(&handle, &settings, &current()),
+            Err(e) => error!("Failed to parse settings event: {}", e),
+        }
+    });
+
+    match load_settings(&app_handle) {
+        Ok(settings) if settings.presence_file_enabled => {
+            if let Ok(path) = file_path(&app_handle, &settings) {
+                info!("Writing presence to {}", path.display());
+            }
+            write_if_enabled(&app_handle, &settings, &current());
+        }
+        Ok(_) => {}
+        Err(e) => error!("Failed to load settings for presence: {:?}", e),
+    }
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn get_presence() -> Presence {
+    debug!("Call get_presence");
+
+    current()
+}
+
+/// 書き出し先のファイル。設定で指定していなければアプリのデータフォルダの presence.json
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn get_presence_file_path(app_handle: tauri::AppHandle) -> Result<String, RustError> {
+    debug!("Call get_presence_file_path");
+
+    let settings = load_settings(&app_handle)?;
+    Ok(file_path(&app_handle, &settings)?.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use crate::history::PlayerEncounter;
+
+    fn player(name: &str, left_at: Option<&str>) -> PlayerEncounter {
+        PlayerEncounter {
+            display_name: name.to_string(),
+            user_id: None,
+            joined_at: "2025-01-01T20:00:00+09:00".to_string(),
+            left_at: left_at.map(str::to_string),
+        }
+    }
+
+    fn visit(left_at: Option<&str>) -> InstanceVisit {
+        InstanceVisit {
+            id: "2025-01-01T20:00:00+09:00@wrld_a:12345~friends(usr_x)~region(jp)".to_string(),
+            world_id: "wrld_a".to_string(),
+            instance_id: "12345~friends(usr_x)~region(jp)".to_string(),
+            world_name: Some("Home".to_string()),
+            joined_at: "2025-01-01T20:00:00+09:00".to_string(),
+            left_at: left_at.map(str::to_string),
+            players: vec![
+                player("Me", None),
+                player("Alice", None),
+                player("Bob", Some("2025-01-01T20:05:00+09:00")),
+            ],
+            videos: Vec::new(),
+            portals: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn describes_the_instance_while_in_world() {
+        let presence = Presence::from_visit(Some(&visit(None)));
+        assert_eq!(presence.version, PRESENCE_VERSION);
+        assert!(presence.in_world);
+        assert_eq!(presence.world_id.as_deref(), Some("wrld_a"));
+        assert_eq!(presence.world_name.as_deref(), Some("Home"));
+        assert_eq!(presence.location.as_deref(), Some("wrld_a:12345~friends(usr_x)~region(jp)"));
+        assert_eq!(presence.instance_name.as_deref(), Some("12345"));
+        assert_eq!(presence.instance_type.as_deref(), Some("Friends"));
+        assert_eq!(presence.region.as_deref(), Some("jp"));
+        // 退出したプレイヤーは数えない
+        assert_eq!(presence.player_count, 2);
+        assert_eq!(presence.joined_at.as_deref(), Some("2025-01-01T20:00:00+09:00"));
+    }
+
+    #[test]
+    fn clears_the_world_after_leaving() {
+        for presence in [
+            Presence::from_visit(Some(&visit(Some("2025-01-01T21:00:00+09:00")))),
+            Presence::from_visit(None),
+        ] {
+            let json = serde_json::to_value(&presence).unwrap();
+            let updated_at = json["updatedAt"].clone();
+            assert!(updated_at.is_string());
+            assert_eq!(
+                json,
+                serde_json::json!({
+                    "version": PRESENCE_VERSION,
+                    "inWorld": false,
+                    "worldId": null,
+                    "worldName": null,
+                    "location": null,
+                    "instanceName": null,
+                    "instanceType": null,
+                    "region": null,
+                    "playerCount": 0,
+                    "joinedAt": null,
+                    "updatedAt": updated_at,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn same_place_ignores_only_the_update_time() {
+        let presence = Presence::from_visit(Some(&visit(None)));
+        let later = Presence {
+            updated_at: "2030-01-01T00:00:00+09:00".to_string(),
+            ..presence.clone()
+        };
+        assert!(presence.same_place(&later));
+        assert!(later.same_place(&presence));
+
+        // 人数が変わっただけでも書き直す
+        let mut fewer = visit(None);
+        fewer.players[1].left_at = Some("2025-01-01T20:10:00+09:00".to_string());
+        assert!(!presence.same_place(&Presence::from_visit(Some(&fewer))));
+
+        assert!(!presence.same_place(&Presence::from_visit(None)));
+        assert!(Presence::from_visit(None).same_place(&Presence::from_visit(None)));
+    }
+
+    #[test]
+    fn writes_the_file_atomically() {
+        let dir = TempDir::new("presence");
+        let path = dir.join("nested").join(PRESENCE_FILE_NAME);
+        let presence = Presence::from_visit(Some(&visit(None)));
+
+        write_file(&path, &presence).unwrap();
+        let written: Presence = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(written, presence);
+        // 一時ファイルは残らない
+        assert!(!path.with_extension("json.tmp").exists());
+    }
+}
@@ -0,0 +1,620 @@
+use crate::accounts::{current_session, load_accounts, save_session_cookies, Session};
+use crate::commands::{
+    get_current_user_id, parse_login_response, post_two_factor_code, LoginOutcome, OtpMethod, RustError,
+    VRCHAT_API_BASE_URL,
+};
+use crate::headless::{AppDirs, HeadlessApp};
+use crate::redaction::{register_secret, SecretKind};
+use crate::totp;
+use keyring::Entry;
+use log::{debug, error, info, warn};
+use once_cell::sync::{Lazy, OnceCell};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::Emitter;
+use tokio::sync::{oneshot, Mutex};
+
+pub(crate) const REAUTH_OTP_EVENT: &str = "vrchat-reauth-otp-required";
+pub(crate) const SESSION_EXPIRED_EVENT: &str = "vrchat-session-expired";
+/// 再認証中に2要素認証のコード入力を待つ時間
+const OTP_TIMEOUT: Duration = Duration::from_secs(300);
+
+static APP_HANDLE: OnceCell<tauri::AppHandle> = OnceCell::new();
+
+/// CLIのデータの場所。CLIでは保存したTOTPシークレットでしか再ログインできない
+static HEADLESS_APP: OnceCell<HeadlessApp> = OnceCell::new();
+
+/// アカウントごとに再認証を1つずつ行う。同時に401になったリクエストは先に終わった再認証の結果を使う。
+/// 2要素認証のコードを待っている間も、ほかのアカウントの再認証は止めない
+static REAUTH_LOCKS: Lazy<std::sync::Mutex<HashMap<String, Arc<Mutex<()>>>>> =
+    Lazy::new(|| std::sync::Mutex::new(HashMap::new()));
+
+type OtpSender = oneshot::Sender<(OtpMethod, String)>;
+
+/// コードの入力を待っている再認証。キーはユーザーID
+static PENDING_OTP: Lazy<std::sync::Mutex<HashMap<String, OtpSender>>> =
+    Lazy::new(|| std::sync::Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct ReauthOtpRequest {
+    pub user_id: String,
+    /// 使える方式。先頭が最初に案内する方式
+    pub methods: Vec<OtpMethod>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LoginCredentials {
+    user_name: String,
+    password: String,
+}
+
+pub(crate) fn init(app_handle: tauri::AppHandle) {
+    let _ = APP_HANDLE.set(app_handle);
+}
+
+/// 画面の無いCLI用。コードの入力を待たずに再認証を諦める
+pub(crate) fn init_headless(app: HeadlessApp) {
+    let _ = HEADLESS_APP.set(app);
+}
+
+fn credentials_entry(app: &impl AppDirs, user_id: &str) -> keyring::Result<Entry> {
+    Entry::new(app.identifier(), &format!("credentials:{user_id}"))
+}
+
+fn load_credentials(app: &impl AppDirs, user_id: &str) -> Result<Option<LoginCredentials>, RustError> {
+    match credentials_entry(app, user_id)?.get_password() {
+        Ok(json) => {
+            let credentials: LoginCredentials = serde_json::from_str(&json)?;
+            register_secret(SecretKind::Credential, &credentials.password);
+            Ok(Some(credentials))
+        }
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub(crate) fn delete_credentials(app_handle: &tauri::AppHandle, user_id: &str) -> Result<(), RustError> {
+    match credentials_entry(app_handle, user_id).and_then(|entry| entry.delete_credential()) {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// 認証情報を保存しているアカウントのうち、ログインに使う名前が一致するものを探す
+pub(crate) fn account_for_user_name(app_handle: &tauri::AppHandle, user_name: &str) -> Result<Option<String>, RustError> {
+    for account in load_accounts(app_handle)? {
+        if let Ok(Some(credentials)) = load_credentials(app_handle, &account.id) {
+            if credentials.user_name.eq_ignore_ascii_case(user_name) {
+                return Ok(Some(account.id));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// 再認証のうちアプリの状態に触れる処理。テストではモックのサーバーと固定の認証情報に差し替える
+trait ReauthBackend {
+    fn api_base_url(&self) -> &str;
+    fn load_credentials(&self, user_id: &str) -> Result<Option<LoginCredentials>, RustError>;
+    fn generate_totp(&self, user_id: &str) -> Result<Option<String>, RustError>;
+    async fn request_otp(&self, user_id: &str, methods: Vec<OtpMethod>) -> Result<(OtpMethod, String), RustError>;
+    /// 再ログインしたセッションのCookieを保存する
+    fn save_session(&self, session: &Session) -> Result<(), RustError>;
+    fn session_expired(&self, user_id: &str);
+}
+
+struct AppReauth<'a>(&'a tauri::AppHandle);
+
+impl ReauthBackend for AppReauth<'_> {
+    fn api_base_url(&self) -> &str {
+        VRCHAT_API_BASE_URL
+    }
+
+    fn load_credentials(&self, user_id: &str) -> Result<Option<LoginCredentials>, RustError> {
+        load_credentials(self.0, user_id)
+    }
+
+    fn generate_totp(&self, user_id: &str) -> Result<Option<String>, RustError> {
+        totp::generate_code(self.0, user_id)
+    }
+
+    async fn request_otp(&self, user_id: &str, methods: Vec<OtpMethod>) -> Result<(OtpMethod, String), RustError> {
+        request_otp(self.0, user_id, methods).await
+    }
+
+    fn save_session(&self, session: &Session) -> Result<(), RustError> {
+        save_session_cookies(self.0, session)
+    }
+
+    fn session_expired(&self, user_id: &str) {
+        if let Err(e) = self.0.emit(SESSION_EXPIRED_EVENT, user_id) {
+            error!("Failed to emit session expired: {}", e);
+        }
+    }
+}
+
+/// CLIの再認証。コードを入力する画面が無く、保存した認証情報とTOTPシークレットだけで再ログインする
+struct HeadlessReauth<'a>(&'a HeadlessApp);
+
+impl ReauthBackend for HeadlessReauth<'_> {
+    fn api_base_url(&self) -> &str {
+        VRCHAT_API_BASE_URL
+    }
+
+    fn load_credentials(&self, user_id: &str) -> Result<Option<LoginCredentials>, RustError> {
+        load_credentials(self.0, user_id)
+    }
+
+    fn generate_totp(&self, user_id: &str) -> Result<Option<String>, RustError> {
+        totp::generate_code(self.0, user_id)
+    }
+
+    async fn request_otp(&self, user_id: &str, _methods: Vec<OtpMethod>) -> Result<(OtpMethod, String), RustError> {
+        warn!("Re-login of {} needs a two-factor code but there is no window to ask for it", user_id);
+        Err("errors.reauthCancelled".into())
+    }
+
+    fn save_session(&self, session: &Session) -> Result<(), RustError> {
+        save_session_cookies(self.0, session)
+    }
+
+    fn session_expired(&self, user_id: &str) {
+        warn!("Session of {} has expired", user_id);
+    }
+}
+
+async fn is_session_valid(client: &Client, api_base_url: &str) -> Result<bool, RustError> {
+    let res = client.get(format!("{api_base_url}/1/auth")).send().await?;
+    if res.status() != StatusCode::OK {
+        return Ok(false);
+    }
+    let res_json: serde_json::Value = res.json().await?;
+    Ok(res_json["ok"].as_bool() == Some(true))
+}
+
+/// フロントエンドに2要素認証のコードを求め、入力されるまで待つ
+async fn request_otp(
+    app_handle: &tauri::AppHandle,
+    user_id: &str,
+    mut methods: Vec<OtpMethod>,
+) -> Result<(OtpMethod, String), RustError> {
+    let (sender, receiver) = oneshot::channel();
+    PENDING_OTP.lock()?.insert(user_id.to_string(), sender);
+
+    if let Some(preferred) = OtpMethod::preferred(&methods) {
+        methods.retain(|method| *method != preferred);
+        methods.insert(0, preferred);
+    }
+    let request = ReauthOtpRequest {
+        user_id: user_id.to_string(),
+        methods,
+    };
+    app_handle.emit(REAUTH_OTP_EVENT, request)?;
+
+    let code = tokio::time::timeout(OTP_TIMEOUT, receiver).await;
+    PENDING_OTP.lock()?.remove(user_id);
+    match code {
+        Ok(Ok((method, code))) => {
+            register_secret(SecretKind::Otp, &code);
+            Ok((method, code))
+        }
+        // タイムアウトまたはキャンセル
+        _ => Err("errors.reauthCancelled".into()),
+    }
+}
+
+/// `client` のCookieストアにログインし直す。他のアカウントのセッションには触れない
+async fn login_with_credentials(
+    backend: &impl ReauthBackend,
+    client: &Client,
+    user_id: &str,
+    credentials: &LoginCredentials,
+) -> Result<bool, RustError> {
+    let res = client
+        .get(format!("{}/1/auth/user", backend.api_base_url()))
+        .basic_auth(&credentials.user_name, Some(&credentials.password))
+        .send()
+        .await?;
+    if res.status() != StatusCode::OK {
+        warn!("Re-login failed with status {}", res.status());
+        return Ok(false);
+    }
+
+    let res_json: serde_json::Value = res.json().await?;
+    match parse_login_response(&res_json)? {
+        LoginOutcome::TwoFactorRequired { methods } => {
+            // シークレットを保存していれば人の操作なしで再ログインできる
+            if methods.contains(&OtpMethod::Totp) {
+                if let Some(code) = backend.generate_totp(user_id)? {
+                    match post_two_factor_code(client, OtpMethod::Totp, &code).await {
+                        Ok(true) => return Ok(true),
+                        Ok(false) | Err(_) => warn!("Generated TOTP code was rejected, asking for a code"),
+                    }
+                }
+            }
+            let (method, code) = backend.request_otp(user_id, methods).await?;
+            post_two_factor_code(client, method, &code).await
+        }
+        LoginOutcome::LoggedIn { user_id: logged_in, .. } if logged_in != user_id => {
+            // 保存されている認証情報が別のアカウントのもの
+            error!("Stored credentials belong to another account");
+            Ok(false)
+        }
+        LoginOutcome::LoggedIn { .. } => Ok(true),
+    }
+}
+
+fn reauth_lock(user_id: &str) -> Arc<Mutex<()>> {
+    let mut locks = REAUTH_LOCKS.lock().unwrap();
+    locks.entry(user_id.to_string()).or_default().clone()
+}
+
+async fn reauthenticate_with(backend: &impl ReauthBackend, session: &Session) -> Result<bool, RustError> {
+    let lock = reauth_lock(session.user_id.as_deref().unwrap_or_default());
+    let _guard = lock.lock().await;
+    if is_session_valid(&session.client, backend.api_base_url()).await? {
+        return Ok(true);
+    }
+
+    let Some(user_id) = session.user_id.clone() else {
+        return Ok(false);
+    };
+    let reauthenticated = match backend.load_credentials(&user_id)? {
+        Some(credentials) => login_with_credentials(backend, &session.client, &user_id, &credentials).await?,
+        None => false,
+    };
+
+    if reauthenticated {
+        info!("Re-authenticated {}", user_id);
+        backend.save_session(session)?;
+    } else {
+        backend.session_expired(&user_id);
+    }
+    Ok(reauthenticated)
+}
+
+/// `session` が切れていれば、そのアカウントの認証情報でそのセッションにログインし直す。再認証できたかを返す
+pub(crate) async fn reauthenticate(session: &Session) -> Result<bool, RustError> {
+    if let Some(app) = HEADLESS_APP.get() {
+        return reauthenticate_with(&HeadlessReauth(app), session).await;
+    }
+    let app_handle = APP_HANDLE.get().ok_or("App handle is not initialized")?;
+    reauthenticate_with(&AppReauth(app_handle), session).await
+}
+
+/// リクエストを送り、401なら `reauth` の結果に応じて送り直す
+async fn send_retrying(
+    request: RequestBuilder,
+    reauth: impl Future<Output = Result<bool, RustError>>,
+) -> Result<Response, RustError> {
+    // 送り直し用の複製。Cookieは送信時にCookieストアから付くので、再ログイン後のCookieが使われる
+    let retry = request.try_clone();
+    let res = request.send().await?;
+    if res.status() != StatusCode::UNAUTHORIZED {
+        return Ok(res);
+    }
+    let Some(retry) = retry else {
+        return Ok(res);
+    };
+
+    match reauth.await {
+        Ok(true) => Ok(retry.send().await?),
+        Ok(false) => Ok(res),
+        Err(e) => {
+            error!("Re-authentication failed: {:?}", e);
+            Ok(res)
+        }
+    }
+}
+
+pub(crate) trait SendWithReauth {
+    /// 現在のアカウントのリクエストとして送る。401が返ってきたら再認証し、成功すれば同じリクエストを送り直す
+    async fn send_with_reauth(self) -> Result<Response, RustError>;
+    /// `session` のクライアントで作ったリクエストを送る。401ならそのアカウントを再認証する
+    async fn send_with_reauth_as(self, session: &Session) -> Result<Response, RustError>;
+}
+
+impl SendWithReauth for RequestBuilder {
+    async fn send_with_reauth(self) -> Result<Response, RustError> {
+        let session = current_session();
+        self.send_with_reauth_as(&session).await
+    }
+
+    async fn send_with_reauth_as(self, session: &Session) -> Result<Response, RustError> {
+        send_retrying(self, reauthenticate(session)).await
+    }
+}
+
+/// 現在のアカウントの認証情報をキーリングに保存し、セッション切れのときに自動で再ログインできるようにする
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn remember_login_credentials(
+    app_handle: tauri::AppHandle,
+    user_name: String,
+    password: String,
+) -> Result<(), RustError> {
+    register_secret(SecretKind::Credential, &password);
+    debug!("Call remember_login_credentials");
+
+    let user_id = get_current_user_id().await?;
+    let credentials = LoginCredentials { user_name, password };
+    credentials_entry(&app_handle, &user_id)?.set_password(&serde_json::to_string(&credentials)?)?;
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn forget_login_credentials(app_handle: tauri::AppHandle, user_id: &str) -> Result<(), RustError> {
+    debug!("Call forget_login_credentials {:?}", user_id);
+
+    delete_credentials(&app_handle, user_id)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn has_login_credentials(app_handle: tauri::AppHandle, user_id: &str) -> Result<bool, RustError> {
+    debug!("Call has_login_credentials {:?}", user_id);
+
+    Ok(load_credentials(&app_handle, user_id)?.is_some())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn reauthenticate_session() -> Result<bool, RustError> {
+    debug!("Call reauthenticate_session");
+
+    reauthenticate(&current_session()).await
+}
+
+/// 再認証中に求められた2要素認証のコードを渡す
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn submit_reauth_otp(user_id: String, method: OtpMethod, code: String) -> Result<(), RustError> {
+    debug!("Call submit_reauth_otp {:?} {:?}", user_id, method);
+
+    let sender = PENDING_OTP.lock()?.remove(&user_id).ok_or("errors.noPendingReauth")?;
+    sender.send((method, code)).map_err(|_| "errors.noPendingReauth")?;
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn cancel_reauth(user_id: String) -> Result<(), RustError> {
+    debug!("Call cancel_reauth {:?}", user_id);
+
+    PENDING_OTP.lock()?.remove(&user_id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest_cookie_store::CookieStore;
+    use std::collections::HashSet;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// VRChat API の認証まわりだけを真似るサーバー。`auth` Cookie が有効なトークンなら認証済みとみなす
+    struct MockApi {
+        base_url: String,
+        valid_tokens: std::sync::Mutex<HashSet<String>>,
+        logins: std::sync::Mutex<Vec<String>>,
+    }
+
+    /// Basic認証のヘッダー値とログインするユーザー
+    const ACCOUNTS: [(&str, &str, &str); 2] = [
+        // alice:pw-a
+        ("Basic YWxpY2U6cHctYQ==", "usr_a", "alice"),
+        // bob:pw-b
+        ("Basic Ym9iOnB3LWI=", "usr_b", "bob"),
+    ];
+
+    async fn start_mock_api() -> Arc<MockApi> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api = Arc::new(MockApi {
+            base_url: format!("http://{}", listener.local_addr().unwrap()),
+            valid_tokens: std::sync::Mutex::new(HashSet::new()),
+            logins: std::sync::Mutex::new(Vec::new()),
+        });
+        let server = api.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match stream.read(&mut buf).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let response = server.respond(&String::from_utf8_lossy(&request));
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        api
+    }
+
+    impl MockApi {
+        fn respond(&self, request: &str) -> String {
+            let path = request.split_whitespace().nth(1).unwrap_or_default();
+            let header = |name: &str| {
+                request.lines().find_map(|line| {
+                    let (key, value) = line.split_once(':')?;
+                    key.eq_ignore_ascii_case(name).then(|| value.trim().to_string())
+                })
+            };
+            let authorized = header("cookie")
+                .and_then(|cookie| cookie.split(';').find_map(|c| c.trim().strip_prefix("auth=").map(str::to_string)))
+                .is_some_and(|token| self.valid_tokens.lock().unwrap().contains(&token));
+
+            let login = ACCOUNTS
+                .iter()
+                .find(|(auth, ..)| header("authorization").as_deref() == Some(*auth));
+
+            let (status, set_cookie, body) = match path {
+                "/1/auth/user" => match login {
+                    Some((_, user_id, user_name)) => {
+                        let token = format!("relogin-{user_name}");
+                        self.valid_tokens.lock().unwrap().insert(token.clone());
+                        self.logins.lock().unwrap().push(user_name.to_string());
+                        let body = format!(r#"{{"id":"{user_id}","displayName":"{user_name}"}}"#);
+                        ("200 OK", format!("Set-Cookie: auth={token}; Path=/\r\n"), body)
+                    }
+                    None => ("401 Unauthorized", String::new(), "{}".to_string()),
+                },
+                "/1/auth" if authorized => ("200 OK", String::new(), r#"{"ok":true}"#.to_string()),
+                "/1/ping" if authorized => ("200 OK", String::new(), "{}".to_string()),
+                _ => ("401 Unauthorized", String::new(), "{}".to_string()),
+            };
+            format!(
+                "HTTP/1.1 {status}\r\n{set_cookie}Content-Type: application/json\r\nContent-Length: {}\r\n\
+                 Connection: close\r\n\r\n{body}",
+                body.len()
+            )
+        }
+
+        /// `token` を `auth` Cookie に持つセッションを作る。`valid` でなければ期限切れのトークンになる
+        fn session(&self, user_id: &str, token: &str, valid: bool) -> Session {
+            if valid {
+                self.valid_tokens.lock().unwrap().insert(token.to_string());
+            }
+            let mut cookie_store = CookieStore::default();
+            let url = reqwest::Url::parse(&self.base_url).unwrap();
+            cookie_store.parse(&format!("auth={token}; Path=/"), &url).unwrap();
+            Session::new(Some(user_id.to_string()), cookie_store)
+        }
+    }
+
+    struct TestBackend {
+        api: Arc<MockApi>,
+        credentials: HashMap<&'static str, (&'static str, &'static str)>,
+        saved: std::sync::Mutex<Vec<String>>,
+        expired: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl TestBackend {
+        fn new(api: Arc<MockApi>) -> Self {
+            Self {
+                api,
+                credentials: HashMap::from([("usr_a", ("alice", "pw-a")), ("usr_b", ("bob", "pw-b"))]),
+                saved: std::sync::Mutex::new(Vec::new()),
+                expired: std::sync::Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    impl ReauthBackend for TestBackend {
+        fn api_base_url(&self) -> &str {
+            &self.api.base_url
+        }
+
+        fn load_credentials(&self, user_id: &str) -> Result<Option<LoginCredentials>, RustError> {
+            Ok(self.credentials.get(user_id).map(|(user_name, password)| LoginCredentials {
+                user_name: user_name.to_string(),
+                password: password.to_string(),
+            }))
+        }
+
+        fn generate_totp(&self, _user_id: &str) -> Result<Option<String>, RustError> {
+            Ok(None)
+        }
+
+        async fn request_otp(
+            &self,
+            _user_id: &str,
+            _methods: Vec<OtpMethod>,
+        ) -> Result<(OtpMethod, String), RustError> {
+            Err("errors.reauthCancelled".into())
+        }
+
+        fn save_session(&self, session: &Session) -> Result<(), RustError> {
+            self.saved.lock().unwrap().push(session.user_id.clone().unwrap_or_default());
+            Ok(())
+        }
+
+        fn session_expired(&self, user_id: &str) {
+            self.expired.lock().unwrap().push(user_id.to_string());
+        }
+    }
+
+    fn auth_cookie(session: &Session) -> String {
+        let cookie_store = session.cookie_store.lock().unwrap();
+        cookie_store.get("127.0.0.1", "/", "auth").unwrap().value().to_string()
+    }
+
+    async fn send_as(backend: &TestBackend, session: &Session) -> StatusCode {
+        let request = session.client.get(format!("{}/1/ping", backend.api.base_url));
+        let res = send_retrying(request, reauthenticate_with(backend, session)).await.unwrap();
+        res.status()
+    }
+
+    #[tokio::test]
+    async fn foreground_session_is_reauthenticated_with_its_own_credentials() {
+        let api = start_mock_api().await;
+        let backend = TestBackend::new(api.clone());
+        let foreground = api.session("usr_a", "expired-a", false);
+        let background = api.session("usr_b", "valid-b", true);
+
+        assert_eq!(send_as(&backend, &foreground).await, StatusCode::OK);
+
+        assert_eq!(*api.logins.lock().unwrap(), vec!["alice"]);
+        assert_eq!(auth_cookie(&foreground), "relogin-alice");
+        assert_eq!(auth_cookie(&background), "valid-b");
+        assert_eq!(*backend.saved.lock().unwrap(), vec!["usr_a"]);
+        assert!(backend.expired.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn background_session_is_reauthenticated_instead_of_the_current_one() {
+        let api = start_mock_api().await;
+        let backend = TestBackend::new(api.clone());
+        // 現在のアカウントは有効なままで、裏で使うアカウントだけ期限切れ
+        let foreground = api.session("usr_a", "valid-a", true);
+        let background = api.session("usr_b", "expired-b", false);
+
+        assert_eq!(send_as(&backend, &background).await, StatusCode::OK);
+
+        assert_eq!(*api.logins.lock().unwrap(), vec!["bob"]);
+        assert_eq!(auth_cookie(&background), "relogin-bob");
+        assert_eq!(auth_cookie(&foreground), "valid-a");
+        assert_eq!(*backend.saved.lock().unwrap(), vec!["usr_b"]);
+    }
+
+    #[tokio::test]
+    async fn waiting_for_one_account_does_not_block_another() {
+        let api = start_mock_api().await;
+        let backend = TestBackend::new(api.clone());
+        // 2要素認証のコードを待っている間は、そのアカウントのロックが取られたままになる
+        let waiting = reauth_lock("usr_waiting");
+        let _guard = waiting.lock().await;
+
+        let other = api.session("usr_b", "expired-b", false);
+        let status = tokio::time::timeout(Duration::from_secs(5), send_as(&backend, &other)).await;
+        assert_eq!(status.unwrap(), StatusCode::OK);
+
+        // 同じアカウントの再認証は待つ
+        let same = api.session("usr_waiting", "expired-w", false);
+        let blocked = tokio::time::timeout(Duration::from_millis(200), reauthenticate_with(&backend, &same)).await;
+        assert!(blocked.is_err());
+    }
+
+    #[tokio::test]
+    async fn session_without_credentials_is_reported_as_expired() {
+        let api = start_mock_api().await;
+        let mut backend = TestBackend::new(api.clone());
+        backend.credentials.remove("usr_b");
+        let background = api.session("usr_b", "expired-b", false);
+
+        assert_eq!(send_as(&backend, &background).await, StatusCode::UNAUTHORIZED);
+
+        assert!(api.logins.lock().unwrap().is_empty());
+        assert_eq!(*backend.expired.lock().unwrap(), vec!["usr_b"]);
+        assert!(backend.saved.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,365 @@
+use crate::commands::{
+    get_current_user_json, get_user_json, is_login, update_current_user_inner, RustError,
+};
+use crate::structs::{CurrentUserUpdate, UserStatus};
+use chrono::{Datelike, Local, NaiveDateTime, NaiveTime};
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri_plugin_store::StoreExt;
+
+pub(crate) const SCHEDULES_STORE: &str = "schedules.json";
+const SCHEDULES_KEY: &str = "schedules";
+const LOG_KEY: &str = "log";
+const LOG_MAX_ENTRIES: usize = 500;
+const TICK_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(tag = "type")]
+pub enum ScheduleTrigger {
+    /// 指定した曜日（0=月曜〜6=日曜）の時間帯。`start` > `end` の場合は日付をまたぐ
+    TimeWindow {
+        weekdays: Vec<u8>,
+        start: String,
+        end: String,
+    },
+    /// 指定したフレンドが自分と同じインスタンスにいる間
+    #[serde(rename_all = "camelCase")]
+    FriendInInstance { friend_id: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusSchedule {
+    pub id: String,
+    pub name: String,
+    pub enabled: bool,
+    pub trigger: ScheduleTrigger,
+    pub status: UserStatus,
+    pub status_description: Option<String>,
+    /// ルールが外れたときに適用前のステータスへ戻すか
+    pub revert: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusChangeLog {
+    pub timestamp: String,
+    pub schedule_id: Option<String>,
+    pub schedule_name: Option<String>,
+    pub status: UserStatus,
+    pub status_description: String,
+    pub reverted: bool,
+}
+
+fn parse_time(value: &str) -> Result<NaiveTime, RustError> {
+    NaiveTime::parse_from_str(value, "%H:%M")
+        .map_err(|_| RustError::from(format!("Invalid time format: {value}")))
+}
+
+fn validate_schedule(schedule: &StatusSchedule) -> Result<(), RustError> {
+    CurrentUserUpdate {
+        status_description: schedule.status_description.clone(),
+        ..Default::default()
+    }
+    .validate()?;
+
+    if let ScheduleTrigger::TimeWindow { weekdays, start, end } = &schedule.trigger {
+        parse_time(start)?;
+        parse_time(end)?;
+        if weekdays.iter().any(|d| *d > 6) {
+            return Err("Weekday must be between 0 and 6".into());
+        }
+    }
+    Ok(())
+}
+
+fn is_time_window_active(weekdays: &[u8], start: NaiveTime, end: NaiveTime, now: NaiveDateTime) -> bool {
+    let time = now.time();
+    let today = now.weekday().num_days_from_monday() as u8;
+    let yesterday = (today + 6) % 7;
+
+    if start <= end {
+        weekdays.contains(&today) && time >= start && time < end
+    } else {
+        // 日付をまたぐ時間帯は、開始日の曜日で判定する
+        (weekdays.contains(&today) && time >= start)
+            || (weekdays.contains(&yesterday) && time < end)
+    }
+}
+
+fn load_schedules(app_handle: &tauri::AppHandle) -> Result<Vec<StatusSchedule>, RustError> {
+    let store = app_handle.store(SCHEDULES_STORE)?;
+    match store.get(SCHEDULES_KEY) {
+        Some(value) => Ok(serde_json::from_value(value)?),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn append_log(app_handle: &tauri::AppHandle, entry: StatusChangeLog) -> Result<(), RustError> {
+    let store = app_handle.store(SCHEDULES_STORE)?;
+    let mut log: Vec<StatusChangeLog> = match store.get(LOG_KEY) {
+        Some(value) => serde_json::from_value(value).unwrap_or_default(),
+        None => Vec::new(),
+    };
+    log.push(entry);
+    if log.len() > LOG_MAX_ENTRIES {
+        log.drain(..log.len() - LOG_MAX_ENTRIES);
+    }
+    store.set(LOG_KEY, serde_json::to_value(log)?);
+    store.save()?;
+    Ok(())
+}
+
+/// 自分の現在地（`wrld_xxx:instance`）を返す
+async fn get_my_location() -> Result<Option<String>, RustError> {
+    let user = get_current_user_json().await?;
+    let world = user["presence"]["world"].as_str().unwrap_or_default();
+    let instance = user["presence"]["instance"].as_str().unwrap_or_default();
+
+    if world.starts_with("wrld_") && !instance.is_empty() {
+        Ok(Some(format!("{world}:{instance}")))
+    } else {
+        Ok(None)
+    }
+}
+
+async fn is_friend_in_my_instance(friend_id: &str, my_location: Option<&str>) -> bool {
+    let Some(my_location) = my_location else {
+        return false;
+    };
+    match get_user_json(friend_id).await {
+        Ok(friend) => friend["location"].as_str() == Some(my_location),
+        Err(e) => {
+            error!("Failed to get friend location for schedule: {:?}", e);
+            false
+        }
+    }
+}
+
+/// 有効なスケジュールのうち、リストの先頭に近いものを優先して返す
+async fn find_active_schedule(schedules: &[StatusSchedule]) -> Option<StatusSchedule> {
+    let now = Local::now().naive_local();
+    let mut my_location: Option<Option<String>> = None;
+
+    for schedule in schedules.iter().filter(|s| s.enabled) {
+        let active = match &schedule.trigger {
+            ScheduleTrigger::TimeWindow { weekdays, start, end } => {
+                match (parse_time(start), parse_time(end)) {
+                    (Ok(start), Ok(end)) => is_time_window_active(weekdays, start, end, now),
+                    _ => false,
+                }
+            }
+            ScheduleTrigger::FriendInInstance { friend_id } => {
+                if my_location.is_none() {
+                    my_location = Some(get_my_location().await.unwrap_or_else(|e| {
+                        error!("Failed to get my location for schedule: {:?}", e);
+                        None
+                    }));
+                }
+                let location = my_location.as_ref().and_then(|l| l.as_deref());
+                is_friend_in_my_instance(friend_id, location).await
+            }
+        };
+        if active {
+            return Some(schedule.clone());
+        }
+    }
+    None
+}
+
+async fn get_current_status() -> Option<(UserStatus, String)> {
+    let user = get_current_user_json().await.ok()?;
+    let status = serde_json::from_value::<UserStatus>(user["status"].clone()).ok()?;
+    let description = user["statusDescription"].as_str().unwrap_or_default().to_string();
+    Some((status, description))
+}
+
+async fn apply_status(
+    app_handle: &tauri::AppHandle,
+    schedule: Option<&StatusSchedule>,
+    status: UserStatus,
+    status_description: String,
+) -> Result<(), RustError> {
+    let update = CurrentUserUpdate {
+        status: Some(status),
+        status_description: Some(status_description.clone()),
+        ..Default::default()
+    };
+    update_current_user_inner(&update).await?;
+
+    info!(
+        "Scheduled status change: {:?} {:?} (schedule: {:?})",
+        status,
+        status_description,
+        schedule.map(|s| &s.name)
+    );
+    let entry = StatusChangeLog {
+        timestamp: Local::now().to_rfc3339(),
+        schedule_id: schedule.map(|s| s.id.clone()),
+        schedule_name: schedule.map(|s| s.name.clone()),
+        status,
+        status_description,
+        reverted: schedule.is_none(),
+    };
+    if let Err(e) = append_log(app_handle, entry) {
+        error!("Failed to write status schedule log: {:?}", e);
+    }
+    Ok(())
+}
+
+/// バックグラウンドでスケジュールを評価し続ける。ウィンドウが非表示でも動作する
+pub(crate) async fn run(app_handle: tauri::AppHandle) {
+    let mut interval = tokio::time::interval(TICK_INTERVAL);
+    let mut active: Option<StatusSchedule> = None;
+    // スケジュール適用前のステータス。ルールが外れたときに戻すために使う
+    let mut baseline: Option<(UserStatus, String)> = None;
+
+    loop {
+        interval.tick().await;
+
+        if !is_login().await {
+            continue;
+        }
+
+        let schedules = match load_schedules(&app_handle) {
+            Ok(schedules) => schedules,
+            Err(e) => {
+                error!("Failed to load status schedules: {:?}", e);
+                continue;
+            }
+        };
+        let next = find_active_schedule(&schedules).await;
+
+        match (&active, &next) {
+            (Some(current), Some(next)) if current.id == next.id => continue,
+            (None, None) => continue,
+            _ => {}
+        }
+
+        let applied = match &next {
+            Some(schedule) => {
+                if active.is_none() {
+                    baseline = get_current_status().await;
+                }
+                debug!("Status schedule activated: {:?}", schedule.name);
+                apply_status(
+                    &app_handle,
+                    Some(schedule),
+                    schedule.status,
+                    schedule.status_description.clone().unwrap_or_default(),
+                )
+                .await
+            }
+            None => match (active.as_ref().is_some_and(|s| s.revert), &baseline) {
+                (true, Some((status, description))) => {
+                    apply_status(&app_handle, None, *status, description.clone()).await
+                }
+                _ => Ok(()),
+            },
+        };
+
+        // 失敗したときは状態を進めず、次の確認でやり直す
+        match applied {
+            Ok(()) => {
+                if next.is_none() {
+                    baseline = None;
+                }
+                active = next;
+            }
+            Err(e) => error!("Failed to apply scheduled status: {:?}", e),
+        }
+    }
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn get_status_schedules(app_handle: tauri::AppHandle) -> Result<Vec<StatusSchedule>, RustError> {
+    debug!("Call get_status_schedules");
+
+    load_schedules(&app_handle)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn set_status_schedules(
+    app_handle: tauri::AppHandle,
+    schedules: Vec<StatusSchedule>,
+) -> Result<(), RustError> {
+    debug!("Call set_status_schedules {:?}", schedules);
+
+    for schedule in &schedules {
+        validate_schedule(schedule)?;
+    }
+
+    let store = app_handle.store(SCHEDULES_STORE)?;
+    store.set(SCHEDULES_KEY, serde_json::to_value(schedules)?);
+    store.save()?;
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn get_status_schedule_log(app_handle: tauri::AppHandle) -> Result<Vec<StatusChangeLog>, RustError> {
+    debug!("Call get_status_schedule_log");
+
+    let store = app_handle.store(SCHEDULES_STORE)?;
+    match store.get(LOG_KEY) {
+        Some(value) => Ok(serde_json::from_value(value)?),
+        None => Ok(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn time(value: &str) -> NaiveTime {
+        parse_time(value).unwrap()
+    }
+
+    /// 2024-01-15 は月曜日
+    fn at(day: u32, value: &str) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap().and_time(time(value))
+    }
+
+    #[test]
+    fn window_applies_only_on_listed_weekdays() {
+        let weekdays = [0, 2];
+        assert!(is_time_window_active(&weekdays, time("09:00"), time("17:00"), at(15, "12:00")));
+        assert!(!is_time_window_active(&weekdays, time("09:00"), time("17:00"), at(16, "12:00")));
+        assert!(is_time_window_active(&weekdays, time("09:00"), time("17:00"), at(17, "12:00")));
+    }
+
+    #[test]
+    fn window_includes_start_and_excludes_end() {
+        let weekdays = [0];
+        assert!(is_time_window_active(&weekdays, time("09:00"), time("17:00"), at(15, "09:00")));
+        assert!(!is_time_window_active(&weekdays, time("09:00"), time("17:00"), at(15, "17:00")));
+        assert!(!is_time_window_active(&weekdays, time("09:00"), time("17:00"), at(15, "08:59")));
+    }
+
+    #[test]
+    fn window_past_midnight_belongs_to_the_start_day() {
+        // 月曜の22時から火曜の2時まで
+        let weekdays = [0];
+        assert!(is_time_window_active(&weekdays, time("22:00"), time("02:00"), at(15, "23:30")));
+        assert!(is_time_window_active(&weekdays, time("22:00"), time("02:00"), at(16, "01:30")));
+        assert!(!is_time_window_active(&weekdays, time("22:00"), time("02:00"), at(16, "02:00")));
+        assert!(!is_time_window_active(&weekdays, time("22:00"), time("02:00"), at(16, "23:00")));
+        // 日曜に始まる時間帯は月曜の朝まで続く
+        assert!(is_time_window_active(&[6], time("22:00"), time("02:00"), at(15, "01:00")));
+        assert!(!is_time_window_active(&[6], time("22:00"), time("02:00"), at(15, "23:00")));
+    }
+
+    #[test]
+    fn window_with_same_start_and_end_is_never_active() {
+        let weekdays = [0, 1, 2, 3, 4, 5, 6];
+        assert!(!is_time_window_active(&weekdays, time("09:00"), time("09:00"), at(15, "09:00")));
+        assert!(!is_time_window_active(&weekdays, time("09:00"), time("09:00"), at(15, "12:00")));
+    }
+}
@@ -0,0 +1,697 @@
+use crate::commands::RustError;
+use crate::history::{get_visits, InstanceVisit};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tauri_plugin_store::StoreExt;
+
+const SCREENSHOT_STORE: &str = "screenshots.json";
+const INDEX_KEY: &str = "index";
+const SCREENSHOT_DIR_KEY: &str = "vrchat-screenshot-dir";
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+const FILE_NAME_TIMESTAMP_FORMAT: &str = "%Y-%m-%d_%H-%M-%S";
+const FILE_NAME_TIMESTAMP_LENGTH: usize = 19;
+
+/// このアプリが書き込むiTXtチャンクのキーワード
+const METADATA_KEYWORD: &str = "VRCSocialManager";
+/// VRCXが書き込むiTXtチャンクのキーワード（JSON）
+const VRCX_KEYWORD: &str = "Description";
+/// VRChatが書き込むXMPのキーワード
+const XMP_KEYWORD: &str = "XML:com.adobe.xmp";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct ScreenshotPlayer {
+    pub display_name: String,
+    pub user_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct ScreenshotMetadata {
+    /// メタデータを書き込んだツール（`VRCSocialManager` / `VRCX` / `VRChat`）
+    pub source: String,
+    pub world_id: Option<String>,
+    pub world_name: Option<String>,
+    pub instance_id: Option<String>,
+    pub players: Vec<ScreenshotPlayer>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct ScreenshotEntry {
+    pub path: String,
+    pub taken_at: String,
+    /// ファイルの更新日時（UNIX秒）。変わっていなければ再インデックス時に読み直さない
+    pub modified: u32,
+    /// PNGに埋め込まれていたメタデータ
+    pub embedded: Option<ScreenshotMetadata>,
+    /// インスタンス履歴から推定したメタデータ
+    pub correlated: Option<ScreenshotMetadata>,
+}
+
+struct Chunk {
+    kind: [u8; 4],
+    data: Vec<u8>,
+}
+
+fn invalid_png() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Not a PNG file")
+}
+
+fn read_chunks(bytes: &[u8]) -> io::Result<Vec<Chunk>> {
+    if bytes.len() < PNG_SIGNATURE.len() || bytes[..PNG_SIGNATURE.len()] != PNG_SIGNATURE {
+        return Err(invalid_png());
+    }
+
+    let mut chunks = Vec::new();
+    let mut pos = PNG_SIGNATURE.len();
+    while pos + 12 <= bytes.len() {
+        let len = u32::from_be_bytes(bytes[pos..pos + 4].try_into().unwrap()) as usize;
+        let kind: [u8; 4] = bytes[pos + 4..pos + 8].try_into().unwrap();
+        let end = pos + 8 + len;
+        if end + 4 > bytes.len() {
+            return Err(invalid_png());
+        }
+        chunks.push(Chunk {
+            kind,
+            data: bytes[pos + 8..end].to_vec(),
+        });
+        pos = end + 4;
+        if &kind == b"IEND" {
+            return Ok(chunks);
+        }
+    }
+    // IENDまで読めないファイルは途中で切れている
+    Err(invalid_png())
+}
+
+fn write_chunks(chunks: &[Chunk]) -> Vec<u8> {
+    let mut bytes = PNG_SIGNATURE.to_vec();
+    for chunk in chunks {
+        bytes.extend_from_slice(&(chunk.data.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&chunk.kind);
+        bytes.extend_from_slice(&chunk.data);
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&chunk.kind);
+        hasher.update(&chunk.data);
+        bytes.extend_from_slice(&hasher.finalize().to_be_bytes());
+    }
+    bytes
+}
+
+/// tEXt / 非圧縮のiTXt チャンクを (キーワード, テキスト) として取り出す
+fn parse_text_chunk(kind: &[u8; 4], data: &[u8]) -> Option<(String, String)> {
+    let keyword_end = data.iter().position(|b| *b == 0)?;
+    let keyword: String = data[..keyword_end].iter().map(|b| *b as char).collect();
+    let rest = &data[keyword_end + 1..];
+
+    match kind {
+        b"tEXt" => Some((keyword, rest.iter().map(|b| *b as char).collect())),
+        b"iTXt" => {
+            // 圧縮フラグ, 圧縮方式, 言語タグ\0, 翻訳キーワード\0, テキスト
+            let (&compressed, rest) = rest.split_first()?;
+            if compressed != 0 {
+                return None;
+            }
+            let rest = rest.get(1..)?;
+            let language_end = rest.iter().position(|b| *b == 0)?;
+            let rest = &rest[language_end + 1..];
+            let translated_end = rest.iter().position(|b| *b == 0)?;
+            let text = String::from_utf8_lossy(&rest[translated_end + 1..]).to_string();
+            Some((keyword, text))
+        }
+        _ => None,
+    }
+}
+
+fn itxt_chunk(keyword: &str, text: &str) -> Chunk {
+    let mut data = keyword.as_bytes().to_vec();
+    // キーワード終端, 非圧縮, 圧縮方式, 言語タグ終端, 翻訳キーワード終端
+    data.extend_from_slice(&[0, 0, 0, 0, 0]);
+    data.extend_from_slice(text.as_bytes());
+    Chunk {
+        kind: *b"iTXt",
+        data,
+    }
+}
+
+/// このアプリのメタデータのチャンクを置き換えたPNGを返す。画像のチャンクはそのまま残す
+fn embed_metadata(bytes: &[u8], metadata: &ScreenshotMetadata) -> Result<Vec<u8>, RustError> {
+    let mut chunks = read_chunks(bytes)?;
+    chunks.retain(|c| {
+        !(&c.kind == b"iTXt" && parse_text_chunk(&c.kind, &c.data).is_some_and(|(k, _)| k == METADATA_KEYWORD))
+    });
+    let iend = chunks.iter().position(|c| &c.kind == b"IEND").unwrap_or(chunks.len());
+    chunks.insert(iend, itxt_chunk(METADATA_KEYWORD, &serde_json::to_string(metadata)?));
+    Ok(write_chunks(&chunks))
+}
+
+/// 読めないPNGは書き換えない。書き込み途中で壊れないよう、一時ファイルに書いてから置き換える
+fn write_metadata_file(file: &Path, metadata: &ScreenshotMetadata) -> Result<(), RustError> {
+    let bytes = embed_metadata(&std::fs::read(file)?, metadata)?;
+    let temp = file.with_extension("png.tmp");
+    std::fs::write(&temp, bytes)?;
+    std::fs::rename(&temp, file)?;
+    Ok(())
+}
+
+/// スクリーンショットフォルダの中のPNGだけを書き換えられるようにする
+fn resolve_screenshot(dir: &Path, path: &Path) -> Result<PathBuf, RustError> {
+    let dir = dir.canonicalize().map_err(|_| "errors.screenshotDirNotFound")?;
+    let path = path.canonicalize().map_err(|_| "errors.screenshotOutsideDir")?;
+    let is_png = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("png"));
+    if !is_png || !path.starts_with(&dir) {
+        return Err("errors.screenshotOutsideDir".into());
+    }
+    Ok(path)
+}
+
+/// インデックスのパスは設定したフォルダから辿ったままなので、正規化したパス同士で比べる
+fn find_entry<'a>(index: &'a mut [ScreenshotEntry], file: &Path) -> Option<&'a mut ScreenshotEntry> {
+    index
+        .iter_mut()
+        .find(|e| Path::new(&e.path).canonicalize().is_ok_and(|path| path == file))
+}
+
+/// 画像データは読み飛ばし、テキストチャンクだけを読み取る
+fn read_text_chunks(path: &Path) -> io::Result<Vec<(String, String)>> {
+    let mut file = File::open(path)?;
+    let mut signature = [0u8; 8];
+    file.read_exact(&mut signature)?;
+    if signature != PNG_SIGNATURE {
+        return Err(invalid_png());
+    }
+
+    let size = file.metadata()?.len();
+    let mut texts = Vec::new();
+    let mut header = [0u8; 8];
+    while file.read_exact(&mut header).is_ok() {
+        let len = u32::from_be_bytes(header[..4].try_into().unwrap());
+        // 長さは2^31-1まで。壊れたファイルの長さで大きなバッファを確保しないよう、残りのバイト数とも比べる
+        if len > i32::MAX as u32 || u64::from(len) + 4 > size.saturating_sub(file.stream_position()?) {
+            return Err(invalid_png());
+        }
+        let len = len as usize;
+        let kind: [u8; 4] = header[4..].try_into().unwrap();
+        if &kind == b"IEND" {
+            break;
+        }
+        if &kind == b"tEXt" || &kind == b"iTXt" {
+            let mut data = vec![0u8; len];
+            file.read_exact(&mut data)?;
+            texts.extend(parse_text_chunk(&kind, &data));
+            file.seek(SeekFrom::Current(4))?;
+        } else {
+            file.seek(SeekFrom::Current(len as i64 + 4))?;
+        }
+    }
+    Ok(texts)
+}
+
+fn xmp_value(xmp: &str, tag: &str) -> Option<String> {
+    let start = xmp.find(&format!("<{tag}>"))? + tag.len() + 2;
+    let end = start + xmp[start..].find(&format!("</{tag}>"))?;
+    Some(xmp[start..end].trim().to_string()).filter(|v| !v.is_empty())
+}
+
+fn parse_vrcx_metadata(text: &str) -> Option<ScreenshotMetadata> {
+    let json: serde_json::Value = serde_json::from_str(text).ok()?;
+    if json["application"].as_str() != Some("VRCX") {
+        return None;
+    }
+    let players = json["players"]
+        .as_array()
+        .map(|players| {
+            players
+                .iter()
+                .filter_map(|p| {
+                    Some(ScreenshotPlayer {
+                        display_name: p["displayName"].as_str()?.to_string(),
+                        user_id: p["id"].as_str().map(|id| id.to_string()),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Some(ScreenshotMetadata {
+        source: "VRCX".to_string(),
+        world_id: json["world"]["id"].as_str().map(|v| v.to_string()),
+        world_name: json["world"]["name"].as_str().map(|v| v.to_string()),
+        instance_id: json["world"]["instanceId"].as_str().map(|v| v.to_string()),
+        players,
+    })
+}
+
+/// 埋め込まれたメタデータを、このアプリ → VRCX → VRChat の優先順で読み取る
+fn parse_embedded_metadata(texts: &[(String, String)]) -> Option<ScreenshotMetadata> {
+    let find = |keyword: &str| texts.iter().find(|(k, _)| k == keyword).map(|(_, t)| t.as_str());
+
+    if let Some(metadata) = find(METADATA_KEYWORD).and_then(|t| serde_json::from_str(t).ok()) {
+        return Some(metadata);
+    }
+    if let Some(metadata) = find(VRCX_KEYWORD).and_then(parse_vrcx_metadata) {
+        return Some(metadata);
+    }
+    find(XMP_KEYWORD).map(|xmp| ScreenshotMetadata {
+        source: "VRChat".to_string(),
+        world_id: xmp_value(xmp, "vrc:WorldID"),
+        world_name: xmp_value(xmp, "vrc:WorldDisplayName"),
+        instance_id: None,
+        players: Vec::new(),
+    })
+}
+
+/// `VRChat_2024-01-15_21-10-30.123_1920x1080.png` 形式のファイル名から撮影日時を読み取る
+fn parse_file_name_timestamp(file_name: &str) -> Option<NaiveDateTime> {
+    (0..file_name.len().saturating_sub(FILE_NAME_TIMESTAMP_LENGTH - 1)).find_map(|i| {
+        let candidate = file_name.get(i..i + FILE_NAME_TIMESTAMP_LENGTH)?;
+        NaiveDateTime::parse_from_str(candidate, FILE_NAME_TIMESTAMP_FORMAT).ok()
+    })
+}
+
+fn modified_secs(path: &Path) -> io::Result<u32> {
+    let modified = std::fs::metadata(path)?.modified()?;
+    Ok(modified.duration_since(UNIX_EPOCH).map(|d| d.as_secs() as u32).unwrap_or(0))
+}
+
+fn taken_at(path: &Path, modified: u32) -> DateTime<Local> {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .and_then(parse_file_name_timestamp)
+        .and_then(|t| Local.from_local_datetime(&t).earliest())
+        .or_else(|| Local.timestamp_opt(modified as i64, 0).single())
+        .unwrap_or_else(Local::now)
+}
+
+fn parse_rfc3339(value: &str) -> Option<DateTime<Local>> {
+    DateTime::parse_from_rfc3339(value).ok().map(|d| d.with_timezone(&Local))
+}
+
+/// 撮影日時に滞在していたインスタンスと、その時点でいたプレイヤーを求める
+fn correlate(visits: &[InstanceVisit], at: DateTime<Local>) -> Option<ScreenshotMetadata> {
+    let is_within = |joined: &str, left: Option<&String>| {
+        parse_rfc3339(joined).is_some_and(|j| j <= at)
+            && left.and_then(|l| parse_rfc3339(l)).is_none_or(|l| at <= l)
+    };
+
+    let visit = visits
+        .iter()
+        .rev()
+        .find(|v| is_within(&v.joined_at, v.left_at.as_ref()))?;
+    let players = visit
+        .players
+        .iter()
+        .filter(|p| is_within(&p.joined_at, p.left_at.as_ref()))
+        .map(|p| ScreenshotPlayer {
+            display_name: p.display_name.clone(),
+            user_id: p.user_id.clone(),
+        })
+        .collect();
+
+    Some(ScreenshotMetadata {
+        source: METADATA_KEYWORD.to_string(),
+        world_id: Some(visit.world_id.clone()),
+        world_name: visit.world_name.clone(),
+        instance_id: Some(visit.instance_id.clone()),
+        players,
+    })
+}
+
+fn find_screenshots(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_screenshots(&path, files)?;
+        } else if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("png")) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn get_screenshot_dir_path(app_handle: &tauri::AppHandle) -> Option<PathBuf> {
+    let configured = app_handle
+        .store("store.json")
+        .ok()
+        .and_then(|store| store.get(SCREENSHOT_DIR_KEY))
+        .and_then(|value| value.as_str().map(PathBuf::from));
+    configured.or_else(|| dirs::picture_dir().map(|dir| dir.join("VRChat")))
+}
+
+fn load_index(app_handle: &tauri::AppHandle) -> Result<Vec<ScreenshotEntry>, RustError> {
+    let store = app_handle.store(SCREENSHOT_STORE)?;
+    match store.get(INDEX_KEY) {
+        Some(value) => Ok(serde_json::from_value(value)?),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn save_index(app_handle: &tauri::AppHandle, index: &[ScreenshotEntry]) -> Result<(), RustError> {
+    let store = app_handle.store(SCREENSHOT_STORE)?;
+    store.set(INDEX_KEY, serde_json::to_value(index)?);
+    store.save()?;
+    Ok(())
+}
+
+fn matches_text(value: Option<&String>, query: &str) -> bool {
+    value.is_some_and(|v| v.to_lowercase().contains(query))
+}
+
+fn matches_world(metadata: &ScreenshotMetadata, query: &str) -> bool {
+    matches_text(metadata.world_id.as_ref(), query) || matches_text(metadata.world_name.as_ref(), query)
+}
+
+fn matches_player(metadata: &ScreenshotMetadata, query: &str) -> bool {
+    metadata.players.iter().any(|p| {
+        p.display_name.to_lowercase().contains(query) || matches_text(p.user_id.as_ref(), query)
+    })
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn get_screenshot_dir(app_handle: tauri::AppHandle) -> Option<String> {
+    debug!("Call get_screenshot_dir");
+
+    get_screenshot_dir_path(&app_handle).map(|dir| dir.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn set_screenshot_dir(app_handle: tauri::AppHandle, dir: Option<String>) -> Result<(), RustError> {
+    debug!("Call set_screenshot_dir {:?}", dir);
+
+    let store = app_handle.store("store.json")?;
+    match dir {
+        Some(dir) => {
+            if !PathBuf::from(&dir).is_dir() {
+                return Err("errors.screenshotDirNotFound".into());
+            }
+            store.set(SCREENSHOT_DIR_KEY, dir);
+        }
+        None => {
+            store.delete(SCREENSHOT_DIR_KEY);
+        }
+    }
+    store.save()?;
+    Ok(())
+}
+
+/// スクリーンショットフォルダを走査してインデックスを作り直す。インデックスした枚数を返す
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn index_screenshots(app_handle: tauri::AppHandle) -> Result<u32, RustError> {
+    debug!("Call index_screenshots");
+
+    let dir = get_screenshot_dir_path(&app_handle).ok_or("errors.screenshotDirNotFound")?;
+    if !dir.is_dir() {
+        return Err("errors.screenshotDirNotFound".into());
+    }
+
+    let previous: HashMap<String, ScreenshotEntry> = load_index(&app_handle)?
+        .into_iter()
+        .map(|e| (e.path.clone(), e))
+        .collect();
+    let visits = get_visits(&app_handle).await?;
+
+    let index = tauri::async_runtime::spawn_blocking(move || -> io::Result<Vec<ScreenshotEntry>> {
+        let mut files = Vec::new();
+        find_screenshots(&dir, &mut files)?;
+
+        let mut index: Vec<ScreenshotEntry> = files
+            .into_iter()
+            .filter_map(|path| {
+                let modified = modified_secs(&path).ok()?;
+                let key = path.to_string_lossy().to_string();
+                let at = taken_at(&path, modified);
+                let embedded = match previous.get(&key) {
+                    Some(entry) if entry.modified == modified => entry.embedded.clone(),
+                    _ => read_text_chunks(&path)
+                        .ok()
+                        .and_then(|texts| parse_embedded_metadata(&texts)),
+                };
+                Some(ScreenshotEntry {
+                    path: key,
+                    taken_at: at.to_rfc3339(),
+                    modified,
+                    embedded,
+                    correlated: correlate(&visits, at),
+                })
+            })
+            .collect();
+        index.sort_by(|a, b| a.taken_at.cmp(&b.taken_at));
+        Ok(index)
+    })
+    .await??;
+
+    save_index(&app_handle, &index)?;
+    info!("Indexed {} screenshots", index.len());
+    Ok(index.len() as u32)
+}
+
+/// ワールド（IDか名前）やプレイヤー（表示名かID）でスクリーンショットを検索する。新しい順に返す
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn search_screenshots(
+    app_handle: tauri::AppHandle,
+    world: Option<String>,
+    player: Option<String>,
+    offset: u32,
+    n: u32,
+) -> Result<Vec<ScreenshotEntry>, RustError> {
+    debug!("Call search_screenshots {:?} {:?}", world, player);
+
+    let world = world.map(|w| w.trim().to_lowercase()).filter(|w| !w.is_empty());
+    let player = player.map(|p| p.trim().to_lowercase()).filter(|p| !p.is_empty());
+
+    Ok(load_index(&app_handle)?
+        .into_iter()
+        .rev()
+        .filter(|entry| {
+            let metadata = [entry.embedded.as_ref(), entry.correlated.as_ref()];
+            world
+                .as_deref()
+                .is_none_or(|w| metadata.iter().flatten().any(|m| matches_world(m, w)))
+                && player
+                    .as_deref()
+                    .is_none_or(|p| metadata.iter().flatten().any(|m| matches_player(m, p)))
+        })
+        .skip(offset as usize)
+        .take(n as usize)
+        .collect())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn read_screenshot_metadata(path: &str) -> Result<Option<ScreenshotMetadata>, RustError> {
+    debug!("Call read_screenshot_metadata {:?}", path);
+
+    let texts = read_text_chunks(Path::new(path))?;
+    Ok(parse_embedded_metadata(&texts))
+}
+
+/// インスタンス履歴から求めたワールド・インスタンス・同席者をPNGのiTXtチャンクに書き込む。
+/// 書き換えられるのはスクリーンショットフォルダの中のファイルだけ
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn write_screenshot_metadata(
+    app_handle: tauri::AppHandle,
+    path: &str,
+) -> Result<ScreenshotMetadata, RustError> {
+    debug!("Call write_screenshot_metadata {:?}", path);
+
+    let dir = get_screenshot_dir_path(&app_handle).ok_or("errors.screenshotDirNotFound")?;
+    let file = resolve_screenshot(&dir, Path::new(path))?;
+    let at = taken_at(&file, modified_secs(&file)?);
+    let visits = get_visits(&app_handle).await?;
+    let metadata = correlate(&visits, at).ok_or("errors.screenshotNoHistory")?;
+
+    write_metadata_file(&file, &metadata)?;
+
+    let mut index = load_index(&app_handle)?;
+    if let Some(entry) = find_entry(&mut index, &file) {
+        entry.embedded = Some(metadata.clone());
+        entry.modified = modified_secs(&file)?;
+        save_index(&app_handle, &index)?;
+    }
+
+    Ok(metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    /// 1x1の画像と、VRChatが書くXMPを持つ最小のPNG
+    fn sample_png() -> Vec<u8> {
+        write_chunks(&[
+            Chunk {
+                kind: *b"IHDR",
+                data: vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0],
+            },
+            itxt_chunk(XMP_KEYWORD, "<vrc:WorldID>wrld_xmp</vrc:WorldID>"),
+            Chunk {
+                kind: *b"IDAT",
+                data: vec![0x78, 0x9c, 0x63, 0x60, 0x00, 0x00, 0x00, 0x02, 0x00, 0x01],
+            },
+            Chunk {
+                kind: *b"IEND",
+                data: Vec::new(),
+            },
+        ])
+    }
+
+    fn sample_metadata(world_name: &str) -> ScreenshotMetadata {
+        ScreenshotMetadata {
+            source: METADATA_KEYWORD.to_string(),
+            world_id: Some("wrld_1".to_string()),
+            world_name: Some(world_name.to_string()),
+            instance_id: Some("12345~region(jp)".to_string()),
+            players: vec![ScreenshotPlayer {
+                display_name: "Alice".to_string(),
+                user_id: Some("usr_1".to_string()),
+            }],
+        }
+    }
+
+    /// すべてのチャンクのCRCを検証しながら (種類, データ) を返す
+    fn checked_chunks(bytes: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(bytes[..8], PNG_SIGNATURE);
+        let mut chunks = Vec::new();
+        let mut pos = 8;
+        while pos < bytes.len() {
+            let len = u32::from_be_bytes(bytes[pos..pos + 4].try_into().unwrap()) as usize;
+            let body = &bytes[pos + 4..pos + 8 + len];
+            let crc = u32::from_be_bytes(bytes[pos + 8 + len..pos + 12 + len].try_into().unwrap());
+            assert_eq!(crc, crc32fast::hash(body));
+            chunks.push((body[..4].try_into().unwrap(), body[4..].to_vec()));
+            pos += 12 + len;
+        }
+        chunks
+    }
+
+    #[test]
+    fn embedded_metadata_round_trips_and_keeps_image_data() {
+        let dir = TempDir::new("png-roundtrip");
+        let file = dir.join("VRChat_2024-01-15_21-10-30.123_1920x1080.png");
+        let original = sample_png();
+        std::fs::write(&file, &original).unwrap();
+
+        write_metadata_file(&file, &sample_metadata("First")).unwrap();
+        write_metadata_file(&file, &sample_metadata("Second")).unwrap();
+
+        let texts = read_text_chunks(&file).unwrap();
+        assert_eq!(texts.iter().filter(|(k, _)| k == METADATA_KEYWORD).count(), 1);
+        assert_eq!(parse_embedded_metadata(&texts), Some(sample_metadata("Second")));
+
+        let written = checked_chunks(&std::fs::read(&file).unwrap());
+        let image = |chunks: &[([u8; 4], Vec<u8>)]| -> Vec<([u8; 4], Vec<u8>)> {
+            chunks
+                .iter()
+                .filter(|(kind, _)| kind != b"iTXt")
+                .cloned()
+                .collect()
+        };
+        assert_eq!(image(&written), image(&checked_chunks(&original)));
+        assert_eq!(&written.last().unwrap().0, b"IEND");
+        // VRChatのXMPは残る
+        assert!(texts.iter().any(|(k, _)| k == XMP_KEYWORD));
+    }
+
+    #[test]
+    fn truncated_or_non_png_files_are_rejected_and_left_untouched() {
+        let dir = TempDir::new("png-invalid");
+        let png = sample_png();
+        let cases = [
+            ("truncated.png", png[..png.len() - 6].to_vec()),
+            ("cut-in-chunk.png", png[..30].to_vec()),
+            ("text.png", b"not a png at all".to_vec()),
+        ];
+        for (name, bytes) in cases {
+            let file = dir.join(name);
+            std::fs::write(&file, &bytes).unwrap();
+            assert!(write_metadata_file(&file, &sample_metadata("World")).is_err(), "{name}");
+            assert_eq!(std::fs::read(&file).unwrap(), bytes, "{name}");
+            assert!(!file.with_extension("png.tmp").exists());
+        }
+    }
+
+    #[test]
+    fn only_pngs_inside_the_screenshot_dir_can_be_written() {
+        let root = TempDir::new("png-resolve");
+        let dir = root.join("VRChat");
+        std::fs::create_dir_all(dir.join("2024-01")).unwrap();
+        let inside = dir.join("2024-01").join("shot.png");
+        let outside = root.join("other.png");
+        let not_png = dir.join("notes.txt");
+        for file in [&inside, &outside, &not_png] {
+            std::fs::write(file, sample_png()).unwrap();
+        }
+
+        assert_eq!(resolve_screenshot(&dir, &inside).unwrap(), inside.canonicalize().unwrap());
+        assert!(resolve_screenshot(&dir, &outside).is_err());
+        assert!(resolve_screenshot(&dir, &dir.join("..").join("other.png")).is_err());
+        assert!(resolve_screenshot(&dir, &not_png).is_err());
+        assert!(resolve_screenshot(&dir, &dir.join("missing.png")).is_err());
+    }
+
+    #[test]
+    fn chunk_lengths_beyond_the_file_are_rejected() {
+        let dir = TempDir::new("png-length");
+        let png = sample_png();
+        // IHDRの次のチャンク（XMPのiTXt）の長さを書き換える
+        let length_at = PNG_SIGNATURE.len() + 12 + 13;
+        // チャンクの中身とCRCに使えるバイト数
+        let left = (png.len() - length_at - 8) as u32;
+        for len in [u32::MAX, i32::MAX as u32 + 1, i32::MAX as u32, left - 3] {
+            let mut bytes = png.clone();
+            bytes[length_at..length_at + 4].copy_from_slice(&len.to_be_bytes());
+            let file = dir.join("broken.png");
+            std::fs::write(&file, &bytes).unwrap();
+            assert_eq!(read_text_chunks(&file).unwrap_err().kind(), io::ErrorKind::InvalidData, "{len}");
+        }
+
+        // ファイルの最後までを1つのチャンクとして読める長さは受け付ける
+        let mut bytes = png.clone();
+        bytes[length_at..length_at + 4].copy_from_slice(&(left - 4).to_be_bytes());
+        let file = dir.join("last.png");
+        std::fs::write(&file, &bytes).unwrap();
+        assert!(read_text_chunks(&file).is_ok());
+    }
+
+    #[test]
+    fn index_entries_are_found_by_resolved_path() {
+        let root = TempDir::new("png-index");
+        let dir = root.join("VRChat");
+        std::fs::create_dir_all(dir.join("2024-01")).unwrap();
+        let file = dir.join("2024-01").join("shot.png");
+        let other = dir.join("2024-01").join("other.png");
+        for file in [&file, &other] {
+            std::fs::write(file, sample_png()).unwrap();
+        }
+        let entry = |path: &Path| ScreenshotEntry {
+            path: path.to_string_lossy().to_string(),
+            taken_at: "2024-01-15T21:10:30+09:00".to_string(),
+            modified: 0,
+            embedded: None,
+            correlated: None,
+        };
+        // フォルダの設定が `..` を含むと、インデックスのパスも正規化されていない
+        let indexed = dir.join("2024-01").join("..").join("2024-01").join("shot.png");
+        let mut index = vec![entry(&other), entry(&indexed)];
+
+        let resolved = resolve_screenshot(&dir, &file).unwrap();
+        let found = find_entry(&mut index, &resolved).unwrap();
+        assert_eq!(found.path, indexed.to_string_lossy());
+        assert!(find_entry(&mut index, &dir.join("missing.png")).is_none());
+    }
+}
@@ -0,0 +1,362 @@
+use crate::commands::RustError;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::Emitter;
+use tauri_plugin_store::StoreExt;
+
+const SETTINGS_STORE: &str = "store.json";
+const SETTINGS_KEY: &str = "settings";
+const SCHEMA_VERSION_KEY: &str = "settings-version";
+pub(crate) const SETTINGS_CHANGED_EVENT: &str = "settings-changed";
+
+/// 設定の形式のバージョン。形式を変えたら上げて `MIGRATIONS` に変換を追加する
+const SCHEMA_VERSION: u32 = 1;
+
+/// `MIGRATIONS[n]` はバージョン `n` から `n + 1` への変換
+const MIGRATIONS: [fn(&mut StoreEntries); SCHEMA_VERSION as usize] = [migrate_flat_keys];
+
+type StoreEntries = serde_json::Map<String, serde_json::Value>;
+
+/// フロントエンドの翻訳がある言語
+const SUPPORTED_LANGUAGES: [&str; 2] = ["ja", "en"];
+const FETCH_FRIENDS_COUNT_MAX: u32 = 100;
+
+/// 画面を閉じるまでの間だけ使うフロントエンドのキャッシュ
+const INSTANCES_CACHE_KEY: &str = "instances-data";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub enum Theme {
+    Light,
+    Dark,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    pub theme: Theme,
+    pub language: String,
+    /// フレンド一覧を1ページで取得する人数（1〜100）
+    pub fetch_friends_count: u32,
+    pub auto_check_updates: bool,
+    /// スクリプトやオーバーレイ向けのローカルAPIを起動する
+    pub local_api_enabled: bool,
+    /// ローカルAPIのポート（127.0.0.1のみで待ち受ける）
+    pub local_api_port: u16,
+    /// 現在のワールド・インスタンスの種類・人数をJSONファイルに書き出す
+    pub presence_file_enabled: bool,
+    /// 書き出し先。空ならアプリのデータフォルダの presence.json
+    pub presence_file_path: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            theme: Theme::Light,
+            language: "ja".to_string(),
+            fetch_friends_count: 50,
+            auto_check_updates: true,
+            local_api_enabled: false,
+            local_api_port: 39410,
+            presence_file_enabled: false,
+            presence_file_path: String::new(),
+        }
+    }
+}
+
+/// 変更する設定。指定した項目だけ更新する
+#[derive(Debug, Clone, Default, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingsUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub theme: Option<Theme>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fetch_friends_count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_check_updates: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_api_enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_api_port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_file_enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_file_path: Option<String>,
+}
+
+impl Settings {
+    /// 保存前に値の範囲をチェックし、不正ならi18nのエラーキーを返す
+    pub(crate) fn validate(&self) -> Result<(), &'static str> {
+        if !SUPPORTED_LANGUAGES.contains(&self.language.as_str()) {
+            return Err("errors.unsupportedLanguage");
+        }
+        if !(1..=FETCH_FRIENDS_COUNT_MAX).contains(&self.fetch_friends_count) {
+            return Err("errors.invalidFetchFriendsCount");
+        }
+        // 特権ポートは使わない
+        if self.local_api_port < 1024 {
+            return Err("errors.invalidLocalApiPort");
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, update: SettingsUpdate) {
+        if let Some(theme) = update.theme {
+            self.theme = theme;
+        }
+        if let Some(language) = update.language {
+            self.language = language;
+        }
+        if let Some(count) = update.fetch_friends_count {
+            self.fetch_friends_count = count;
+        }
+        if let Some(auto_check_updates) = update.auto_check_updates {
+            self.auto_check_updates = auto_check_updates;
+        }
+        if let Some(enabled) = update.local_api_enabled {
+            self.local_api_enabled = enabled;
+        }
+        if let Some(port) = update.local_api_port {
+            self.local_api_port = port;
+        }
+        if let Some(enabled) = update.presence_file_enabled {
+            self.presence_file_enabled = enabled;
+        }
+        if let Some(path) = update.presence_file_path {
+            self.presence_file_path = path;
+        }
+    }
+}
+
+/// バージョン0 → 1: フロントエンドが store.json に直接書いていたキーを `settings` にまとめる
+fn migrate_flat_keys(entries: &mut StoreEntries) {
+    let mut settings = Settings::default();
+    if let Some(theme) = entries.get("data-theme").and_then(|value| serde_json::from_value(value.clone()).ok()) {
+        settings.theme = theme;
+    }
+    if let Some(language) = entries.get("lang").and_then(|value| value.as_str()) {
+        settings.language = language.to_string();
+    }
+    if let Some(count) = entries.get("fetch-friends-count").and_then(|value| value.as_u64()) {
+        settings.fetch_friends_count = count.min(u32::MAX as u64) as u32;
+    }
+    if let Some(auto_check_updates) = entries.get("auto-check-updates").and_then(|value| value.as_bool()) {
+        settings.auto_check_updates = auto_check_updates;
+    }
+
+    // 範囲外の値は既定値に戻す
+    let defaults = Settings::default();
+    if !SUPPORTED_LANGUAGES.contains(&settings.language.as_str()) {
+        settings.language = defaults.language;
+    }
+    if !(1..=FETCH_FRIENDS_COUNT_MAX).contains(&settings.fetch_friends_count) {
+        settings.fetch_friends_count = defaults.fetch_friends_count;
+    }
+
+    for key in ["data-theme", "lang", "fetch-friends-count", "auto-check-updates", "user-data"] {
+        entries.remove(key);
+    }
+    entries.insert(SETTINGS_KEY.to_string(), serde_json::to_value(settings).unwrap_or_default());
+}
+
+/// store.json の中身を現在の形式に変換する。変換したかを返す
+fn migrate_entries(entries: &mut StoreEntries) -> bool {
+    let version = entries
+        .get(SCHEMA_VERSION_KEY)
+        .and_then(|value| value.as_u64())
+        .unwrap_or(0) as u32;
+    if version > SCHEMA_VERSION {
+        warn!("Settings schema {} is newer than {}, leaving it as is", version, SCHEMA_VERSION);
+        return false;
+    }
+    if version == SCHEMA_VERSION {
+        return false;
+    }
+
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        info!("Migrating settings from version {} to {}", from, from + 1);
+        migration(entries);
+    }
+    entries.insert(SCHEMA_VERSION_KEY.to_string(), SCHEMA_VERSION.into());
+    true
+}
+
+/// 起動時に古い形式の設定を現在の形式に変換する
+pub(crate) fn migrate(app_handle: &tauri::AppHandle) -> Result<(), RustError> {
+    let store = app_handle.store(SETTINGS_STORE)?;
+    let before: StoreEntries = store.entries().into_iter().collect();
+    let mut entries = before.clone();
+    if !migrate_entries(&mut entries) {
+        return Ok(());
+    }
+
+    for key in before.keys().filter(|key| !entries.contains_key(*key)) {
+        store.delete(key);
+    }
+    for (key, value) in entries {
+        if before.get(&key) != Some(&value) {
+            store.set(key, value);
+        }
+    }
+    store.save()?;
+    Ok(())
+}
+
+pub(crate) fn load_settings(app_handle: &tauri::AppHandle) -> Result<Settings, RustError> {
+    let store = app_handle.store(SETTINGS_STORE)?;
+    let settings: Settings = match store.get(SETTINGS_KEY) {
+        Some(value) => serde_json::from_value(value)?,
+        None => Settings::default(),
+    };
+    Ok(settings)
+}
+
+/// 終了時に、次回起動時に持ち越さないキャッシュを消す
+pub(crate) fn clear_session_cache(app_handle: &tauri::AppHandle) -> Result<(), RustError> {
+    let store = app_handle.store(SETTINGS_STORE)?;
+    store.delete(INSTANCES_CACHE_KEY);
+    store.save()?;
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn get_settings(app_handle: tauri::AppHandle) -> Result<Settings, RustError> {
+    debug!("Call get_settings");
+
+    load_settings(&app_handle)
+}
+
+/// 設定を更新して保存し、`settings-changed` イベントで新しい設定を通知する
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn update_settings(app_handle: tauri::AppHandle, update: SettingsUpdate) -> Result<Settings, RustError> {
+    debug!("Call update_settings {:?}", update);
+
+    let mut settings = load_settings(&app_handle)?;
+    let previous = settings.clone();
+    settings.apply(update);
+    settings.validate()?;
+    if settings == previous {
+        return Ok(settings);
+    }
+
+    let store = app_handle.store(SETTINGS_STORE)?;
+    store.set(SETTINGS_KEY, serde_json::to_value(&settings)?);
+    store.save()?;
+    app_handle.emit(SETTINGS_CHANGED_EVENT, &settings)?;
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entries(value: serde_json::Value) -> StoreEntries {
+        value.as_object().unwrap().clone()
+    }
+
+    fn migrated_settings(entries: &StoreEntries) -> Settings {
+        serde_json::from_value(entries[SETTINGS_KEY].clone()).unwrap()
+    }
+
+    #[test]
+    fn migrates_flat_keys_from_version_0() {
+        let mut store = entries(json!({
+            "data-theme": "dark",
+            "lang": "en",
+            "fetch-friends-count": 30,
+            "auto-check-updates": false,
+            "user-data": {"name": "old"},
+            "users": [{"id": "usr_a"}],
+        }));
+        assert!(migrate_entries(&mut store));
+
+        let settings = migrated_settings(&store);
+        assert_eq!(settings.theme, Theme::Dark);
+        assert_eq!(settings.language, "en");
+        assert_eq!(settings.fetch_friends_count, 30);
+        assert!(!settings.auto_check_updates);
+        assert_eq!(store[SCHEMA_VERSION_KEY], json!(SCHEMA_VERSION));
+        // 設定以外のキーは残し、平たいキーは消す
+        let mut keys: Vec<&str> = store.keys().map(String::as_str).collect();
+        keys.sort();
+        assert_eq!(keys, vec![SETTINGS_KEY, SCHEMA_VERSION_KEY, "users"]);
+    }
+
+    #[test]
+    fn resets_out_of_range_legacy_values() {
+        for count in [0, 101, u64::MAX] {
+            let mut store = entries(json!({"lang": "fr", "fetch-friends-count": count, "data-theme": "sepia"}));
+            assert!(migrate_entries(&mut store));
+
+            let settings = migrated_settings(&store);
+            assert_eq!(settings, Settings::default(), "count {count}");
+        }
+    }
+
+    #[test]
+    fn leaves_current_version_untouched() {
+        let original = entries(json!({
+            SETTINGS_KEY: {"theme": "dark", "language": "en"},
+            SCHEMA_VERSION_KEY: SCHEMA_VERSION,
+            "lang": "ja",
+        }));
+        let mut store = original.clone();
+        assert!(!migrate_entries(&mut store));
+        assert_eq!(store, original);
+    }
+
+    #[test]
+    fn leaves_newer_version_untouched() {
+        let original = entries(json!({
+            "data-theme": "dark",
+            SCHEMA_VERSION_KEY: SCHEMA_VERSION + 1,
+        }));
+        let mut store = original.clone();
+        assert!(!migrate_entries(&mut store));
+        assert_eq!(store, original);
+    }
+
+    #[test]
+    fn validate_checks_ranges() {
+        assert_eq!(Settings::default().validate(), Ok(()));
+
+        let invalid = [
+            (Settings { language: "fr".to_string(), ..Settings::default() }, "errors.unsupportedLanguage"),
+            (Settings { fetch_friends_count: 0, ..Settings::default() }, "errors.invalidFetchFriendsCount"),
+            (Settings { fetch_friends_count: 101, ..Settings::default() }, "errors.invalidFetchFriendsCount"),
+            (Settings { local_api_port: 80, ..Settings::default() }, "errors.invalidLocalApiPort"),
+        ];
+        for (settings, error) in invalid {
+            assert_eq!(settings.validate(), Err(error));
+        }
+    }
+
+    #[test]
+    fn apply_changes_only_given_fields() {
+        let mut settings = Settings::default();
+        settings.apply(SettingsUpdate {
+            theme: Some(Theme::Dark),
+            local_api_port: Some(40000),
+            presence_file_path: Some("C:/presence.json".to_string()),
+            ..SettingsUpdate::default()
+        });
+
+        assert_eq!(
+            settings,
+            Settings {
+                theme: Theme::Dark,
+                local_api_port: 40000,
+                presence_file_path: "C:/presence.json".to_string(),
+                ..Settings::default()
+            }
+        );
+    }
+}
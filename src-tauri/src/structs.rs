@@ -21,8 +21,177 @@ pub(crate) struct Worlds {
     pub(crate) world: HashMap<String, World>,
 }
 
+#[derive(Default, Clone)]
+pub(crate) struct Avatars {
+    /// `/avatars/{id}` の応答そのもの。一覧の応答は項目が欠けているので入れない
+    pub(crate) avatar: HashMap<String, String>,
+}
+
 #[derive(Default, Clone)]
 pub(crate) struct AppState {
     pub(crate) is_login: bool,
     pub(crate) worlds: Worlds,
+    pub(crate) avatars: Avatars,
+    /// 読み込み中のフレンド一覧（ユーザーID → 表示名）。全ページ取得後にスナップショットとして記録する
+    pub(crate) loading_friends: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+pub enum UserStatus {
+    #[serde(rename = "join me")]
+    JoinMe,
+    #[serde(rename = "active")]
+    Active,
+    #[serde(rename = "ask me")]
+    AskMe,
+    #[serde(rename = "busy")]
+    Busy,
+}
+
+pub(crate) const STATUS_DESCRIPTION_MAX_LENGTH: usize = 32;
+pub(crate) const BIO_MAX_LENGTH: usize = 512;
+pub(crate) const BIO_LINKS_MAX_COUNT: usize = 3;
+pub(crate) const PRONOUNS_MAX_LENGTH: usize = 32;
+pub(crate) const STATUS_HISTORY_MAX_COUNT: usize = 5;
+
+/// `PUT /users/{id}` に送る自分のプロフィール更新内容。指定した項目だけ更新する
+#[derive(Debug, Clone, Default, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct CurrentUserUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<UserStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bio: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bio_links: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pronouns: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_history: Option<Vec<String>>,
+}
+
+impl CurrentUserUpdate {
+    /// 送信前に文字数・件数の上限をチェックし、超えていればi18nのエラーキーを返す
+    pub(crate) fn validate(&self) -> Result<(), &'static str> {
+        if let Some(description) = &self.status_description {
+            if description.chars().count() > STATUS_DESCRIPTION_MAX_LENGTH {
+                return Err("errors.statusDescriptionTooLong");
+            }
+        }
+        if let Some(bio) = &self.bio {
+            if bio.chars().count() > BIO_MAX_LENGTH {
+                return Err("errors.bioTooLong");
+            }
+        }
+        if let Some(links) = &self.bio_links {
+            if links.len() > BIO_LINKS_MAX_COUNT {
+                return Err("errors.tooManyBioLinks");
+            }
+            if links
+                .iter()
+                .any(|link| !(link.starts_with("https://") || link.starts_with("http://")))
+            {
+                return Err("errors.invalidBioLink");
+            }
+        }
+        if let Some(pronouns) = &self.pronouns {
+            if pronouns.chars().count() > PRONOUNS_MAX_LENGTH {
+                return Err("errors.pronounsTooLong");
+            }
+        }
+        if let Some(history) = &self.status_history {
+            if history.len() > STATUS_HISTORY_MAX_COUNT {
+                return Err("errors.tooManyStatusHistory");
+            }
+            if history
+                .iter()
+                .any(|h| h.chars().count() > STATUS_DESCRIPTION_MAX_LENGTH)
+            {
+                return Err("errors.statusDescriptionTooLong");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 文字数で数えることを確かめるため、1文字が複数バイトになる文字で作る
+    fn text(length: usize) -> String {
+        "あ".repeat(length)
+    }
+
+    fn links(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("https://example.com/{i}")).collect()
+    }
+
+    /// 上限ちょうどは通り、1文字多いと `error` になる
+    fn assert_length_limit(update: impl Fn(String) -> CurrentUserUpdate, max: usize, error: &str) {
+        assert_eq!(update(text(max)).validate(), Ok(()), "{error}");
+        assert_eq!(update(text(max + 1)).validate(), Err(error));
+    }
+
+    #[test]
+    fn text_fields_accept_the_limit_and_reject_one_more() {
+        let status_description = |value| CurrentUserUpdate {
+            status_description: Some(value),
+            ..Default::default()
+        };
+        assert_length_limit(status_description, STATUS_DESCRIPTION_MAX_LENGTH, "errors.statusDescriptionTooLong");
+
+        let bio = |value| CurrentUserUpdate {
+            bio: Some(value),
+            ..Default::default()
+        };
+        assert_length_limit(bio, BIO_MAX_LENGTH, "errors.bioTooLong");
+
+        let pronouns = |value| CurrentUserUpdate {
+            pronouns: Some(value),
+            ..Default::default()
+        };
+        assert_length_limit(pronouns, PRONOUNS_MAX_LENGTH, "errors.pronounsTooLong");
+
+        // 履歴の各項目もステータスの説明と同じ上限
+        let history = |value| CurrentUserUpdate {
+            status_history: Some(vec![text(1), value]),
+            ..Default::default()
+        };
+        assert_length_limit(history, STATUS_DESCRIPTION_MAX_LENGTH, "errors.statusDescriptionTooLong");
+    }
+
+    #[test]
+    fn lists_accept_the_limit_and_reject_one_more() {
+        let bio_links = |count| CurrentUserUpdate {
+            bio_links: Some(links(count)),
+            ..Default::default()
+        };
+        assert_eq!(bio_links(BIO_LINKS_MAX_COUNT).validate(), Ok(()));
+        assert_eq!(bio_links(BIO_LINKS_MAX_COUNT + 1).validate(), Err("errors.tooManyBioLinks"));
+
+        let history = |count| CurrentUserUpdate {
+            status_history: Some(vec![text(1); count]),
+            ..Default::default()
+        };
+        assert_eq!(history(STATUS_HISTORY_MAX_COUNT).validate(), Ok(()));
+        assert_eq!(history(STATUS_HISTORY_MAX_COUNT + 1).validate(), Err("errors.tooManyStatusHistory"));
+    }
+
+    #[test]
+    fn bio_links_must_be_http_urls() {
+        let update = |links: Vec<String>| CurrentUserUpdate {
+            bio_links: Some(links),
+            ..Default::default()
+        };
+        assert_eq!(update(vec!["http://example.com".to_string()]).validate(), Ok(()));
+        for link in ["example.com", "ftp://example.com", "javascript:alert(1)", ""] {
+            assert_eq!(update(vec![link.to_string()]).validate(), Err("errors.invalidBioLink"), "{link}");
+        }
+        assert_eq!(CurrentUserUpdate::default().validate(), Ok(()));
+    }
 }
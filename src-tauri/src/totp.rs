@@ -0,0 +1,121 @@
+use crate::commands::RustError;
+use crate::headless::AppDirs;
+use crate::redaction::{register_secret, SecretKind};
+use keyring::Entry;
+use log::debug;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+/// VRChatの認証アプリ用のコードはSHA1・6桁・30秒
+const DIGITS: usize = 6;
+const STEP: u64 = 30;
+
+fn secret_entry(app: &impl AppDirs, user_id: &str) -> keyring::Result<Entry> {
+    Entry::new(app.identifier(), &format!("totp:{user_id}"))
+}
+
+/// 認証アプリに表示されるBase32の文字列からTOTPを作る。空白やハイフン区切り、小文字も受け付ける
+fn build_totp(secret: &str) -> Result<TOTP, RustError> {
+    let normalized: String = secret
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    let bytes = Secret::Encoded(normalized)
+        .to_bytes()
+        .map_err(|_| "errors.invalidTotpSecret")?;
+    if bytes.is_empty() {
+        return Err("errors.invalidTotpSecret".into());
+    }
+    // RFCが推奨する128bitより短いシークレットを使うサービスもあるので、長さは確認しない
+    Ok(TOTP::new_unchecked(Algorithm::SHA1, DIGITS, 1, STEP, bytes))
+}
+
+/// シークレットから現在のコードを作る
+pub(crate) fn generate_from_secret(secret: &str) -> Result<String, RustError> {
+    register_secret(SecretKind::Credential, secret);
+    let code = build_totp(secret)?.generate_current()?;
+    register_secret(SecretKind::Otp, &code);
+    Ok(code)
+}
+
+/// 保存されているシークレットから現在のコードを作る。シークレットが無ければ `None`
+pub(crate) fn generate_code(app: &impl AppDirs, user_id: &str) -> Result<Option<String>, RustError> {
+    match secret_entry(app, user_id)?.get_password() {
+        Ok(secret) => Ok(Some(generate_from_secret(&secret)?)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// シークレットをキーリングに保存する。コードを作れない値は保存しない
+pub(crate) fn save_secret(app_handle: &tauri::AppHandle, user_id: &str, secret: &str) -> Result<(), RustError> {
+    generate_from_secret(secret)?;
+    secret_entry(app_handle, user_id)?.set_password(secret)?;
+    Ok(())
+}
+
+pub(crate) fn delete_secret(app_handle: &tauri::AppHandle, user_id: &str) -> Result<(), RustError> {
+    match secret_entry(app_handle, user_id).and_then(|entry| entry.delete_credential()) {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn has_totp_secret(app_handle: tauri::AppHandle, user_id: &str) -> Result<bool, RustError> {
+    debug!("Call has_totp_secret {:?}", user_id);
+
+    match secret_entry(&app_handle, user_id)?.get_password() {
+        Ok(_) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn forget_totp_secret(app_handle: tauri::AppHandle, user_id: &str) -> Result<(), RustError> {
+    debug!("Call forget_totp_secret {:?}", user_id);
+
+    delete_secret(&app_handle, user_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RFC 6238 付録BのSHA1のシークレット "12345678901234567890" をBase32にしたもの
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn matches_rfc6238_sha1_vectors() {
+        // RFCの8桁のコードの下6桁
+        let vectors = [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+            (20000000000, "353130"),
+        ];
+        let totp = build_totp(RFC_SECRET).unwrap();
+        for (time, code) in vectors {
+            assert_eq!(totp.generate(time), code, "time {time}");
+        }
+    }
+
+    #[test]
+    fn accepts_grouped_lowercase_secret() {
+        let grouped = "gezd gnbv-gy3t qojq gezd gnbv gy3t qojq";
+        assert_eq!(build_totp(grouped).unwrap().generate(59), "287082");
+    }
+
+    #[test]
+    fn rejects_invalid_secret() {
+        for secret in ["", " - ", "not base32 1890"] {
+            let err = build_totp(secret).err().unwrap();
+            assert_eq!(err.message(), "errors.invalidTotpSecret", "{secret:?}");
+        }
+    }
+}
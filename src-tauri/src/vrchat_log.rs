@@ -0,0 +1,331 @@
+use chrono::NaiveDateTime;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+const LOG_FILE_PREFIX: &str = "output_log_";
+const LOG_FILE_EXTENSION: &str = "txt";
+const TIMESTAMP_FORMAT: &str = "%Y.%m.%d %H:%M:%S";
+const TIMESTAMP_LENGTH: usize = 19;
+
+/// VRChatの `output_log_*.txt` から読み取れるイベント
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum LogEvent {
+    RoomJoin {
+        timestamp: NaiveDateTime,
+        location: String,
+    },
+    RoomName {
+        timestamp: NaiveDateTime,
+        name: String,
+    },
+    PlayerJoined {
+        timestamp: NaiveDateTime,
+        display_name: String,
+        user_id: Option<String>,
+    },
+    PlayerLeft {
+        timestamp: NaiveDateTime,
+        display_name: String,
+        user_id: Option<String>,
+    },
+    VideoUrl {
+        timestamp: NaiveDateTime,
+        url: String,
+    },
+    PortalDropped {
+        timestamp: NaiveDateTime,
+    },
+    LeftRoom {
+        timestamp: NaiveDateTime,
+    },
+}
+
+/// 古いバージョンのログは `表示名` のみ、新しいものは `表示名 (usr_xxx)` の形式
+fn parse_player(value: &str) -> (String, Option<String>) {
+    let value = value.trim();
+    if let Some(index) = value.rfind(" (usr_") {
+        if value.ends_with(')') {
+            let user_id = &value[index + 2..value.len() - 1];
+            return (value[..index].to_string(), Some(user_id.to_string()));
+        }
+    }
+    (value.to_string(), None)
+}
+
+pub(crate) fn parse_line(line: &str) -> Option<LogEvent> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    let timestamp = NaiveDateTime::parse_from_str(line.get(..TIMESTAMP_LENGTH)?, TIMESTAMP_FORMAT).ok()?;
+    let message = line[line.find(" -  ")? + 4..].trim();
+
+    if let Some(name) = message.strip_prefix("[Behaviour] Joining or Creating Room: ") {
+        Some(LogEvent::RoomName {
+            timestamp,
+            name: name.to_string(),
+        })
+    } else if let Some(name) = message.strip_prefix("[Behaviour] Entering Room: ") {
+        Some(LogEvent::RoomName {
+            timestamp,
+            name: name.to_string(),
+        })
+    } else if let Some(location) = message.strip_prefix("[Behaviour] Joining ") {
+        location.starts_with("wrld_").then(|| LogEvent::RoomJoin {
+            timestamp,
+            location: location.to_string(),
+        })
+    } else if let Some(player) = message.strip_prefix("[Behaviour] OnPlayerJoined ") {
+        let (display_name, user_id) = parse_player(player);
+        Some(LogEvent::PlayerJoined {
+            timestamp,
+            display_name,
+            user_id,
+        })
+    } else if let Some(player) = message.strip_prefix("[Behaviour] OnPlayerLeft ") {
+        let (display_name, user_id) = parse_player(player);
+        Some(LogEvent::PlayerLeft {
+            timestamp,
+            display_name,
+            user_id,
+        })
+    } else if message == "[Behaviour] OnLeftRoom" {
+        Some(LogEvent::LeftRoom { timestamp })
+    } else if let Some(url) = message.strip_prefix("[Video Playback] Attempting to resolve URL '") {
+        Some(LogEvent::VideoUrl {
+            timestamp,
+            url: url.trim_end_matches('\'').to_string(),
+        })
+    } else if message.starts_with("[Behaviour] Instantiated a (Clone [")
+        && message.contains("Portals/PortalInternalDynamic")
+    {
+        Some(LogEvent::PortalDropped { timestamp })
+    } else {
+        None
+    }
+}
+
+pub(crate) fn is_log_file(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == LOG_FILE_EXTENSION)
+        && path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with(LOG_FILE_PREFIX))
+}
+
+/// ファイル名に日時が含まれるため、名前順に並べると古い順になる
+pub(crate) fn find_log_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| is_log_file(path))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+pub(crate) fn parse_log_text(text: &str) -> Vec<LogEvent> {
+    text.lines().filter_map(parse_line).collect()
+}
+
+pub(crate) fn parse_log_file(path: &Path) -> io::Result<Vec<LogEvent>> {
+    let bytes = std::fs::read(path)?;
+    Ok(parse_log_text(&String::from_utf8_lossy(&bytes)))
+}
+
+/// デフォルトのログ出力先（`%USERPROFILE%\AppData\LocalLow\VRChat\VRChat`）
+pub(crate) fn default_log_dir() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join("AppData").join("LocalLow").join("VRChat").join("VRChat"))
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct TailBatch {
+    /// 新しいログファイルに切り替わった（VRChatが再起動された）
+    pub(crate) rotated: bool,
+    /// 切り替わる前のファイルに残っていた行
+    pub(crate) previous: Vec<LogEvent>,
+    pub(crate) events: Vec<LogEvent>,
+}
+
+/// ディレクトリ内の最新のログファイルを追いかけ、追記された行だけを読み取る
+pub(crate) struct LogTailer {
+    dir: PathBuf,
+    current: Option<PathBuf>,
+    offset: u64,
+    partial: Vec<u8>,
+}
+
+impl LogTailer {
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            current: None,
+            offset: 0,
+            partial: Vec::new(),
+        }
+    }
+
+    pub(crate) fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub(crate) fn current_file(&self) -> Option<&Path> {
+        self.current.as_deref()
+    }
+
+    pub(crate) fn poll(&mut self) -> io::Result<TailBatch> {
+        let mut batch = TailBatch::default();
+        let Some(latest) = find_log_files(&self.dir)?.pop() else {
+            return Ok(batch);
+        };
+
+        if self.current.as_ref() != Some(&latest) {
+            if self.current.is_some() {
+                // 切り替わる前のファイルに残っている行を読み切る
+                batch.previous.extend(self.read_new_lines()?);
+                if !self.partial.is_empty() {
+                    let rest = std::mem::take(&mut self.partial);
+                    batch.previous.extend(parse_line(&String::from_utf8_lossy(&rest)));
+                }
+                batch.rotated = true;
+            }
+            self.current = Some(latest);
+            self.offset = 0;
+            self.partial.clear();
+        }
+
+        batch.events.extend(self.read_new_lines()?);
+        Ok(batch)
+    }
+
+    fn read_new_lines(&mut self) -> io::Result<Vec<LogEvent>> {
+        let Some(path) = &self.current else {
+            return Ok(Vec::new());
+        };
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let len = file.metadata()?.len();
+        if len < self.offset {
+            // 切り詰められた場合は先頭から読み直す
+            self.offset = 0;
+            self.partial.clear();
+        }
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        self.offset += bytes.len() as u64;
+
+        self.partial.extend_from_slice(&bytes);
+        let Some(last_newline) = self.partial.iter().rposition(|b| *b == b'\n') else {
+            return Ok(Vec::new());
+        };
+        let complete: Vec<u8> = self.partial.drain(..=last_newline).collect();
+
+        Ok(parse_log_text(&String::from_utf8_lossy(&complete)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::io::Write;
+
+    const FIXTURE: &str = include_str!("../tests/fixtures/output_log_2024-01-15_21-00-00.txt");
+
+    fn ts(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, TIMESTAMP_FORMAT).unwrap()
+    }
+
+    #[test]
+    fn parses_room_join() {
+        let event = parse_line(
+            "2024.01.15 21:03:12 Log        -  [Behaviour] Joining wrld_4cf554b4-430c-4f8f-b53e-1f294eed230b:12345~region(jp)",
+        );
+        assert_eq!(
+            event,
+            Some(LogEvent::RoomJoin {
+                timestamp: ts("2024.01.15 21:03:12"),
+                location: "wrld_4cf554b4-430c-4f8f-b53e-1f294eed230b:12345~region(jp)".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn parses_player_with_and_without_user_id() {
+        assert_eq!(
+            parse_line("2024.01.15 21:03:20 Log        -  [Behaviour] OnPlayerJoined Alice (usr_1111)"),
+            Some(LogEvent::PlayerJoined {
+                timestamp: ts("2024.01.15 21:03:20"),
+                display_name: "Alice".to_string(),
+                user_id: Some("usr_1111".to_string()),
+            })
+        );
+        assert_eq!(
+            parse_line("2024.01.15 21:03:20 Log        -  [Behaviour] OnPlayerLeft Bob (the builder)"),
+            Some(LogEvent::PlayerLeft {
+                timestamp: ts("2024.01.15 21:03:20"),
+                display_name: "Bob (the builder)".to_string(),
+                user_id: None,
+            })
+        );
+    }
+
+    #[test]
+    fn ignores_unrelated_lines() {
+        assert_eq!(parse_line("2024.01.15 21:03:20 Log        -  [Behaviour] OnPlayerLeftRoom"), None);
+        assert_eq!(parse_line("   at UnityEngine.Debug.Log"), None);
+        assert_eq!(parse_line(""), None);
+    }
+
+    #[test]
+    fn parses_fixture() {
+        let events = parse_log_text(FIXTURE);
+        let joins = events.iter().filter(|e| matches!(e, LogEvent::RoomJoin { .. })).count();
+        let players = events.iter().filter(|e| matches!(e, LogEvent::PlayerJoined { .. })).count();
+        let videos: Vec<&str> = events
+            .iter()
+            .filter_map(|e| match e {
+                LogEvent::VideoUrl { url, .. } => Some(url.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(joins, 2);
+        assert_eq!(players, 5);
+        assert_eq!(videos, vec!["https://www.youtube.com/watch?v=dQw4w9WgXcQ"]);
+        assert!(events.iter().any(|e| matches!(e, LogEvent::PortalDropped { .. })));
+    }
+
+    #[test]
+    fn tailer_reads_appended_lines_and_follows_rotation() {
+        let dir = TempDir::new("tailer");
+        let first = dir.join("output_log_2024-01-15_21-00-00.txt");
+        std::fs::write(&first, "2024.01.15 21:03:12 Log        -  [Behaviour] Joining wrld_a:1\n").unwrap();
+
+        let mut tailer = LogTailer::new(dir.to_path_buf());
+        let batch = tailer.poll().unwrap();
+        assert!(!batch.rotated);
+        assert_eq!(batch.events.len(), 1);
+
+        // 改行が来るまでは途中の行を読まない
+        let mut file = std::fs::OpenOptions::new().append(true).open(&first).unwrap();
+        write!(file, "2024.01.15 21:03:20 Log        -  [Behaviour] OnPlayerJoined Alice").unwrap();
+        assert!(tailer.poll().unwrap().events.is_empty());
+        writeln!(file, " (usr_1111)").unwrap();
+        let batch = tailer.poll().unwrap();
+        assert_eq!(batch.events.len(), 1);
+        assert!(matches!(&batch.events[0], LogEvent::PlayerJoined { user_id: Some(id), .. } if id == "usr_1111"));
+
+        writeln!(file, "2024.01.15 22:00:00 Log        -  [Behaviour] OnLeftRoom").unwrap();
+        let second = dir.join("output_log_2024-01-16_20-00-00.txt");
+        std::fs::write(&second, "2024.01.16 20:01:00 Log        -  [Behaviour] Joining wrld_b:2\n").unwrap();
+
+        let batch = tailer.poll().unwrap();
+        assert!(batch.rotated);
+        assert_eq!(tailer.current_file(), Some(second.as_path()));
+        assert!(matches!(batch.previous[..], [LogEvent::LeftRoom { .. }]));
+        assert!(matches!(&batch.events[..], [LogEvent::RoomJoin { location, .. }] if location == "wrld_b:2"));
+    }
+}
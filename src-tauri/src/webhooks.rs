@@ -0,0 +1,599 @@
+use crate::commands::RustError;
+use crate::friend_events::{self, FriendEvent, FriendEventKind};
+use chrono::Local;
+use log::{debug, error, info, warn};
+use once_cell::sync::Lazy;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri_plugin_store::StoreExt;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{Mutex, Notify};
+
+pub(crate) const WEBHOOKS_STORE: &str = "webhooks.json";
+const WEBHOOKS_KEY: &str = "webhooks";
+const LOG_KEY: &str = "log";
+const LOG_MAX_ENTRIES: usize = 200;
+
+const MAX_ATTEMPTS: u32 = 3;
+/// 再送までの待ち時間。失敗するたびに倍にする
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+/// `Retry-After` で指定されても、これ以上は待たない
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// VRChatのCookieを送らないように、セッションとは別のクライアントを使う
+static CLIENT: Lazy<Client> = Lazy::new(|| {
+    Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .user_agent(concat!("vrc-social-manager/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .unwrap_or_default()
+});
+
+static CONFIG_CHANGED: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// 配信ログの読み書きを直列化する
+static LOG_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct Webhook {
+    pub id: String,
+    pub name: String,
+    pub enabled: bool,
+    pub url: String,
+    pub events: Vec<FriendEventKind>,
+    /// 送るJSON。`{displayName}` などは文字列としてエスケープした値に置き換える
+    pub template: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[specta(export)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookDelivery {
+    pub timestamp: String,
+    pub webhook_id: String,
+    pub webhook_name: String,
+    pub event: FriendEventKind,
+    /// 最後の応答のステータスコード。接続できなかった場合は `None`
+    pub status: Option<u16>,
+    pub attempts: u32,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// JSONの文字列の中に入れられるように値をエスケープして `{name}` を置き換える
+fn render(template: &str, variables: &[(&str, String)]) -> String {
+    friend_events::render_template(template, variables, |value| {
+        let escaped = serde_json::to_string(value).unwrap_or_default();
+        escaped[1..escaped.len() - 1].to_string()
+    })
+}
+
+fn render_payload(
+    template: &str,
+    event: FriendEventKind,
+    variables: &[(&str, String)],
+) -> Result<serde_json::Value, RustError> {
+    let mut variables = variables.to_vec();
+    variables.push(("event", serde_json::to_value(event)?.as_str().unwrap_or_default().to_string()));
+    serde_json::from_str(&render(template, &variables)).map_err(|_| "errors.invalidWebhookTemplate".into())
+}
+
+fn validate_url(url: &str) -> Result<(), RustError> {
+    let url = reqwest::Url::parse(url).map_err(|_| "errors.invalidWebhookUrl")?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("errors.invalidWebhookUrl".into());
+    }
+    Ok(())
+}
+
+/// バックアップから復元したWebhookはURLが空のまま無効になっているので、無効なものは空のURLを許す
+fn validate_webhook(webhook: &Webhook) -> Result<(), RustError> {
+    if webhook.enabled || !webhook.url.is_empty() {
+        validate_url(&webhook.url)?;
+    }
+    render_payload(&webhook.template, FriendEventKind::FriendOnline, &friend_events::sample_variables())?;
+    Ok(())
+}
+
+/// 接続できない・429・5xxのときだけ再送する
+async fn deliver(webhook: &Webhook, event: FriendEventKind, payload: &serde_json::Value) -> WebhookDelivery {
+    deliver_with_delay(webhook, event, payload, RETRY_DELAY).await
+}
+
+/// `retry_delay` は最初の再送までの待ち時間。テストでは短くする
+async fn deliver_with_delay(
+    webhook: &Webhook,
+    event: FriendEventKind,
+    payload: &serde_json::Value,
+    retry_delay: Duration,
+) -> WebhookDelivery {
+    let mut delivery = WebhookDelivery {
+        timestamp: String::new(),
+        webhook_id: webhook.id.clone(),
+        webhook_name: webhook.name.clone(),
+        event,
+        status: None,
+        attempts: 0,
+        success: false,
+        error: None,
+    };
+    let mut delay = retry_delay;
+
+    while delivery.attempts < MAX_ATTEMPTS {
+        if delivery.attempts > 0 {
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RETRY_DELAY);
+        }
+        delivery.attempts += 1;
+
+        let res = match CLIENT.post(&webhook.url).json(payload).send().await {
+            Ok(res) => res,
+            Err(e) => {
+                // エラーの文字列にURLが入らないようにする
+                delivery.error = Some(e.without_url().to_string());
+                continue;
+            }
+        };
+        let status = res.status();
+        delivery.status = Some(status.as_u16());
+        if status.is_success() {
+            delivery.success = true;
+            delivery.error = None;
+            break;
+        }
+        delivery.error = Some(format!("HTTP {status}"));
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            if let Some(seconds) = res
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<u64>().ok())
+            {
+                delay = Duration::from_secs(seconds).min(MAX_RETRY_DELAY);
+            }
+        } else if !status.is_server_error() {
+            break;
+        }
+    }
+    delivery.timestamp = Local::now().to_rfc3339();
+    delivery
+}
+
+async fn append_log(app_handle: &tauri::AppHandle, entry: WebhookDelivery) -> Result<(), RustError> {
+    let _guard = LOG_LOCK.lock().await;
+    let store = app_handle.store(WEBHOOKS_STORE)?;
+    let mut log: Vec<WebhookDelivery> = match store.get(LOG_KEY) {
+        Some(value) => serde_json::from_value(value).unwrap_or_default(),
+        None => Vec::new(),
+    };
+    log.push(entry);
+    if log.len() > LOG_MAX_ENTRIES {
+        log.drain(..log.len() - LOG_MAX_ENTRIES);
+    }
+    store.set(LOG_KEY, serde_json::to_value(log)?);
+    store.save()?;
+    Ok(())
+}
+
+fn load_webhooks(app_handle: &tauri::AppHandle) -> Result<Vec<Webhook>, RustError> {
+    let store = app_handle.store(WEBHOOKS_STORE)?;
+    match store.get(WEBHOOKS_KEY) {
+        Some(value) => Ok(serde_json::from_value(value)?),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// バックアップに入れるWebhookの設定。URLにはトークンが含まれるため空にする。
+/// 配信ログも接続エラーにURLが残るので入れない
+pub(crate) fn backup_entries(
+    mut entries: serde_json::Map<String, serde_json::Value>,
+) -> serde_json::Map<String, serde_json::Value> {
+    entries.remove(LOG_KEY);
+    if let Some(webhooks) = entries.get_mut(WEBHOOKS_KEY).and_then(|value| value.as_array_mut()) {
+        for webhook in webhooks {
+            webhook["url"] = serde_json::Value::String(String::new());
+        }
+    }
+    entries
+}
+
+/// 復元するWebhookに、同じIDのWebhookが今持っているURLを入れ直す。
+/// URLが分からないものは無効にして、入力し直してもらう。配信ログは今のものを残す
+pub(crate) fn restore_entries(
+    app_handle: &tauri::AppHandle,
+    mut entries: serde_json::Map<String, serde_json::Value>,
+) -> Result<serde_json::Map<String, serde_json::Value>, RustError> {
+    let store = app_handle.store(WEBHOOKS_STORE)?;
+    let current: Vec<Webhook> = match store.get(WEBHOOKS_KEY) {
+        Some(value) => serde_json::from_value(value).unwrap_or_default(),
+        None => Vec::new(),
+    };
+    if let Some(webhooks) = entries.get_mut(WEBHOOKS_KEY).and_then(|value| value.as_array_mut()) {
+        restore_urls(webhooks, &current);
+    }
+    match store.get(LOG_KEY) {
+        Some(log) => entries.insert(LOG_KEY.to_string(), log),
+        None => entries.remove(LOG_KEY),
+    };
+    Ok(entries)
+}
+
+fn restore_urls(webhooks: &mut [serde_json::Value], current: &[Webhook]) {
+    for webhook in webhooks {
+        if webhook["url"].as_str().is_some_and(|url| !url.is_empty()) {
+            continue;
+        }
+        let known = current.iter().find(|known| webhook["id"].as_str() == Some(known.id.as_str()));
+        match known {
+            Some(known) => webhook["url"] = serde_json::Value::String(known.url.clone()),
+            None => webhook["enabled"] = serde_json::Value::Bool(false),
+        }
+    }
+}
+
+/// ストアを直接書き換えたときに、配信中のループに設定を読み直させる
+pub(crate) fn reload_config() {
+    CONFIG_CHANGED.notify_one();
+}
+
+fn dispatch(app_handle: &tauri::AppHandle, webhooks: &[Webhook], event: &FriendEvent) {
+    let variables = event.variables();
+    for webhook in webhooks {
+        let Some(&kind) = webhook.events.iter().find(|kind| event.matches(**kind)) else {
+            continue;
+        };
+        let payload = match render_payload(&webhook.template, kind, &variables) {
+            Ok(payload) => payload,
+            Err(e) => {
+                warn!("Failed to render webhook {}: {:?}", webhook.name, e);
+                continue;
+            }
+        };
+        // 再送で後のイベントを待たせないように、配信ごとにタスクを分ける
+        let app_handle = app_handle.clone();
+        let webhook = webhook.clone();
+        tauri::async_runtime::spawn(async move {
+            let delivery = deliver(&webhook, kind, &payload).await;
+            if !delivery.success {
+                warn!("Webhook {} failed after {} attempts: {:?}", webhook.name, delivery.attempts, delivery.error);
+            }
+            if let Err(e) = append_log(&app_handle, delivery).await {
+                error!("Failed to save webhook delivery log: {:?}", e);
+            }
+        });
+    }
+}
+
+/// 有効なWebhookがある間はフレンドのイベントを購読し、該当するWebhookに送る
+pub(crate) async fn run(app_handle: tauri::AppHandle) {
+    loop {
+        let webhooks: Vec<Webhook> = match load_webhooks(&app_handle) {
+            Ok(webhooks) => webhooks.into_iter().filter(|webhook| webhook.enabled).collect(),
+            Err(e) => {
+                error!("Failed to load webhooks: {:?}", e);
+                Vec::new()
+            }
+        };
+        if webhooks.is_empty() {
+            CONFIG_CHANGED.notified().await;
+            continue;
+        }
+
+        info!("Delivering friend events to {} webhooks", webhooks.len());
+        let mut events = friend_events::subscribe();
+        loop {
+            tokio::select! {
+                _ = CONFIG_CHANGED.notified() => break,
+                event = events.recv() => match event {
+                    Ok(event) => {
+                        debug!("Dispatching {:?} to webhooks", event.kind());
+                        dispatch(&app_handle, &webhooks, &event);
+                    }
+                    Err(RecvError::Lagged(skipped)) => warn!("Skipped {} friend events", skipped),
+                    Err(RecvError::Closed) => return,
+                },
+            }
+        }
+    }
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn get_webhooks(app_handle: tauri::AppHandle) -> Result<Vec<Webhook>, RustError> {
+    debug!("Call get_webhooks");
+
+    load_webhooks(&app_handle)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn set_webhooks(app_handle: tauri::AppHandle, webhooks: Vec<Webhook>) -> Result<(), RustError> {
+    // URLにトークンを含むサービスがあるのでURLはログに出さない
+    debug!("Call set_webhooks ({} webhooks)", webhooks.len());
+
+    for webhook in &webhooks {
+        validate_webhook(webhook)?;
+    }
+    let store = app_handle.store(WEBHOOKS_STORE)?;
+    store.set(WEBHOOKS_KEY, serde_json::to_value(webhooks)?);
+    store.save()?;
+    CONFIG_CHANGED.notify_one();
+    Ok(())
+}
+
+/// 新しい順の配信ログ
+#[tauri::command]
+#[specta::specta]
+pub(crate) fn get_webhook_deliveries(app_handle: tauri::AppHandle) -> Result<Vec<WebhookDelivery>, RustError> {
+    debug!("Call get_webhook_deliveries");
+
+    let store = app_handle.store(WEBHOOKS_STORE)?;
+    let mut log: Vec<WebhookDelivery> = match store.get(LOG_KEY) {
+        Some(value) => serde_json::from_value(value)?,
+        None => Vec::new(),
+    };
+    log.reverse();
+    Ok(log)
+}
+
+/// 見本の値で1回送る。保存前のWebhookでも試せ、結果は配信ログにも残す
+#[tauri::command]
+#[specta::specta]
+pub(crate) async fn send_test_webhook(
+    app_handle: tauri::AppHandle,
+    webhook: Webhook,
+    event: FriendEventKind,
+) -> Result<WebhookDelivery, RustError> {
+    debug!("Call send_test_webhook {:?} {:?}", webhook.name, event);
+
+    // 無効なWebhookのURLは空のことがあるが、送るにはURLが要る
+    validate_url(&webhook.url)?;
+    validate_webhook(&webhook)?;
+    let payload = render_payload(&webhook.template, event, &friend_events::sample_variables())?;
+    let delivery = deliver(&webhook, event, &payload).await;
+    append_log(&app_handle, delivery.clone()).await?;
+    Ok(delivery)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex as StdMutex};
+    use std::time::Instant;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const TEST_RETRY_DELAY: Duration = Duration::from_millis(10);
+
+    /// 受け取ったリクエストの本文を記録し、`responses` の応答を順に返すサーバー
+    async fn start_server(responses: Vec<&'static str>) -> (String, Arc<StdMutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let bodies = Arc::new(StdMutex::new(Vec::new()));
+        let received = bodies.clone();
+        tokio::spawn(async move {
+            for response in responses {
+                let Ok((mut stream, _)) = listener.accept().await else {
+                    return;
+                };
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                let body_start = loop {
+                    if let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                        break end + 4;
+                    }
+                    match stream.read(&mut buf).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                };
+                let headers = String::from_utf8_lossy(&request[..body_start]).to_lowercase();
+                let length: usize = headers
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length:"))
+                    .and_then(|value| value.trim().parse().ok())
+                    .unwrap_or(0);
+                while request.len() < body_start + length {
+                    match stream.read(&mut buf).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                received
+                    .lock()
+                    .unwrap()
+                    .push(String::from_utf8_lossy(&request[body_start..]).to_string());
+                let response = format!("{response}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        (url, bodies)
+    }
+
+    fn webhook(url: &str) -> Webhook {
+        Webhook {
+            id: "hook_1".to_string(),
+            name: "Test".to_string(),
+            enabled: true,
+            url: url.to_string(),
+            events: vec![FriendEventKind::FriendOnline],
+            template: r#"{"content": "{displayName} is online"}"#.to_string(),
+        }
+    }
+
+    async fn deliver_to(url: &str) -> WebhookDelivery {
+        let payload = serde_json::json!({ "content": "Alice is online" });
+        deliver_with_delay(&webhook(url), FriendEventKind::FriendOnline, &payload, TEST_RETRY_DELAY).await
+    }
+
+    #[test]
+    fn escapes_values_inside_json_strings() {
+        let variables = [
+            ("displayName", r#"Al"ice\"#.to_string()),
+            ("message", "line1\nline2\t\u{1}".to_string()),
+        ];
+        let rendered = render(r#"{"name": "{displayName}", "message": "{message}"}"#, &variables);
+        assert_eq!(rendered, r#"{"name": "Al\"ice\\", "message": "line1\nline2\t\u0001"}"#);
+
+        let json: serde_json::Value = serde_json::from_str(&rendered).unwrap();
+        assert_eq!(json["name"], r#"Al"ice\"#);
+        assert_eq!(json["message"], "line1\nline2\t\u{1}");
+    }
+
+    #[test]
+    fn does_not_render_variables_inside_values() {
+        // 表示名に `{message}` と書かれていても、メッセージの値に置き換えない
+        let variables = [("displayName", "{message}".to_string()), ("message", "hi".to_string())];
+        assert_eq!(render("{displayName}: {message}", &variables), "{message}: hi");
+        assert_eq!(render("{unknown}", &variables), "{unknown}");
+    }
+
+    #[test]
+    fn renders_the_event_name_into_the_payload() {
+        let variables = [("displayName", "Alice".to_string())];
+        let payload = render_payload(
+            r#"{"event": "{event}", "text": "{displayName}"}"#,
+            FriendEventKind::FriendOnline,
+            &variables,
+        )
+        .unwrap();
+        let event = serde_json::to_value(FriendEventKind::FriendOnline).unwrap();
+        assert_eq!(payload, serde_json::json!({ "event": event, "text": "Alice" }));
+    }
+
+    #[test]
+    fn rejects_templates_that_are_not_json() {
+        let variables = [("displayName", "Alice".to_string())];
+        for template in [r#"{"text": {displayName}}"#, "{displayName} is online", r#"{"text": "{displayName}""#] {
+            let error = render_payload(template, FriendEventKind::FriendOnline, &variables).unwrap_err();
+            assert_eq!(error.message(), "errors.invalidWebhookTemplate");
+        }
+    }
+
+    #[test]
+    fn restored_webhooks_pass_validation() {
+        let backup = backup_entries(serde_json::Map::from_iter([(
+            WEBHOOKS_KEY.to_string(),
+            serde_json::to_value(vec![
+                webhook("https://example.com/known"),
+                Webhook {
+                    id: "hook_2".to_string(),
+                    ..webhook("https://example.com/unknown")
+                },
+            ])
+            .unwrap(),
+        )]));
+        let mut entries = backup[WEBHOOKS_KEY].as_array().unwrap().clone();
+
+        // 今の設定にあるWebhookだけURLが戻り、ほかは無効のまま保存できる
+        restore_urls(&mut entries, &[webhook("https://example.com/known")]);
+        let restored: Vec<Webhook> = serde_json::from_value(serde_json::Value::Array(entries)).unwrap();
+        assert_eq!(restored[0].url, "https://example.com/known");
+        assert!(restored[0].enabled);
+        assert_eq!(restored[1].url, "");
+        assert!(!restored[1].enabled);
+        for webhook in &restored {
+            validate_webhook(webhook).unwrap();
+        }
+
+        // 有効にするにはURLが要る
+        let enabled = Webhook {
+            enabled: true,
+            ..restored[1].clone()
+        };
+        assert_eq!(validate_webhook(&enabled).unwrap_err().message(), "errors.invalidWebhookUrl");
+        // 無効でも入力されたURLは確かめる
+        let invalid = Webhook {
+            url: "ftp://example.com".to_string(),
+            ..restored[1].clone()
+        };
+        assert_eq!(validate_webhook(&invalid).unwrap_err().message(), "errors.invalidWebhookUrl");
+    }
+
+    #[tokio::test]
+    async fn delivers_the_payload_once_on_success() {
+        let (url, bodies) = start_server(vec!["HTTP/1.1 204 No Content"]).await;
+        let delivery = deliver_to(&url).await;
+
+        assert!(delivery.success);
+        assert_eq!(delivery.status, Some(204));
+        assert_eq!(delivery.attempts, 1);
+        assert_eq!(delivery.error, None);
+        let bodies = bodies.lock().unwrap();
+        assert_eq!(bodies.len(), 1);
+        let body: serde_json::Value = serde_json::from_str(&bodies[0]).unwrap();
+        assert_eq!(body["content"], "Alice is online");
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let (url, bodies) = start_server(vec![
+            "HTTP/1.1 500 Internal Server Error",
+            "HTTP/1.1 503 Service Unavailable",
+            "HTTP/1.1 200 OK",
+        ])
+        .await;
+        let delivery = deliver_to(&url).await;
+
+        assert!(delivery.success);
+        assert_eq!(delivery.attempts, 3);
+        assert_eq!(delivery.status, Some(200));
+        assert_eq!(bodies.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_last_attempt() {
+        let (url, _) = start_server(vec!["HTTP/1.1 502 Bad Gateway"; MAX_ATTEMPTS as usize]).await;
+        let delivery = deliver_to(&url).await;
+
+        assert!(!delivery.success);
+        assert_eq!(delivery.attempts, MAX_ATTEMPTS);
+        assert_eq!(delivery.status, Some(502));
+        assert_eq!(delivery.error.as_deref(), Some("HTTP 502 Bad Gateway"));
+    }
+
+    #[tokio::test]
+    async fn waits_for_retry_after_on_429() {
+        let responses = vec!["HTTP/1.1 429 Too Many Requests\r\nRetry-After: 1", "HTTP/1.1 200 OK"];
+        let (url, bodies) = start_server(responses).await;
+        let started = Instant::now();
+        let delivery = deliver_to(&url).await;
+
+        assert!(delivery.success);
+        assert_eq!(delivery.attempts, 2);
+        assert_eq!(bodies.lock().unwrap().len(), 2);
+        // 最初の待ち時間（10ms）ではなく `Retry-After` の1秒待つ
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        let (url, bodies) = start_server(vec!["HTTP/1.1 404 Not Found", "HTTP/1.1 200 OK"]).await;
+        let delivery = deliver_to(&url).await;
+
+        assert!(!delivery.success);
+        assert_eq!(delivery.attempts, 1);
+        assert_eq!(delivery.status, Some(404));
+        assert_eq!(delivery.error.as_deref(), Some("HTTP 404 Not Found"));
+        assert_eq!(bodies.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_when_the_server_cannot_be_reached() {
+        // すぐに閉じたポートには接続できない
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        drop(listener);
+        let delivery = deliver_to(&url).await;
+
+        assert!(!delivery.success);
+        assert_eq!(delivery.attempts, MAX_ATTEMPTS);
+        assert_eq!(delivery.status, None);
+        // エラーにURLを残さない
+        assert!(!delivery.error.unwrap().contains(&url));
+    }
+}
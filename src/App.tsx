@@ -16,7 +16,9 @@ import {logging} from "@/libs/logging.tsx";
 import { UpdateConfirm } from "@/components/ui/dialogs/updateConfirm";
 import { ThirdPartyLicenses } from "@/components/ui/dialogs/license";
 import { userDataStore } from "./libs/userDataStore";
-import { commands } from "./bindings";
+import { commands, ReauthOtpRequest, Settings } from "./bindings";
+import { ReauthOtp } from "@/components/ui/dialogs/reauthOtp";
+import { listen } from "@tauri-apps/api/event";
 
 export default function App() {
   const isDev = import.meta.env.DEV;
@@ -57,6 +59,24 @@ export default function App() {
     }
     loadTheme();
     init();
+
+    // セッション切れの再ログイン中に2要素認証のコードを求められたら入力してもらう
+    const unlisten = listen<ReauthOtpRequest>("vrchat-reauth-otp-required", async (event) => {
+      const res = await ReauthOtp.call({ methods: event.payload.methods });
+      if (res) {
+        await commands.submitReauthOtp(event.payload.userId, res.method, res.code);
+      } else {
+        await commands.cancelReauth(event.payload.userId);
+      }
+    });
+    // 設定が変わったらテーマを反映する
+    const unlistenSettings = listen<Settings>("settings-changed", (event) => {
+      document.documentElement.setAttribute("data-theme", event.payload.theme);
+    });
+    return () => {
+      unlisten.then((f) => f());
+      unlistenSettings.then((f) => f());
+    };
   }, []);
 
   return (
@@ -66,6 +86,7 @@ export default function App() {
       <FriendDetail.Root />
       <InstanceDetail.Root />
       <Login.Root />
+      <ReauthOtp.Root />
       <UpdateConfirm.Root />
       <ThirdPartyLicenses.Root />
       {/* 理由がよくわからんがメインコンテンツをw-screenで覆わないとダイアログ表示時になんかズレる */}
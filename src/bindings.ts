@@ -5,41 +5,38 @@
 
 
 export const commands = {
-async login(userName: string, password: string) : Promise<Result<string, RustError>> {
+async login(userName: string, password: string, totpSecret: string | null) : Promise<Result<LoginOutcome, RustError>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("login", { userName, password }) };
+    return { status: "ok", data: await TAURI_INVOKE("login", { userName, password, totpSecret }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
 }
 },
-async emailOtp(otp: string) : Promise<Result<boolean, RustError>> {
+async verifyTwoFactor(method: OtpMethod, code: string, rememberDevice: boolean) : Promise<Result<boolean, RustError>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("email_otp", { otp }) };
+    return { status: "ok", data: await TAURI_INVOKE("verify_two_factor", { method, code, rememberDevice }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
 }
 },
-async twoFactorAuth(otp: string) : Promise<Result<boolean, RustError>> {
+async verifyAuthToken() : Promise<Result<boolean, RustError>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("two_factor_auth", { otp }) };
+    return { status: "ok", data: await TAURI_INVOKE("verify_auth_token") };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
 }
 },
-async verifyAuthToken() : Promise<Result<boolean, RustError>> {
+async cookieClear() : Promise<Result<null, RustError>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("verify_auth_token") };
+    return { status: "ok", data: await TAURI_INVOKE("cookie_clear") };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
 }
 },
-async cookieClear() : Promise<void> {
-    await TAURI_INVOKE("cookie_clear");
-},
 async getCurrentUserInfo() : Promise<Result<string, RustError>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("get_current_user_info") };
@@ -128,9 +125,6 @@ async getUserGroupInstances() : Promise<Result<string, RustError>> {
     else return { status: "error", error: e  as any };
 }
 },
-async switchUser(userId: string) : Promise<void> {
-    await TAURI_INVOKE("switch_user", { userId });
-},
 async getReleaseNote(tagName: string) : Promise<Result<string, RustError>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("get_release_note", { tagName }) };
@@ -138,6 +132,512 @@ async getReleaseNote(tagName: string) : Promise<Result<string, RustError>> {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
 }
+},
+async getOwnAvatars(offset: number, n: number) : Promise<Result<string, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_own_avatars", { offset, n }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getFavoriteAvatars(offset: number, n: number) : Promise<Result<string, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_favorite_avatars", { offset, n }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getAvatarById(avatarid: string) : Promise<Result<string, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_avatar_by_id", { avatarid }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async selectAvatar(avatarId: string) : Promise<Result<string, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("select_avatar", { avatarId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async updateCurrentUserProfile(update: CurrentUserUpdate) : Promise<Result<string, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("update_current_user_profile", { update }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async setCurrentUserStatus(status: UserStatus, statusDescription: string | null) : Promise<Result<string, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_current_user_status", { status, statusDescription }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getAccounts() : Promise<Result<Account[], RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_accounts") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getCurrentAccount() : Promise<Result<Account | null, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_current_account") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async saveAccount(userData: string) : Promise<Result<Account, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("save_account", { userData }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async removeAccount(userId: string) : Promise<Result<null, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("remove_account", { userId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async renameAccount(userId: string, label: string | null) : Promise<Result<null, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("rename_account", { userId, label }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async setDefaultAccount(userId: string | null) : Promise<Result<null, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_default_account", { userId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async switchUser(userId: string) : Promise<Result<Account, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("switch_user", { userId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async rememberLoginCredentials(userName: string, password: string) : Promise<Result<null, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("remember_login_credentials", { userName, password }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async forgetLoginCredentials(userId: string) : Promise<Result<null, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("forget_login_credentials", { userId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async hasLoginCredentials(userId: string) : Promise<Result<boolean, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("has_login_credentials", { userId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async reauthenticateSession() : Promise<Result<boolean, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("reauthenticate_session") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async submitReauthOtp(userId: string, method: OtpMethod, code: string) : Promise<Result<null, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("submit_reauth_otp", { userId, method, code }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async cancelReauth(userId: string) : Promise<Result<null, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("cancel_reauth", { userId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async hasTotpSecret(userId: string) : Promise<Result<boolean, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("has_totp_secret", { userId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async forgetTotpSecret(userId: string) : Promise<Result<null, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("forget_totp_secret", { userId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getLogRedactionPolicy() : Promise<RedactionPolicy> {
+    return await TAURI_INVOKE("get_log_redaction_policy");
+},
+async setLogRedactionPolicy(policy: RedactionPolicy) : Promise<Result<null, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_log_redaction_policy", { policy }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getSettings() : Promise<Result<Settings, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_settings") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async updateSettings(update: SettingsUpdate) : Promise<Result<Settings, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("update_settings", { update }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getStatusSchedules() : Promise<Result<StatusSchedule[], RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_status_schedules") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async setStatusSchedules(schedules: StatusSchedule[]) : Promise<Result<null, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_status_schedules", { schedules }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getStatusScheduleLog() : Promise<Result<StatusChangeLog[], RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_status_schedule_log") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getUserNotes() : Promise<Result<Partial<{ [key in string]: UserNote }>, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_user_notes") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async setUserNote(userId: string, note: string, syncOfficial: boolean) : Promise<Result<UserNote, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_user_note", { userId, note, syncOfficial }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async setUserTags(userId: string, tags: string[]) : Promise<Result<UserNote, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_user_tags", { userId, tags }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getAllTags() : Promise<Result<string[], RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_all_tags") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async syncUserNotes() : Promise<Result<number, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("sync_user_notes") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async searchFriends(filter: FriendFilter) : Promise<Result<string, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("search_friends", { filter }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getMergedFriends(includeOffline: boolean) : Promise<Result<MergedFriends, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_merged_friends", { includeOffline }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async exportFriendInstances(options: ExportOptions) : Promise<Result<ExportSummary, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("export_friend_instances", { options }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getLocalApiStatus() : Promise<Result<LocalApiStatus, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_local_api_status") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getLocalApiToken() : Promise<Result<string, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_local_api_token") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async regenerateLocalApiToken() : Promise<Result<string, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("regenerate_local_api_token") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getPresence() : Promise<Presence> {
+    return await TAURI_INVOKE("get_presence");
+},
+async getPresenceFilePath() : Promise<Result<string, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_presence_file_path") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async watchInstance(worldId: string, instanceId: string, intervalSeconds: number | null) : Promise<Result<InstanceWatch, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("watch_instance", { worldId, instanceId, intervalSeconds }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async unwatchInstance(worldId: string, instanceId: string) : Promise<Result<null, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("unwatch_instance", { worldId, instanceId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getInstanceWatches() : Promise<InstanceWatch[]> {
+    return await TAURI_INVOKE("get_instance_watches");
+},
+async setAutoInvite(worldId: string, instanceId: string, trigger: AutoInviteTrigger, timeoutMinutes: number) : Promise<Result<InstanceWatch, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_auto_invite", { worldId, instanceId, trigger, timeoutMinutes }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async cancelAutoInvite(worldId: string, instanceId: string) : Promise<Result<null, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("cancel_auto_invite", { worldId, instanceId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getOscConfig() : Promise<Result<OscConfig, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_osc_config") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async setOscConfig(config: OscConfig) : Promise<Result<null, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_osc_config", { config }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async sendTestOscMessage(config: OscConfig, event: FriendEventKind) : Promise<Result<number, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("send_test_osc_message", { config, event }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getWebhooks() : Promise<Result<Webhook[], RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_webhooks") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async setWebhooks(webhooks: Webhook[]) : Promise<Result<null, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_webhooks", { webhooks }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getWebhookDeliveries() : Promise<Result<WebhookDelivery[], RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_webhook_deliveries") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async sendTestWebhook(webhook: Webhook, event: FriendEventKind) : Promise<Result<WebhookDelivery, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("send_test_webhook", { webhook, event }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async createBackup(path: string | null) : Promise<Result<BackupSummary, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("create_backup", { path }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async restoreBackup(path: string) : Promise<Result<BackupSummary, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("restore_backup", { path }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getFriendChanges(kind: FriendChangeKind | null, limit: number | null) : Promise<Result<FriendChange[], RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_friend_changes", { kind, limit }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getVrchatLogDir() : Promise<string | null> {
+    return await TAURI_INVOKE("get_vrchat_log_dir");
+},
+async setVrchatLogDir(dir: string | null) : Promise<Result<null, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_vrchat_log_dir", { dir }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getInstanceHistory(offset: number, n: number) : Promise<Result<InstanceVisit[], RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_instance_history", { offset, n }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getCurrentInstanceVisit() : Promise<InstanceVisit | null> {
+    return await TAURI_INVOKE("get_current_instance_visit");
+},
+async importVrchatLogs(dir: string, force: boolean) : Promise<Result<LogImportSummary, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("import_vrchat_logs", { dir, force }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getScreenshotDir() : Promise<string | null> {
+    return await TAURI_INVOKE("get_screenshot_dir");
+},
+async setScreenshotDir(dir: string | null) : Promise<Result<null, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_screenshot_dir", { dir }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async indexScreenshots() : Promise<Result<number, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("index_screenshots") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async searchScreenshots(world: string | null, player: string | null, offset: number, n: number) : Promise<Result<ScreenshotEntry[], RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("search_screenshots", { world, player, offset, n }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async readScreenshotMetadata(path: string) : Promise<Result<ScreenshotMetadata | null, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("read_screenshot_metadata", { path }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async writeScreenshotMetadata(path: string) : Promise<Result<ScreenshotMetadata, RustError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("write_screenshot_metadata", { path }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
 }
 }
 
@@ -151,9 +651,270 @@ async getReleaseNote(tagName: string) : Promise<Result<string, RustError>> {
 
 /** user-defined types **/
 
+export type Account = { id: string; displayName: string; 
+/**
+ * ユーザーが付けた表示名。未設定ならVRChatの表示名を使う
+ */
+label: string | null; lastLogin: string; 
+/**
+ * 起動時にアカウントを選んでいなければこのアカウントでログインする
+ */
+isDefault: boolean; 
+/**
+ * 最後に取得した `/auth/user` のJSON
+ */
+userData: string }
 export type ApiResponse = { status: string; data: string }
+export type AutoInvite = { trigger: AutoInviteTrigger; expiresAt: string; status: AutoInviteStatus; sentAt: string | null; error: string | null }
+export type AutoInviteStatus = 
+/**
+ * 条件を満たすのを待っている
+ */
+"Pending" | "Sent" | 
+/**
+ * 招待に失敗した。再送はしない
+ */
+"Failed" | 
+/**
+ * 条件を満たさないまま期限を過ぎた
+ */
+"Expired" | 
+/**
+ * インスタンスが閉じたか、取り消された
+ */
+"Cancelled"
+export type AutoInviteTrigger = { type: "RoomAvailable" } | { type: "FriendJoined"; userId: string }
+export type BackupSummary = { path: string; 
+/**
+ * 読み込んだバックアップの元の形式のバージョン（作成時は現在のバージョン）
+ */
+version: number; createdAt: string; stores: string[]; worlds: number }
+export type CurrentUserUpdate = { status?: UserStatus | null; statusDescription?: string | null; bio?: string | null; bioLinks?: string[] | null; pronouns?: string | null; statusHistory?: string[] | null }
 export type DebugApiRequest = { method: string; endpoint: string; data?: string | null }
+export type ExportColumn = "worldName" | "worldId" | "instanceId" | 
+/**
+ * Public / Friends+ / Invite などのインスタンスの種類
+ */
+"instanceType" | "region" | 
+/**
+ * インスタンスIDの `~` 以降のタグ
+ */
+"instanceTags" | "friendCount" | "friendName" | "friendId" | "status" | "statusDescription" | "platform"
+export type ExportFormat = "csv" | "json" | "markdown"
+export type ExportOptions = { format: ExportFormat; 
+/**
+ * 出力する列。並び順もこの順になる
+ */
+columns: ExportColumn[]; includeOffline: boolean; 
+/**
+ * インスタンスごとに1行にまとめる。フレンドの列は1つのセルに並べる
+ */
+groupByInstance: boolean; 
+/**
+ * 出力先のファイル。未指定ならダウンロードフォルダに日時付きのファイル名で保存する
+ */
+path: string | null }
+export type ExportSummary = { path: string; rows: number }
+export type FriendChange = { timestamp: string; kind: FriendChangeKind; userId: string; displayName: string; previousDisplayName: string | null }
+export type FriendChangeKind = "Added" | "Removed" | "Renamed"
+export type FriendEventKind = "friendOnline" | 
+/**
+ * お気に入りに入れているフレンドがオンラインになった
+ */
+"favoriteOnline" | "friendLocation" | 
+/**
+ * 自分のいるインスタンスにフレンドが来た（VRChatのログから検出）
+ */
+"friendJoinedInstance" | "inviteReceived" | 
+/**
+ * 参加しているグループのイベントがまもなく始まる
+ */
+"groupEventStarting"
+export type FriendFilter = { 
+/**
+ * 指定したタグをすべて持つフレンドに絞り込む
+ */
+tags: string[]; 
+/**
+ * 表示名・ローカルメモ・タグに対する部分一致（大文字小文字は区別しない）
+ */
+query: string | null; includeOffline: boolean }
+export type InstanceSample = { timestamp: string; userCount: number; capacity: number; queueSize: number; closedAt: string | null }
+export type InstanceVisit = { 
+/**
+ * `参加時刻@ロケーション`。再インポート時の重複判定に使う
+ */
+id: string; worldId: string; instanceId: string; worldName: string | null; joinedAt: string; leftAt: string | null; players: PlayerEncounter[]; videos: VideoPlay[]; portals: string[] }
+export type InstanceWatch = { location: string; intervalSeconds: number; startedAt: string; 
+/**
+ * 古いものから順の取得結果。多くなったら古いものから消す
+ */
+samples: InstanceSample[]; 
+/**
+ * インスタンスにいるフレンドのID
+ */
+friends: string[]; 
+/**
+ * 自分が入れる空きがあるか
+ */
+hasRoom: boolean; 
+/**
+ * インスタンスが閉じられたか、無くなった。監視はそこで終わる
+ */
+closed: boolean; autoInvite: AutoInvite | null }
+export type InstanceWatchEvent = { type: "RoomAvailable"; location: string; userCount: number; capacity: number } | { type: "FriendJoined"; location: string; userId: string; displayName: string } | { type: "Closed"; location: string; closedAt: string | null } | { type: "AutoInviteSent"; location: string }
+export type LocalApiStatus = { enabled: boolean; running: boolean; port: number; 
+/**
+ * 起動に失敗したときの理由（ポートが使用中など）
+ */
+error: string | null }
+export type LogImportProgress = { processed: number; total: number; fileName: string }
+export type LogImportSummary = { files: number; 
+/**
+ * 前回から変わっていないため読み飛ばしたファイル数
+ */
+skippedFiles: number; failedFiles: number; visits: number; 
+/**
+ * 既存の履歴に無かった滞在の数
+ */
+newVisits: number; players: number }
+export type LoginOutcome = { type: "LoggedIn"; userId: string; displayName: string } | 
+/**
+ * 2要素認証が必要。`methods` のいずれかで `verify_two_factor` を呼ぶ
+ */
+{ type: "TwoFactorRequired"; methods: OtpMethod[] }
+export type MergedFriends = { 
+/**
+ * フレンドのJSON配列。各要素の `seenBy` に、そのフレンドが見えているアカウントIDが入る
+ */
+friends: string; 
+/**
+ * 取得に失敗したアカウントID
+ */
+failedAccounts: string[] }
+export type OscArgument = { type: "Bool"; value: boolean } | { type: "Int"; value: number } | { type: "Float"; value: number } | { type: "String"; value: string }
+export type OscConfig = { enabled: boolean; host: string; port: number; messages: OscMessageTemplate[] }
+export type OscMessageTemplate = { event: FriendEventKind; 
+/**
+ * 送信先のアドレス。`{displayName}` などのテンプレートを使える
+ */
+address: string; arguments: OscArgument[] }
+export type OtpMethod = 
+/**
+ * メールに届くコード
+ */
+"emailOtp" | 
+/**
+ * 認証アプリのコード
+ */
+"totp" | 
+/**
+ * TOTPを設定したときに発行されるリカバリーコード
+ */
+"otp"
+export type PlayerEncounter = { displayName: string; userId: string | null; joinedAt: string; leftAt: string | null }
+export type Presence = { version: number; inWorld: boolean; worldId: string | null; worldName: string | null; 
+/**
+ * `wrld_xxx:12345~region(jp)` 形式のロケーション
+ */
+location: string | null; 
+/**
+ * インスタンス番号（`~` より前の部分）
+ */
+instanceName: string | null; 
+/**
+ * Public / Friends+ / Friends / Invite+ / Invite / Group Public / Group+ / Group
+ */
+instanceType: string | null; region: string | null; 
+/**
+ * 自分を含めた、いまインスタンスにいる人数
+ */
+playerCount: number; joinedAt: string | null; updatedAt: string }
+export type ReauthOtpRequest = { userId: string; 
+/**
+ * 使える方式。先頭が最初に案内する方式
+ */
+methods: OtpMethod[] }
+export type RedactionPolicy = { 
+/**
+ * パスワード・Basic認証ヘッダー
+ */
+credentials: boolean; 
+/**
+ * 認証Cookie（`auth` / `twoFactorAuth`）
+ */
+authCookies: boolean; 
+/**
+ * 2要素認証のコード
+ */
+otpCodes: boolean; 
+/**
+ * `usr_` から始まるユーザーID
+ */
+userIds: boolean }
 export type RustError = { type: "Unrecoverable"; message: string }
+export type ScheduleTrigger = { type: "TimeWindow"; weekdays: number[]; start: string; end: string } | { type: "FriendInInstance"; friendId: string }
+export type ScreenshotEntry = { path: string; takenAt: string; 
+/**
+ * ファイルの更新日時（UNIX秒）。変わっていなければ再インデックス時に読み直さない
+ */
+modified: number; 
+/**
+ * PNGに埋め込まれていたメタデータ
+ */
+embedded: ScreenshotMetadata | null; 
+/**
+ * インスタンス履歴から推定したメタデータ
+ */
+correlated: ScreenshotMetadata | null }
+export type ScreenshotMetadata = { 
+/**
+ * メタデータを書き込んだツール（`VRCSocialManager` / `VRCX` / `VRChat`）
+ */
+source: string; worldId: string | null; worldName: string | null; instanceId: string | null; players: ScreenshotPlayer[] }
+export type ScreenshotPlayer = { displayName: string; userId: string | null }
+export type Settings = { theme: Theme; language: string; 
+/**
+ * フレンド一覧を1ページで取得する人数（1〜100）
+ */
+fetchFriendsCount: number; autoCheckUpdates: boolean; 
+/**
+ * スクリプトやオーバーレイ向けのローカルAPIを起動する
+ */
+localApiEnabled: boolean; 
+/**
+ * ローカルAPIのポート（127.0.0.1のみで待ち受ける）
+ */
+localApiPort: number; 
+/**
+ * 現在のワールド・インスタンスの種類・人数をJSONファイルに書き出す
+ */
+presenceFileEnabled: boolean; 
+/**
+ * 書き出し先。空ならアプリのデータフォルダの presence.json
+ */
+presenceFilePath: string }
+export type SettingsUpdate = { theme?: Theme | null; language?: string | null; fetchFriendsCount?: number | null; autoCheckUpdates?: boolean | null; localApiEnabled?: boolean | null; localApiPort?: number | null; presenceFileEnabled?: boolean | null; presenceFilePath?: string | null }
+export type StatusChangeLog = { timestamp: string; scheduleId: string | null; scheduleName: string | null; status: UserStatus; statusDescription: string; reverted: boolean }
+export type StatusSchedule = { id: string; name: string; enabled: boolean; trigger: ScheduleTrigger; status: UserStatus; statusDescription: string | null; 
+/**
+ * ルールが外れたときに適用前のステータスへ戻すか
+ */
+revert: boolean }
+export type Theme = "light" | "dark"
+export type UserNote = { note: string; tags: string[]; officialNote: string | null; updatedAt: string | null }
+export type UserStatus = "join me" | "active" | "ask me" | "busy"
+export type VideoPlay = { url: string; playedAt: string }
+export type Webhook = { id: string; name: string; enabled: boolean; url: string; events: FriendEventKind[]; 
+/**
+ * 送るJSON。`{displayName}` などは文字列としてエスケープした値に置き換える
+ */
+template: string }
+export type WebhookDelivery = { timestamp: string; webhookId: string; webhookName: string; event: FriendEventKind; 
+/**
+ * 最後の応答のステータスコード。接続できなかった場合は `None`
+ */
+status: number | null; attempts: number; success: boolean; error: string | null }
 
 /** tauri-specta globals **/
 
@@ -1,7 +1,8 @@
 import { createCallable } from 'react-call'
 import { useState } from 'react'
 import { useTranslation } from "react-i18next"
-import { commands } from "@/bindings"
+import { commands, OtpMethod } from "@/bindings"
+import { OtpMethodSelect } from "@/components/ui/otpMethodSelect"
 
 interface Props {
   onLoginSuccess: () => void;
@@ -15,43 +16,41 @@ export const Login = createCallable<Props, void>(({ call, onLoginSuccess }) => {
   const [password, setPassword] = useState("");
   const [errorMessage, setErrorMessage] = useState("");
   const [requires2FA, setRequires2FA] = useState(false);
-  const [twoFactorMethod, setTwoFactorMethod] = useState("");
+  const [twoFactorMethods, setTwoFactorMethods] = useState<OtpMethod[]>([]);
+  const [twoFactorMethod, setTwoFactorMethod] = useState<OtpMethod>("totp");
   const [twoFactorCode, setTwoFactorCode] = useState("");
+  const [rememberDevice, setRememberDevice] = useState(true);
+  const [rememberCredentials, setRememberCredentials] = useState(false);
+  const [totpSecret, setTotpSecret] = useState("");
 
-  const handleLogin = async () => {
-    setErrorMessage("");
-    try {
-      const res = await commands.login(email, password)
-      if (res.status == "ok") {
-        switch(res.data) {
-          case "emailOtp":
-          case "totp":
-            setRequires2FA(true);
-            setTwoFactorMethod(res.data);
-            break;
-          default:
-            onLoginSuccess();
-            call.end();
-            break;
-        }
-      } else {
+  // セッション切れのときに自動で再ログインできるよう認証情報を保存する
+  const finishLogin = async () => {
+    if (rememberCredentials) {
+      const res = await commands.rememberLoginCredentials(email, password);
+      if (res.status == "error") {
         setErrorMessage(t(res.error.message));
       }
-    } catch {
-      setErrorMessage(t("errors.unknown"));
     }
+    onLoginSuccess();
+    call.end();
   }
 
-  const handle2FAVerification = async () => {
+  const handleLogin = async () => {
     setErrorMessage("");
     try {
-      const res = await commands.twoFactorAuth(twoFactorCode);
+      const res = await commands.login(email, password, totpSecret || null)
       if (res.status == "ok") {
-        if (res.data) {
-          onLoginSuccess()
-          call.end()
-        } else {
-          setErrorMessage(t("errors.2faFail"));
+        switch(res.data.type) {
+          case "TwoFactorRequired": {
+            const methods = res.data.methods;
+            setRequires2FA(true);
+            setTwoFactorMethods(methods);
+            setTwoFactorMethod(methods.includes("emailOtp") ? "emailOtp" : methods[0]);
+            break;
+          }
+          case "LoggedIn":
+            await finishLogin();
+            break;
         }
       } else {
         setErrorMessage(t(res.error.message));
@@ -61,14 +60,13 @@ export const Login = createCallable<Props, void>(({ call, onLoginSuccess }) => {
     }
   }
 
-  const handleEmailOtpVerification = async () => {
+  const handle2FAVerification = async () => {
     setErrorMessage("");
     try {
-      const res = await commands.emailOtp(twoFactorCode);
+      const res = await commands.verifyTwoFactor(twoFactorMethod, twoFactorCode, rememberDevice);
       if (res.status == "ok") {
         if (res.data) {
-          onLoginSuccess()
-          call.end()
+          await finishLogin();
         } else {
           setErrorMessage(t("errors.2faFail"));
         }
@@ -124,23 +122,52 @@ export const Login = createCallable<Props, void>(({ call, onLoginSuccess }) => {
                 onChange={(e) => setPassword(e.target.value)}
                 onKeyDown={(e) => e.key === "Enter" && handleLogin()}
               />
+              <label className="label cursor-pointer justify-start gap-2 mt-2">
+                <input
+                  type="checkbox"
+                  className="checkbox checkbox-sm"
+                  checked={rememberCredentials}
+                  onChange={(e) => setRememberCredentials(e.target.checked)}
+                />
+                <span className="label-text">{t("login.rememberCredentials")}</span>
+              </label>
+              {rememberCredentials && (
+                // 共有のテスト用アカウントなどで、再ログインを人の操作なしで行えるようにする
+                <input
+                  type="password"
+                  autoComplete="off"
+                  className="input input-bordered w-full mt-2"
+                  placeholder={t("login.totpSecret")}
+                  value={totpSecret}
+                  onChange={(e) => setTotpSecret(e.target.value)}
+                  onKeyDown={(e) => e.key === "Enter" && handleLogin()}
+                />
+              )}
               <button className="btn btn-primary w-full mt-4" onClick={handleLogin}>{t("login.submit")}</button>
             </div>
           ) : (
             <div>
+              <OtpMethodSelect methods={twoFactorMethods} value={twoFactorMethod} onChange={setTwoFactorMethod} />
               <input
                 type="text"
                 className="input input-bordered w-full mt-2"
-                placeholder="Enter 2FA Code"
+                placeholder={t(`login.otpPlaceholder.${twoFactorMethod}`)}
                 value={twoFactorCode}
                 onChange={(e) => setTwoFactorCode(e.target.value)}
-                onKeyDown={(e) => e.key === "Enter" &&
-                  (twoFactorMethod === "emailOtp" ? handleEmailOtpVerification() : handle2FAVerification())
-                }
+                onKeyDown={(e) => e.key === "Enter" && handle2FAVerification()}
               />
+              <label className="label cursor-pointer justify-start gap-2 mt-2">
+                <input
+                  type="checkbox"
+                  className="checkbox checkbox-sm"
+                  checked={rememberDevice}
+                  onChange={(e) => setRememberDevice(e.target.checked)}
+                />
+                <span className="label-text">{t("login.rememberDevice")}</span>
+              </label>
               <button 
                 className="btn btn-primary w-full mt-4" 
-                onClick={twoFactorMethod === "emailOtp" ? handleEmailOtpVerification : handle2FAVerification}
+                onClick={handle2FAVerification}
               >
                 {t("login.submit2FA")}
               </button>
@@ -0,0 +1,39 @@
+import { createCallable } from 'react-call'
+import { useState } from 'react'
+import { useTranslation } from "react-i18next"
+import { OtpMethod } from "@/bindings"
+import { OtpMethodSelect } from "@/components/ui/otpMethodSelect"
+
+interface Props { methods: OtpMethod[] }
+type Response = { method: OtpMethod, code: string } | null
+
+const UNMOUNTING_DELAY = 300;
+
+// セッション切れで再ログインする途中に2要素認証のコードを入力してもらう
+export const ReauthOtp = createCallable<Props, Response>(({ call, methods }) => {
+  const { t } = useTranslation();
+  const [method, setMethod] = useState<OtpMethod>(methods[0]);
+  const [code, setCode] = useState("");
+
+  return (
+    <div className="fixed inset-0 flex z-20 items-center justify-center" role="dialog">
+      <div className={`modal modal-open ${call.ended ? 'exit-animation' : ''}`}>
+        <div className="modal-box">
+          <h2 className="text-lg font-semibold">{t("reauth.title")}</h2>
+          <p className="mt-2">{t(`reauth.${method}`)}</p>
+          <OtpMethodSelect methods={methods} value={method} onChange={setMethod} />
+          <input
+            type="text"
+            className="input input-bordered w-full mt-2"
+            placeholder={t(`login.otpPlaceholder.${method}`)}
+            value={code}
+            onChange={(e) => setCode(e.target.value)}
+            onKeyDown={(e) => e.key === "Enter" && call.end({ method, code })}
+          />
+          <button className="btn btn-primary w-full mt-4" onClick={() => call.end({ method, code })}>{t("login.submit2FA")}</button>
+          <button className="btn btn-secondary w-full mt-4" onClick={() => call.end(null)}>Cancel</button>
+        </div>
+      </div>
+    </div>
+  )
+}, UNMOUNTING_DELAY)
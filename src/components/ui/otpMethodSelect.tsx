@@ -0,0 +1,30 @@
+import { useTranslation } from "react-i18next"
+import { OtpMethod } from "@/bindings"
+
+interface Props {
+  methods: OtpMethod[];
+  value: OtpMethod;
+  onChange: (method: OtpMethod) => void;
+}
+
+// 2要素認証の方式が複数あるときだけ切り替えを表示する（認証アプリとリカバリーコードなど）
+export function OtpMethodSelect({ methods, value, onChange }: Props) {
+  const { t } = useTranslation();
+  if (methods.length < 2) {
+    return null;
+  }
+
+  return (
+    <select
+      className="select select-bordered w-full mt-2"
+      value={value}
+      onChange={(e) => onChange(e.target.value as OtpMethod)}
+    >
+      {methods.map(method => (
+        <option key={method} value={method}>
+          {t(`login.otpMethod.${method}`)}
+        </option>
+      ))}
+    </select>
+  );
+}
@@ -1,4 +1,6 @@
 import { LazyStore } from '@tauri-apps/plugin-store';
+import { commands, Account, Settings, SettingsUpdate, Theme } from '@/bindings';
+import { logging } from '@/libs/logging';
 
 export interface UserData {
     id: string;
@@ -7,6 +9,26 @@ export interface UserData {
     userData: string;
 }
 
+// アカウント一覧はRust側で管理している
+const toUserData = (account: Account): UserData => ({
+    id: account.id,
+    displayName: account.label ?? account.displayName,
+    lastLogin: new Date(account.lastLogin),
+    userData: account.userData
+});
+
+// 設定もRust側で管理している（既定値・値のチェックもRust側）
+const defaultSettings: Settings = {
+    theme: "light",
+    language: "ja",
+    fetchFriendsCount: 50,
+    autoCheckUpdates: true,
+    localApiEnabled: false,
+    localApiPort: 39410,
+    presenceFileEnabled: false,
+    presenceFilePath: ""
+};
+
 class UserDataStore {
     private store: LazyStore;
 
@@ -14,52 +36,35 @@ class UserDataStore {
         this.store = new LazyStore('store.json');
     }
 
-    async getTheme(): Promise<string> {
-        const theme = await this.store.get<string>("data-theme");
-        if (theme) {
-            return theme;
-        } else {
-            // デフォルトテーマを設定
-            await this.store.set('data-theme', "light");
-            await this.store.save();
-            return "light";
+    async getSettings(): Promise<Settings> {
+        const res = await commands.getSettings();
+        if (res.status == "ok") {
+            return res.data;
         }
+        return defaultSettings;
     }
 
-    async getFetchFriendsCount(): Promise<number> {
-        const count = await this.store.get<number>("fetch-friends-count");
-        if (count !== undefined) {
-            return count;
-        } else {
-            // デフォルト値を設定
-            await this.store.set("fetch-friends-count", 50);
-            await this.store.save();
-            return 50;
+    async updateSettings(update: SettingsUpdate): Promise<void> {
+        const res = await commands.updateSettings(update);
+        if (res.status == "error") {
+            await logging.error(`Failed to update settings: ${res.error.message}`);
         }
     }
 
+    async getTheme(): Promise<string> {
+        return (await this.getSettings()).theme;
+    }
+
+    async getFetchFriendsCount(): Promise<number> {
+        return (await this.getSettings()).fetchFriendsCount;
+    }
+
     async getAutoCheckUpdates(): Promise<boolean> {
-        const autoCheck = await this.store.get<boolean>("auto-check-updates");
-        if (autoCheck !== undefined) {
-            return autoCheck;
-        } else {
-            // デフォルト値を設定
-            await this.store.set("auto-check-updates", true);
-            await this.store.save();
-            return true;
-        }
+        return (await this.getSettings()).autoCheckUpdates;
     }
 
     async getLanguage(): Promise<string> {
-        const language = await this.store.get<string>("lang");
-        if (language) {
-            return language;
-        } else {
-            // デフォルト言語を設定
-            await this.store.set("lang", "ja");
-            await this.store.save();
-            return "ja";
-        }
+        return (await this.getSettings()).language;
     }
 
     async getInstancesData(): Promise<string> {
@@ -76,39 +81,39 @@ class UserDataStore {
 
 
     async getUsers(): Promise<UserData[]> {
-        const savedUsers = await this.store.get<string>("users");
-        if (savedUsers) {
-            const parsedUsers = JSON.parse(savedUsers);
-            return parsedUsers.map((user: any) => ({
-                ...user,
-                lastLogin: new Date(user.lastLogin)
-            }));
+        const res = await commands.getAccounts();
+        if (res.status == "ok") {
+            return res.data.map(toUserData);
         }
         return [];
     }
 
     async getCurrentUserId(): Promise<string | null> {
-        return await this.store.get<string>("current-user-id") || null;
+        return (await this.getCurrentUser())?.id ?? null;
     }
 
     async setTheme(theme: string): Promise<void> {
-        await this.store.set('data-theme', theme);
-        await this.store.save();
+        await this.updateSettings({ theme: theme as Theme });
     }
 
     async setFetchFriendsCount(count: number): Promise<void> {
-        await this.store.set("fetch-friends-count", count);
-        await this.store.save();
+        await this.updateSettings({ fetchFriendsCount: count });
     }
 
     async setAutoCheckUpdates(autoCheck: boolean): Promise<void> {
-        await this.store.set("auto-check-updates", autoCheck);
-        await this.store.save();
+        await this.updateSettings({ autoCheckUpdates: autoCheck });
+    }
+
+    async setLocalApiEnabled(enabled: boolean): Promise<void> {
+        await this.updateSettings({ localApiEnabled: enabled });
+    }
+
+    async setLocalApiPort(port: number): Promise<void> {
+        await this.updateSettings({ localApiPort: port });
     }
 
     async setLanguage(language: string): Promise<void> {
-        await this.store.set("lang", language);
-        await this.store.save();
+        await this.updateSettings({ language });
     }
 
     async setInstancesData(data: string): Promise<void> {
@@ -117,40 +122,22 @@ class UserDataStore {
     }
 
 
-    async addOrUpdateUser(id: string, displayName: string, userData: string): Promise<void> {
-        const users = await this.getUsers();
-        const updatedUsers = users.filter(user => user.id !== id);
-
-        const newUser: UserData = {
-            id,
-            displayName,
-            lastLogin: new Date(),
-            userData
-        };
-
-        updatedUsers.push(newUser);
-        await this.store.set("users", JSON.stringify(updatedUsers));
-        await this.store.save();
-    }
-
-    async setCurrentUser(userId: string): Promise<void> {
-        await this.store.set("current-user-id", userId);
-        await this.store.save();
+    // ログイン中のセッションをこのアカウントのものとして保存し、現在のアカウントにする
+    async saveUser(userData: string | object): Promise<void> {
+        const json = typeof userData === 'string' ? userData : JSON.stringify(userData);
+        await commands.saveAccount(json);
     }
 
     async removeUser(userId: string): Promise<void> {
-        const users = await this.getUsers();
-        const updatedUsers = users.filter(user => user.id !== userId);
-        await this.store.set("users", JSON.stringify(updatedUsers));
-        await this.store.save();
+        await commands.removeAccount(userId);
     }
 
     async getCurrentUser(): Promise<UserData | null> {
-        const currentId = await this.getCurrentUserId();
-        if (!currentId) return null;
-
-        const users = await this.getUsers();
-        return users.find(user => user.id === currentId) || null;
+        const res = await commands.getCurrentAccount();
+        if (res.status == "ok" && res.data) {
+            return toUserData(res.data);
+        }
+        return null;
     }
 }
 
@@ -42,8 +42,7 @@ export default function FriendScreen() {
 
   // userDataの保存
   const saveUserData = async (data: any) => {
-    await userDataStore.addOrUpdateUser(data.id, data.displayName, data);
-    await userDataStore.setCurrentUser(data.id);
+    await userDataStore.saveUser(data);
   };
 
   // userDataの復元
@@ -75,7 +74,7 @@ export default function FriendScreen() {
         await checkAuthToken();
       }
     }
-    async function checkAuthToken() {
+    async function checkAuthToken(retried = false) {
       const res = await commands.verifyAuthToken();
       if (res.status == "ok") {
         const currentUser = await commands.getCurrentUserInfo();
@@ -85,6 +84,14 @@ export default function FriendScreen() {
           await saveUserData(parsedUserData);
         }
       } else {
+        // 保存済みの認証情報があれば再ログインを試みる
+        if (!retried) {
+          const reauth = await commands.reauthenticateSession();
+          if (reauth.status == "ok" && reauth.data) {
+            await checkAuthToken(true);
+            return;
+          }
+        }
         await userDataStore.removeUser(await userDataStore.getCurrentUserId() || "");
         setUserData(null);
         toastError(t(res.error.message));
@@ -1,6 +1,6 @@
 import { useState, useEffect } from "react";
 import { useNavigate } from "react-router";
-import { commands } from "@/bindings";
+import { commands, LocalApiStatus } from "@/bindings";
 import { useTranslation } from "react-i18next";
 import i18n, { resources } from "@/libs/i18n";
 import { Login } from "@/components/ui/dialogs/login";
@@ -13,6 +13,10 @@ import { logging } from "@/libs/logging.tsx";
 import { UpdateConfirm } from "@/components/ui/dialogs/updateConfirm";
 import { userDataStore, UserData } from "@/libs/userDataStore";
 import { FaArrowLeft } from 'react-icons/fa';
+import { writeText } from "@tauri-apps/plugin-clipboard-manager";
+
+// 設定を変えてからサーバーが起動・停止するまで待つ時間
+const LOCAL_API_STATUS_DELAY = 500;
 
 export default function SettingsScreen() {
   const navigate = useNavigate();
@@ -29,6 +33,10 @@ export default function SettingsScreen() {
   const [isCheckingUpdate, setIsCheckingUpdate] = useState(false);
   const [autoCheckUpdates, setAutoCheckUpdates] = useState<boolean | null>(null);
   const [updateMessage, setUpdateMessage] = useState("");
+  const [hasTotpSecret, setHasTotpSecret] = useState(false);
+  const [localApiEnabled, setLocalApiEnabled] = useState(false);
+  const [localApiPort, setLocalApiPort] = useState(39410);
+  const [localApiStatus, setLocalApiStatus] = useState<LocalApiStatus | null>(null);
 
   useEffect(() => {
     async function loadSettings() {
@@ -46,6 +54,11 @@ export default function SettingsScreen() {
       const autoUpdate = await userDataStore.getAutoCheckUpdates();
       setAutoCheckUpdates(autoUpdate ?? true);
 
+      const settings = await userDataStore.getSettings();
+      setLocalApiEnabled(settings.localApiEnabled);
+      setLocalApiPort(settings.localApiPort);
+      refreshLocalApiStatus();
+
       // ユーザーデータの読み込み
       const savedUsers = await userDataStore.getUsers();
       setUsers(savedUsers);
@@ -69,6 +82,16 @@ export default function SettingsScreen() {
     loadSettings();
   }, []);
 
+  useEffect(() => {
+    if (!currentUserId) {
+      setHasTotpSecret(false);
+      return;
+    }
+    commands.hasTotpSecret(currentUserId).then((res) => {
+      setHasTotpSecret(res.status == "ok" && res.data);
+    });
+  }, [currentUserId]);
+
   useEffect(() => {
     if (theme == "") return;
     userDataStore.setTheme(theme);
@@ -80,6 +103,45 @@ export default function SettingsScreen() {
     userDataStore.setAutoCheckUpdates(autoCheckUpdates);
   }, [autoCheckUpdates]);
 
+  const refreshLocalApiStatus = async () => {
+    const res = await commands.getLocalApiStatus();
+    if (res.status == "ok") {
+      setLocalApiStatus(res.data);
+    }
+  };
+
+  const changeLocalApiEnabled = async (enabled: boolean) => {
+    setLocalApiEnabled(enabled);
+    await userDataStore.setLocalApiEnabled(enabled);
+    setTimeout(refreshLocalApiStatus, LOCAL_API_STATUS_DELAY);
+  };
+
+  const changeLocalApiPort = async (port: number) => {
+    setLocalApiPort(port);
+    if (port >= 1024 && port <= 65535) {
+      await userDataStore.setLocalApiPort(port);
+      setTimeout(refreshLocalApiStatus, LOCAL_API_STATUS_DELAY);
+    }
+  };
+
+  const copyLocalApiToken = async () => {
+    const res = await commands.getLocalApiToken();
+    if (res.status == "error") {
+      toastError(t(res.error.message));
+      return;
+    }
+    await writeText(res.data);
+  };
+
+  const regenerateLocalApiToken = async () => {
+    const res = await commands.regenerateLocalApiToken();
+    if (res.status == "error") {
+      toastError(t(res.error.message));
+      return;
+    }
+    await writeText(res.data);
+  };
+
   const toggleTheme = () => {
     setTheme((prev) => (prev === "light" ? "dark" : "light"));
   };
@@ -100,16 +162,27 @@ export default function SettingsScreen() {
     const user = users.find(u => u.id === userId);
     if (!user) return;
 
-    await commands.cookieClear();
-    await commands.switchUser(userId);
-
-    await userDataStore.setCurrentUser(userId);
+    const res = await commands.switchUser(userId);
+    if (res.status == "error") {
+      toastError(t(res.error.message));
+      return;
+    }
     setCurrentUserId(userId);
 
     setLoginUserName(user.displayName);
     setIsLoggedIn(true);
   };
 
+  const forgetTotpSecret = async () => {
+    if (!currentUserId) return;
+    const res = await commands.forgetTotpSecret(currentUserId);
+    if (res.status == "error") {
+      toastError(t(res.error.message));
+      return;
+    }
+    setHasTotpSecret(false);
+  };
+
   const langChange = (e: React.ChangeEvent<HTMLSelectElement>) => {
     const selected = e.target.value;
     i18n.changeLanguage(selected);
@@ -122,11 +195,10 @@ export default function SettingsScreen() {
       const jsonData = JSON.parse(res.data);
       setLoginUserName(jsonData.displayName);
 
-      await userDataStore.addOrUpdateUser(jsonData.id, jsonData.displayName, res.data);
+      await userDataStore.saveUser(res.data);
       const updatedUsers = await userDataStore.getUsers();
       setUsers(updatedUsers);
 
-      await userDataStore.setCurrentUser(jsonData.id);
       setCurrentUserId(jsonData.id);
     }
   }
@@ -218,7 +290,9 @@ export default function SettingsScreen() {
               onChange={(e) => {
                 const value = Number(e.target.value);
                 setFetchFriendsCount(value);
-                userDataStore.setFetchFriendsCount(value);
+                if (value >= 1 && value <= 100) {
+                  userDataStore.setFetchFriendsCount(value);
+                }
               }}
             />
           </div>
@@ -257,6 +331,14 @@ export default function SettingsScreen() {
                     </select>
                 </div>
               )}
+              {hasTotpSecret && (
+                <div className="flex justify-between items-center w-full">
+                  <span>{t("settingScreen.totpSecretStored")}</span>
+                  <button className="btn btn-sm btn-error" onClick={forgetTotpSecret}>
+                    {t("settingScreen.forgetTotpSecret")}
+                  </button>
+                </div>
+              )}
             </div>) : (
             <div className="flex flex-col w-full gap-2">
               <div className="flex justify-between items-center w-full">
@@ -285,6 +367,55 @@ export default function SettingsScreen() {
         </li>
       </ul>
 
+      <h2 className="text-md font-semibold my-4">{t("settingScreen.localApi")}</h2>
+      <ul className="menu bg-base-100 p-2 rounded-box shadow-md w-full">
+        <li>
+          <div className="flex justify-between items-center w-full">
+            <span>{t("settingScreen.localApiEnabled")}</span>
+            <input
+              type="checkbox"
+              className="toggle toggle-primary"
+              checked={localApiEnabled}
+              onChange={(e) => changeLocalApiEnabled(e.target.checked)}
+            />
+          </div>
+        </li>
+        <li>
+          <div className="flex justify-between items-center w-full">
+            <span>{t("settingScreen.localApiPort")}</span>
+            <input
+              type="number"
+              min="1024"
+              max="65535"
+              className="input input-bordered mt-2"
+              value={localApiPort}
+              onChange={(e) => changeLocalApiPort(Number(e.target.value))}
+            />
+          </div>
+        </li>
+        {localApiEnabled && (
+          <li>
+            <div className="flex justify-between items-center w-full">
+              <span>
+                {localApiStatus?.error
+                  ? t("settingScreen.localApiError", { error: localApiStatus.error })
+                  : localApiStatus?.running
+                    ? t("settingScreen.localApiRunning", { port: localApiStatus.port })
+                    : t("settingScreen.localApiStopped")}
+              </span>
+              <div className="flex gap-2">
+                <button className="btn btn-sm" onClick={copyLocalApiToken}>
+                  {t("settingScreen.copyLocalApiToken")}
+                </button>
+                <button className="btn btn-sm btn-error" onClick={regenerateLocalApiToken}>
+                  {t("settingScreen.regenerateLocalApiToken")}
+                </button>
+              </div>
+            </div>
+          </li>
+        )}
+      </ul>
+
       <h2 className="text-md font-semibold my-4">{t("settingScreen.updateSettings")}</h2>
       <ul className="menu bg-base-100 p-2 rounded-box shadow-md w-full">
         <li>